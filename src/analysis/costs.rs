@@ -0,0 +1,115 @@
+use clarity_repl::clarity::ast;
+use clarity_repl::clarity::representations::SymbolicExpression;
+use clarity_repl::clarity::types::QualifiedContractIdentifier;
+
+/// Functions reading from the chain state.
+const READ_FUNCTIONS: &[&str] = &[
+    "var-get",
+    "map-get?",
+    "ft-get-balance",
+    "ft-get-supply",
+    "nft-get-owner?",
+    "stx-get-balance",
+    "get-block-info?",
+    "at-block",
+];
+
+/// Functions writing to the chain state.
+const WRITE_FUNCTIONS: &[&str] = &[
+    "var-set",
+    "map-set",
+    "map-insert",
+    "map-delete",
+    "ft-mint?",
+    "ft-transfer?",
+    "ft-burn?",
+    "nft-mint?",
+    "nft-transfer?",
+    "nft-burn?",
+    "stx-transfer?",
+    "stx-burn?",
+];
+
+/// Static cost estimate of a function.
+///
+/// These are not the consensus costs computed by a stacks node: `runtime` is
+/// the number of expressions in the function body, and the other counters are
+/// the number of state reads, state writes and `contract-call?` appearing in
+/// it, whatever the branches taken at runtime.
+#[derive(Debug, Clone, Serialize)]
+pub struct FunctionCost {
+    pub name: String,
+    pub access: String,
+    pub runtime: u64,
+    pub read_count: u64,
+    pub write_count: u64,
+    pub contract_calls: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ContractCosts {
+    pub contract: String,
+    pub functions: Vec<FunctionCost>,
+}
+
+pub fn estimate_contract_costs(contract_name: &str, code: &str) -> Result<ContractCosts, String> {
+    let contract_id = QualifiedContractIdentifier::local(contract_name)
+        .map_err(|e| format!("Error: invalid contract name {}: {}", contract_name, e))?;
+    let contract_ast = ast::build_ast(&contract_id, code, &mut ())
+        .map_err(|e| format!("Error: unable to parse {}: {}", contract_name, e.diagnostic.message))?;
+
+    let mut functions = vec![];
+    for expr in contract_ast.expressions.iter() {
+        let list = match expr.match_list() {
+            Some(list) => list,
+            None => continue,
+        };
+        let access = match list.get(0).and_then(|e| e.match_atom()).map(|a| a.as_str()) {
+            Some("define-public") => "public",
+            Some("define-read-only") => "read-only",
+            Some("define-private") => "private",
+            _ => continue,
+        };
+        let name = match list.get(1).and_then(|e| e.match_list()).and_then(|s| s.get(0)).and_then(|e| e.match_atom()) {
+            Some(name) => name.to_string(),
+            None => continue,
+        };
+        let mut cost = FunctionCost {
+            name,
+            access: access.to_string(),
+            runtime: 0,
+            read_count: 0,
+            write_count: 0,
+            contract_calls: 0,
+        };
+        for body in list.iter().skip(2) {
+            accumulate(body, &mut cost);
+        }
+        functions.push(cost);
+    }
+
+    Ok(ContractCosts {
+        contract: contract_name.to_string(),
+        functions,
+    })
+}
+
+fn accumulate(expr: &SymbolicExpression, cost: &mut FunctionCost) {
+    cost.runtime += 1;
+    let list = match expr.match_list() {
+        Some(list) => list,
+        None => return,
+    };
+    if let Some(function) = list.get(0).and_then(|e| e.match_atom()).map(|a| a.as_str()) {
+        if READ_FUNCTIONS.contains(&function) {
+            cost.read_count += 1;
+        } else if WRITE_FUNCTIONS.contains(&function) {
+            cost.write_count += 1;
+        } else if function == "contract-call?" {
+            cost.contract_calls += 1;
+        }
+    }
+    for inner in list.iter() {
+        accumulate(inner, cost);
+    }
+}
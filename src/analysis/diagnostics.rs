@@ -0,0 +1,83 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+use clarity_repl::clarity::util::StacksAddress;
+use clarity_repl::repl::{Session, SessionSettings};
+
+/// A diagnostic reported while checking a contract.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContractDiagnostic {
+    pub contract: String,
+    pub level: String,
+    pub message: String,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+}
+
+impl ContractDiagnostic {
+    /// Diagnostics are compared without their position, so that a baseline
+    /// survives unrelated edits moving code around.
+    fn key(&self) -> (String, String, String) {
+        (self.contract.clone(), self.level.clone(), self.message.clone())
+    }
+}
+
+/// Deploy the contracts of the settings one by one, collecting the
+/// diagnostics reported for each of them instead of stopping at the first.
+pub fn collect_diagnostics(settings: &SessionSettings) -> Result<Vec<ContractDiagnostic>, String> {
+    let mut base_settings = settings.clone();
+    let contracts = base_settings.initial_contracts.drain(..).collect::<Vec<_>>();
+    let mut session = Session::new(base_settings);
+    session.check()?;
+
+    let mut diagnostics = vec![];
+    let default_tx_sender = session.get_tx_sender();
+    for contract in contracts.into_iter() {
+        let deployer = match contract.deployer {
+            Some(ref deployer) => deployer.clone(),
+            None => StacksAddress::burn_address(false).to_string(),
+        };
+        let name = contract.name.clone().unwrap_or_default();
+        session.set_tx_sender(deployer);
+        if let Err((message, diagnostic)) = session.interpret(contract.code, contract.name) {
+            let (level, span) = match diagnostic {
+                Some(ref diagnostic) => (format!("{:?}", diagnostic.level), diagnostic.spans.first()),
+                None => ("Error".to_string(), None),
+            };
+            diagnostics.push(ContractDiagnostic {
+                contract: name,
+                level,
+                message,
+                line: span.map(|s| s.start_line),
+                column: span.map(|s| s.start_column),
+            });
+        }
+    }
+    session.set_tx_sender(default_tx_sender);
+    Ok(diagnostics)
+}
+
+pub fn load_baseline(path: &Path) -> Result<Vec<ContractDiagnostic>, String> {
+    let content = fs::read_to_string(path).map_err(|e| {
+        format!(
+            "Error: unable to read {} ({}), create it with `clarinet check --update-baseline`",
+            path.display(),
+            e
+        )
+    })?;
+    serde_json::from_str(&content).map_err(|e| format!("Error: unable to parse {}: {}", path.display(), e))
+}
+
+pub fn save_baseline(path: &Path, diagnostics: &[ContractDiagnostic]) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("Error: unable to create {}: {}", parent.display(), e))?;
+    }
+    let content = serde_json::to_string_pretty(diagnostics).unwrap();
+    fs::write(path, content).map_err(|e| format!("Error: unable to write {}: {}", path.display(), e))
+}
+
+/// Diagnostics of `current` that are not part of `baseline`.
+pub fn new_diagnostics(current: Vec<ContractDiagnostic>, baseline: &[ContractDiagnostic]) -> Vec<ContractDiagnostic> {
+    let known: BTreeSet<_> = baseline.iter().map(|d| d.key()).collect();
+    current.into_iter().filter(|d| !known.contains(&d.key())).collect()
+}
@@ -0,0 +1,77 @@
+use std::collections::{BTreeMap, BTreeSet};
+use clarity_repl::clarity::analysis::contract_interface_builder::{build_contract_interface, ContractInterface};
+use clarity_repl::clarity::types::{PrincipalData, QualifiedContractIdentifier, StandardPrincipalData};
+use clarity_repl::clarity::util::StacksAddress;
+use clarity_repl::contracts::{BNS_CONTRACT, COSTS_CONTRACT, POX_CONTRACT};
+use clarity_repl::repl::{ClarityInterpreter, Session, SessionSettings};
+use super::BOOT_DEPLOYERS;
+
+/// Contracts deployed to analyze the contracts of the settings, in order:
+/// links and boot contracts first, the same way a session does, so that the
+/// calls made to them type check. Each one comes with its deployer, its name,
+/// its source, and whether it is one of the `initial_contracts`.
+pub(super) fn deployment_order(settings: &SessionSettings) -> Result<Vec<(String, String, String, bool)>, String> {
+    let mut deployments = vec![];
+
+    let mut session = Session::new(SessionSettings::default());
+    let mut indexed = BTreeSet::new();
+    for link in settings.initial_links.iter() {
+        for (contract_id, code, _) in session.resolve_link(link)?.into_iter() {
+            if indexed.insert(contract_id.clone()) {
+                let components: Vec<&str> = contract_id.split('.').collect();
+                deployments.push((components[0].to_string(), components[1].to_string(), code, false));
+            }
+        }
+    }
+    if settings.include_boot_contracts {
+        for deployer in BOOT_DEPLOYERS.iter() {
+            for (name, code) in [("pox", POX_CONTRACT), ("bns", BNS_CONTRACT), ("costs", COSTS_CONTRACT)].iter() {
+                deployments.push((deployer.to_string(), name.to_string(), code.to_string(), false));
+            }
+        }
+    }
+    for contract in settings.initial_contracts.iter() {
+        let deployer = match contract.deployer {
+            Some(ref deployer) => deployer.clone(),
+            None => StacksAddress::burn_address(false).to_string(),
+        };
+        let name = contract.name.clone().unwrap_or_default();
+        deployments.push((deployer, name, contract.code.clone(), true));
+    }
+    Ok(deployments)
+}
+
+/// Interface (functions, variables, maps and tokens, with their types) of
+/// each contract of the settings, by contract name, as computed by the
+/// analysis.
+pub fn build_contract_interfaces(settings: &SessionSettings) -> Result<BTreeMap<String, ContractInterface>, String> {
+    let deployments = deployment_order(settings)?;
+    let mut interpreter = ClarityInterpreter::new(StandardPrincipalData::transient());
+    let mut interfaces = BTreeMap::new();
+    for (deployer, name, code, is_initial_contract) in deployments.into_iter() {
+        let contract_id = format!("{}.{}", deployer, name);
+        let contract_identifier = QualifiedContractIdentifier::parse(&contract_id)
+            .map_err(|e| format!("Error: invalid contract identifier {}: {}", contract_id, e))?;
+        let deployer = PrincipalData::parse_standard_principal(&deployer)
+            .map_err(|e| format!("Error: invalid deployer of {}: {}", contract_id, e))?;
+        interpreter.set_tx_sender(deployer);
+
+        let mut contract_ast = interpreter
+            .build_ast(contract_identifier.clone(), code.clone())
+            .map_err(|(message, _)| format!("Error: unable to analyze {}: {}", contract_id, message))?;
+        let contract_analysis = interpreter
+            .run_analysis(contract_identifier.clone(), &mut contract_ast)
+            .map_err(|(message, _)| format!("Error: unable to analyze {}: {}", contract_id, message))?;
+        if is_initial_contract {
+            let interface = match contract_analysis.contract_interface {
+                Some(ref interface) => interface.clone(),
+                None => build_contract_interface(&contract_analysis),
+            };
+            interfaces.insert(contract_identifier.name.to_string(), interface);
+        }
+        interpreter
+            .execute(contract_identifier, &mut contract_ast, code, contract_analysis)
+            .map_err(|(message, _)| format!("Error: unable to deploy {}: {}", contract_id, message))?;
+    }
+    Ok(interfaces)
+}
@@ -0,0 +1,92 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+use clarity_repl::clarity::types::StandardPrincipalData;
+use clarity_repl::repl::{ClarityInterpreter, SessionSettings};
+use crate::types::{LinkConfig, MainConfig};
+use super::interface::deployment_order;
+
+/// Contracts called (`contract-call?`), implemented (`impl-trait`) or
+/// imported a trait from (`use-trait`) by each local contract. References to
+/// local contracts (`.name`) are qualified with the transient address.
+fn contract_references(config: &MainConfig, root: &Path) -> Result<BTreeMap<String, BTreeSet<String>>, String> {
+    let interpreter = ClarityInterpreter::new(StandardPrincipalData::transient());
+    let mut references = BTreeMap::new();
+    if let Some(ref contracts) = config.contracts {
+        for (name, contract) in contracts.iter() {
+            let path = root.join(&contract.path);
+            let code = fs::read_to_string(&path)
+                .map_err(|e| format!("Error: unable to read {:?}: {}", path, e))?;
+            let contract_id = format!("{}.{}", StandardPrincipalData::transient(), name);
+            let dependencies = interpreter
+                .detect_dependencies(contract_id, code)
+                .map_err(|e| format!("Error: unable to parse contract {}: {}", name, e))?;
+            references.insert(name.clone(), dependencies);
+        }
+    }
+    Ok(references)
+}
+
+/// Links of `Clarinet.toml` that no local contract calls (`contract-call?`),
+/// implements (`impl-trait`) or imports a trait from (`use-trait`), either by
+/// their contract id or, for an aliased link, by their local name (`.name`).
+pub fn find_unused_links(config: &MainConfig, root: &Path) -> Result<Vec<String>, String> {
+    let links = match config.links {
+        Some(ref links) => links,
+        None => return Ok(vec![]),
+    };
+
+    let referenced: BTreeSet<String> = contract_references(config, root)?
+        .into_iter()
+        .flat_map(|(_, dependencies)| dependencies.into_iter())
+        .collect();
+
+    let is_referenced = |link: &LinkConfig| {
+        referenced.contains(&link.contract_id)
+            || link
+                .name
+                .as_ref()
+                .map_or(false, |name| referenced.contains(&format!("{}.{}", StandardPrincipalData::transient(), name)))
+    };
+    Ok(links
+        .iter()
+        .filter(|link| !is_referenced(link))
+        .map(|link| link.contract_id.clone())
+        .collect())
+}
+
+/// Contracts referenced by each of the `initial_contracts` of the settings
+/// which are not deployed along with them by a session (the contracts of the
+/// settings, the links and their dependencies, and the boot contracts), by
+/// contract name. Contracts without dangling references are left out.
+pub fn find_missing_references(settings: &SessionSettings) -> Result<BTreeMap<String, Vec<String>>, String> {
+    let deployments = deployment_order(settings)?;
+    let known: BTreeSet<String> = deployments
+        .iter()
+        .map(|(deployer, name, _, _)| format!("{}.{}", deployer, name))
+        .collect();
+
+    let interpreter = ClarityInterpreter::new(StandardPrincipalData::transient());
+    let mut missing = BTreeMap::new();
+    for (deployer, name, code, is_initial_contract) in deployments.into_iter() {
+        if !is_initial_contract {
+            continue;
+        }
+        let dependencies = interpreter
+            .detect_dependencies(format!("{}.{}", deployer, name), code)
+            .map_err(|e| format!("Error: unable to parse contract {}: {}", name, e))?;
+        let local_prefix = format!("{}.", deployer);
+        let dangling: Vec<String> = dependencies
+            .into_iter()
+            .filter(|contract_id| !known.contains(contract_id))
+            .map(|contract_id| match contract_id.strip_prefix(&local_prefix) {
+                Some(local_name) => format!(".{}", local_name),
+                None => contract_id,
+            })
+            .collect();
+        if !dangling.is_empty() {
+            missing.insert(name, dangling);
+        }
+    }
+    Ok(missing)
+}
@@ -0,0 +1,43 @@
+use clarity_repl::clarity::ast;
+use clarity_repl::clarity::types::QualifiedContractIdentifier;
+
+/// Number of top level definitions of each kind in a contract.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ContractMetrics {
+    pub contract: String,
+    pub public_functions: u64,
+    pub read_only_functions: u64,
+    pub private_functions: u64,
+    pub data_vars: u64,
+    pub maps: u64,
+    pub constants: u64,
+}
+
+pub fn count_contract_definitions(contract_name: &str, code: &str) -> Result<ContractMetrics, String> {
+    let contract_id = QualifiedContractIdentifier::local(contract_name)
+        .map_err(|e| format!("Error: invalid contract name {}: {}", contract_name, e))?;
+    let contract_ast = ast::build_ast(&contract_id, code, &mut ())
+        .map_err(|e| format!("Error: unable to parse {}: {}", contract_name, e.diagnostic.message))?;
+
+    let mut metrics = ContractMetrics {
+        contract: contract_name.to_string(),
+        ..ContractMetrics::default()
+    };
+    for expr in contract_ast.expressions.iter() {
+        let keyword = expr
+            .match_list()
+            .and_then(|list| list.get(0))
+            .and_then(|e| e.match_atom())
+            .map(|a| a.as_str());
+        match keyword {
+            Some("define-public") => metrics.public_functions += 1,
+            Some("define-read-only") => metrics.read_only_functions += 1,
+            Some("define-private") => metrics.private_functions += 1,
+            Some("define-data-var") => metrics.data_vars += 1,
+            Some("define-map") => metrics.maps += 1,
+            Some("define-constant") => metrics.constants += 1,
+            _ => {}
+        }
+    }
+    Ok(metrics)
+}
@@ -0,0 +1,16 @@
+mod costs;
+mod diagnostics;
+mod interface;
+mod links;
+mod metrics;
+mod traits;
+
+pub use costs::{estimate_contract_costs, ContractCosts};
+pub use diagnostics::{collect_diagnostics, load_baseline, new_diagnostics, save_baseline, ContractDiagnostic};
+pub use interface::build_contract_interfaces;
+pub use links::{find_missing_references, find_unused_links};
+pub use metrics::{count_contract_definitions, ContractMetrics};
+pub use traits::{analyze_contract_traits, ContractTraits};
+
+/// Deployers of the boot contracts, on testnet and on mainnet.
+const BOOT_DEPLOYERS: &[&str] = &["ST000000000000000000002AMW42H", "SP000000000000000000002Q6VF78"];
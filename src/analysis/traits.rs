@@ -0,0 +1,164 @@
+use std::collections::BTreeMap;
+use clarity_repl::clarity::analysis::ContractAnalysis;
+use clarity_repl::clarity::ast::ContractAST;
+use clarity_repl::clarity::representations::{ClarityName, TraitDefinition};
+use clarity_repl::clarity::types::signatures::{FunctionSignature, FunctionType};
+use clarity_repl::clarity::types::{PrincipalData, QualifiedContractIdentifier, StandardPrincipalData, TraitIdentifier};
+use clarity_repl::repl::{ClarityInterpreter, SessionSettings};
+use super::interface::deployment_order;
+
+/// Traits a contract implements (`impl-trait`) and references (`use-trait`).
+#[derive(Debug, Clone, Serialize)]
+pub struct ContractTraits {
+    pub contract: String,
+    pub implemented: Vec<ImplementedTrait>,
+    /// Traits referenced with `use-trait`, by alias
+    pub referenced: BTreeMap<String, String>,
+    /// Analysis error of the contract, when it fails to analyze
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImplementedTrait {
+    pub trait_id: String,
+    /// Functions of the trait the contract doesn't define, as public or
+    /// read-only functions. `None` when the trait is not defined by any
+    /// contract of the project, link or boot contract.
+    pub missing_functions: Option<Vec<String>>,
+    /// Functions of the trait the contract defines with other argument or
+    /// return types than the trait. Only checked for the contracts which
+    /// analyze.
+    pub mismatched_functions: Vec<String>,
+}
+
+impl ContractTraits {
+    /// Whether the contract defines every function of the traits it
+    /// implements, with the signatures they require.
+    pub fn is_compliant(&self) -> bool {
+        self.error.is_none()
+            && self.implemented.iter().all(|implemented| {
+                implemented.missing_functions.as_ref().map_or(false, |missing| missing.is_empty())
+                    && implemented.mismatched_functions.is_empty()
+            })
+    }
+}
+
+fn trait_id(trait_identifier: &TraitIdentifier) -> String {
+    format!("{}.{}", trait_identifier.contract_identifier, trait_identifier.name)
+}
+
+/// Public and read-only functions defined at the top level of a contract.
+fn defined_functions(contract_ast: &ContractAST) -> Vec<String> {
+    let mut functions = vec![];
+    for expr in contract_ast.expressions.iter() {
+        let list = match expr.match_list() {
+            Some(list) => list,
+            None => continue,
+        };
+        match list.get(0).and_then(|e| e.match_atom()).map(|a| a.as_str()) {
+            Some("define-public") | Some("define-read-only") => {}
+            _ => continue,
+        }
+        if let Some(name) = list.get(1).and_then(|e| e.match_list()).and_then(|signature| signature.get(0)).and_then(|e| e.match_atom()) {
+            functions.push(name.to_string());
+        }
+    }
+    functions
+}
+
+/// Whether the public or read-only function `name` of the analyzed contract
+/// takes the arguments and returns the type of `signature`, as checked by
+/// `impl-trait`.
+fn matches_signature(analysis: &ContractAnalysis, name: &ClarityName, signature: &FunctionSignature) -> bool {
+    match (analysis.get_public_function_type(name), analysis.get_read_only_function_type(name)) {
+        (Some(FunctionType::Fixed(function)), None) | (None, Some(FunctionType::Fixed(function))) => {
+            let args = function.args.iter().map(|arg| arg.signature.clone()).collect();
+            signature.check_args_trait_compliance(args) && signature.returns.admits_type(&function.returns)
+        }
+        _ => false,
+    }
+}
+
+/// Traits of each contract of the settings, in deployment order. The
+/// functions of the traits are read from the analysis of the contracts
+/// defining them, deployed first.
+pub fn analyze_contract_traits(settings: &SessionSettings) -> Result<Vec<ContractTraits>, String> {
+    let mut interpreter = ClarityInterpreter::new(StandardPrincipalData::transient());
+    let mut trait_functions: BTreeMap<String, BTreeMap<ClarityName, FunctionSignature>> = BTreeMap::new();
+    let mut reports = vec![];
+    for (deployer, name, code, is_initial_contract) in deployment_order(settings)?.into_iter() {
+        let contract_id = format!("{}.{}", deployer, name);
+        let contract_identifier = QualifiedContractIdentifier::parse(&contract_id)
+            .map_err(|e| format!("Error: invalid contract identifier {}: {}", contract_id, e))?;
+        let deployer = PrincipalData::parse_standard_principal(&deployer)
+            .map_err(|e| format!("Error: invalid deployer of {}: {}", contract_id, e))?;
+        interpreter.set_tx_sender(deployer);
+
+        let mut contract_ast = interpreter
+            .build_ast(contract_identifier.clone(), code.clone())
+            .map_err(|(message, _)| format!("Error: unable to analyze {}: {}", contract_id, message))?;
+        let analysis = interpreter.run_analysis(contract_identifier.clone(), &mut contract_ast);
+
+        if is_initial_contract {
+            let functions = defined_functions(&contract_ast);
+            let mut implemented: Vec<ImplementedTrait> = contract_ast
+                .implemented_traits
+                .iter()
+                .map(|trait_identifier| {
+                    let trait_id = trait_id(trait_identifier);
+                    let required = trait_functions.get(&trait_id);
+                    let missing_functions = required.map(|required| {
+                        required
+                            .keys()
+                            .map(|function| function.to_string())
+                            .filter(|function| !functions.contains(function))
+                            .collect()
+                    });
+                    let mismatched_functions = match (required, analysis.as_ref()) {
+                        (Some(required), Ok(analysis)) => required
+                            .iter()
+                            .filter(|(function, _)| functions.contains(&function.to_string()))
+                            .filter(|(function, signature)| !matches_signature(analysis, function, signature))
+                            .map(|(function, _)| function.to_string())
+                            .collect(),
+                        _ => vec![],
+                    };
+                    ImplementedTrait { trait_id, missing_functions, mismatched_functions }
+                })
+                .collect();
+            implemented.sort_by(|a, b| a.trait_id.cmp(&b.trait_id));
+            let referenced = contract_ast
+                .referenced_traits
+                .iter()
+                .filter_map(|(alias, definition)| match definition {
+                    TraitDefinition::Imported(trait_identifier) => Some((alias.to_string(), trait_id(trait_identifier))),
+                    TraitDefinition::Defined(_) => None,
+                })
+                .collect();
+            reports.push(ContractTraits {
+                contract: name.clone(),
+                implemented,
+                referenced,
+                error: analysis.as_ref().err().map(|(message, _)| message.clone()),
+            });
+        }
+
+        // Contracts failing to analyze are not deployed, and reported as
+        // errors by the contracts depending on them.
+        let analysis = match analysis {
+            Ok(analysis) => analysis,
+            Err((message, _)) if is_initial_contract => {
+                debug!("Skipping the deployment of {}: {}", contract_id, message);
+                continue;
+            }
+            Err((message, _)) => return Err(format!("Error: unable to analyze {}: {}", contract_id, message)),
+        };
+        for (trait_name, functions) in analysis.defined_traits.iter() {
+            trait_functions.insert(format!("{}.{}", contract_identifier, trait_name), functions.clone());
+        }
+        interpreter
+            .execute(contract_identifier, &mut contract_ast, code, analysis)
+            .map_err(|(message, _)| format!("Error: unable to deploy {}: {}", contract_id, message))?;
+    }
+    Ok(reports)
+}
@@ -0,0 +1,339 @@
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
+use clarity_repl::clarity::diagnostic::Diagnostic;
+use clarity_repl::clarity::types::{TypeSignature, Value};
+use crate::utils::colors;
+
+/// Type of a Clarity value, recovered from its printed representation.
+/// `None` members stand for the parts of a type that a value alone can't
+/// tell (the `err` side of an `(ok ...)`, the inner type of `none`, etc).
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValueType {
+    Int,
+    UInt,
+    Bool,
+    Principal,
+    Buff(usize),
+    StringASCII(usize),
+    StringUTF8(usize),
+    Optional(Option<Box<ValueType>>),
+    Response(Option<Box<ValueType>>, Option<Box<ValueType>>),
+    List(Option<Box<ValueType>>, usize),
+    Tuple(Vec<(String, ValueType)>),
+}
+
+fn fmt_inner(f: &mut fmt::Formatter, inner: &Option<Box<ValueType>>) -> fmt::Result {
+    match inner {
+        Some(inner) => write!(f, "{}", inner),
+        None => write!(f, "..."),
+    }
+}
+
+impl fmt::Display for ValueType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValueType::Int => write!(f, "int"),
+            ValueType::UInt => write!(f, "uint"),
+            ValueType::Bool => write!(f, "bool"),
+            ValueType::Principal => write!(f, "principal"),
+            ValueType::Buff(len) => write!(f, "(buff {})", len),
+            ValueType::StringASCII(len) => write!(f, "(string-ascii {})", len),
+            ValueType::StringUTF8(len) => write!(f, "(string-utf8 {})", len),
+            ValueType::Optional(inner) => {
+                write!(f, "(optional ")?;
+                fmt_inner(f, inner)?;
+                write!(f, ")")
+            }
+            ValueType::Response(ok, err) => {
+                write!(f, "(response ")?;
+                fmt_inner(f, ok)?;
+                write!(f, " ")?;
+                fmt_inner(f, err)?;
+                write!(f, ")")
+            }
+            ValueType::List(inner, len) => {
+                write!(f, "(list {} ", len)?;
+                fmt_inner(f, inner)?;
+                write!(f, ")")
+            }
+            ValueType::Tuple(fields) => {
+                write!(f, "(tuple")?;
+                for (name, field_type) in fields.iter() {
+                    write!(f, " ({} {})", name, field_type)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+/// Render a value the way the console prints evaluation results:
+/// `(ok u5) :: (response uint ...)`.
+pub fn format_value(value: &Value) -> String {
+    // The parts of the type a value can't tell are `NoType`.
+    let value_type = TypeSignature::type_of(value).to_string().replace("UnknownType", "...");
+    format_typed(&value.to_string(), &value_type)
+}
+
+/// Render a result printed by the interpreter, which only hands back the
+/// `Display` form of the values it evaluates, with its inferred type.
+pub fn format_printed_value(value: &str) -> String {
+    match infer_type(value) {
+        Some(value_type) => format_typed(value, &value_type.to_string()),
+        None => colors::green(value),
+    }
+}
+
+fn format_typed(value: &str, value_type: &str) -> String {
+    format!("{} {}", colors::green(value), colors::gray(format!(":: {}", value_type)))
+}
+
+/// Infer the type of a value from its `Display` form, as produced by the
+/// interpreter. Returns `None` if the string isn't a complete value.
+pub fn infer_type(value: &str) -> Option<ValueType> {
+    let mut chars = value.trim().chars().peekable();
+    let value_type = parse_value(&mut chars)?;
+    match chars.next() {
+        None => Some(value_type),
+        Some(_) => None,
+    }
+}
+
+fn parse_value(chars: &mut Peekable<Chars>) -> Option<ValueType> {
+    skip_whitespaces(chars);
+    match chars.peek()? {
+        '(' => {
+            chars.next();
+            let keyword = read_word(chars);
+            let inner = Box::new(parse_value(chars)?);
+            skip_whitespaces(chars);
+            if chars.next()? != ')' {
+                return None;
+            }
+            match keyword.as_str() {
+                "ok" => Some(ValueType::Response(Some(inner), None)),
+                "err" => Some(ValueType::Response(None, Some(inner))),
+                "some" => Some(ValueType::Optional(Some(inner))),
+                _ => None,
+            }
+        }
+        '[' => {
+            chars.next();
+            let mut inner = None;
+            let mut len = 0;
+            loop {
+                skip_whitespaces(chars);
+                if chars.peek() == Some(&']') {
+                    chars.next();
+                    break;
+                }
+                let item_type = parse_value(chars)?;
+                inner = Some(match inner {
+                    Some(known) => merge_types(known, item_type),
+                    None => item_type,
+                });
+                len += 1;
+                skip_whitespaces(chars);
+                match chars.next()? {
+                    ',' => continue,
+                    ']' => break,
+                    _ => return None,
+                }
+            }
+            Some(ValueType::List(inner.map(Box::new), len))
+        }
+        '{' => {
+            chars.next();
+            let mut fields = vec![];
+            loop {
+                skip_whitespaces(chars);
+                if chars.peek() == Some(&'}') {
+                    chars.next();
+                    break;
+                }
+                let name = read_word(chars);
+                if name.is_empty() || chars.next()? != ':' {
+                    return None;
+                }
+                fields.push((name, parse_value(chars)?));
+                skip_whitespaces(chars);
+                match chars.next()? {
+                    ',' => continue,
+                    '}' => break,
+                    _ => return None,
+                }
+            }
+            Some(ValueType::Tuple(fields))
+        }
+        '"' => {
+            chars.next();
+            Some(ValueType::StringASCII(read_string_len(chars)?))
+        }
+        '\'' => {
+            chars.next();
+            read_word(chars);
+            Some(ValueType::Principal)
+        }
+        _ => {
+            let word = read_word(chars);
+            if word == "u" && chars.peek() == Some(&'"') {
+                chars.next();
+                return Some(ValueType::StringUTF8(read_string_len(chars)?));
+            }
+            parse_atom(&word)
+        }
+    }
+}
+
+fn parse_atom(word: &str) -> Option<ValueType> {
+    match word {
+        "true" | "false" => Some(ValueType::Bool),
+        "none" => Some(ValueType::Optional(None)),
+        word if word.starts_with("0x") => Some(ValueType::Buff((word.len() - 2) / 2)),
+        word if word.starts_with('u') && word.len() > 1 && word[1..].chars().all(|c| c.is_ascii_digit()) => {
+            Some(ValueType::UInt)
+        }
+        word if word.parse::<i128>().is_ok() => Some(ValueType::Int),
+        word if word.starts_with('S') => Some(ValueType::Principal),
+        _ => None,
+    }
+}
+
+/// Combine the types of two items of the same list, keeping whatever each
+/// side knows about the other's unknown parts.
+fn merge_types(known: ValueType, other: ValueType) -> ValueType {
+    fn merge_inner(a: Option<Box<ValueType>>, b: Option<Box<ValueType>>) -> Option<Box<ValueType>> {
+        match (a, b) {
+            (Some(a), Some(b)) => Some(Box::new(merge_types(*a, *b))),
+            (Some(a), None) => Some(a),
+            (None, b) => b,
+        }
+    }
+
+    match (known, other) {
+        (ValueType::Optional(a), ValueType::Optional(b)) => ValueType::Optional(merge_inner(a, b)),
+        (ValueType::Response(ok_a, err_a), ValueType::Response(ok_b, err_b)) => {
+            ValueType::Response(merge_inner(ok_a, ok_b), merge_inner(err_a, err_b))
+        }
+        (ValueType::Buff(a), ValueType::Buff(b)) => ValueType::Buff(a.max(b)),
+        (ValueType::StringASCII(a), ValueType::StringASCII(b)) => ValueType::StringASCII(a.max(b)),
+        (ValueType::StringUTF8(a), ValueType::StringUTF8(b)) => ValueType::StringUTF8(a.max(b)),
+        (ValueType::List(a, len_a), ValueType::List(b, len_b)) => {
+            ValueType::List(merge_inner(a, b), len_a.max(len_b))
+        }
+        (known, _) => known,
+    }
+}
+
+fn skip_whitespaces(chars: &mut Peekable<Chars>) {
+    while let Some(c) = chars.peek() {
+        if !c.is_whitespace() {
+            break;
+        }
+        chars.next();
+    }
+}
+
+fn read_word(chars: &mut Peekable<Chars>) -> String {
+    let mut word = String::new();
+    while let Some(c) = chars.peek() {
+        if c.is_alphanumeric() || *c == '-' || *c == '_' || *c == '.' {
+            word.push(*c);
+            chars.next();
+        } else {
+            break;
+        }
+    }
+    word
+}
+
+/// Consume a string literal (opening quote already consumed) and return the
+/// number of bytes it holds once unescaped.
+fn read_string_len(chars: &mut Peekable<Chars>) -> Option<usize> {
+    let mut len = 0;
+    loop {
+        match chars.next()? {
+            '"' => return Some(len),
+            '\\' => match chars.next()? {
+                'x' => {
+                    chars.next()?;
+                    chars.next()?;
+                    len += 1;
+                }
+                'u' => {
+                    if chars.next()? != '{' {
+                        return None;
+                    }
+                    let mut digits = 0;
+                    while chars.next()? != '}' {
+                        digits += 1;
+                    }
+                    len += digits / 2;
+                }
+                _ => len += 1,
+            },
+            _ => len += 1,
+        }
+    }
+}
+
+/// Render an interpretation error, pointing at the offending part of the
+/// snippet when the diagnostic carries a span.
+pub fn format_error(snippet: &str, message: &str, diagnostic: Option<&Diagnostic>) -> Vec<String> {
+    let mut output = vec![colors::red(message)];
+    let spans = match diagnostic {
+        Some(diagnostic) => &diagnostic.spans,
+        None => return output,
+    };
+    let lines: Vec<&str> = snippet.lines().collect();
+    for span in spans.iter() {
+        let line = match lines.get(span.start_line.saturating_sub(1) as usize) {
+            Some(line) => line,
+            None => continue,
+        };
+        let begin = span.start_column.saturating_sub(1) as usize;
+        let end = if span.end_line == span.start_line {
+            (span.end_column as usize).max(begin + 1)
+        } else {
+            line.len().max(begin + 1)
+        };
+        output.push(line.to_string());
+        output.push(format!(
+            "{}{}",
+            " ".repeat(begin),
+            colors::red("^".repeat(end - begin))
+        ));
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn types_are_inferred_from_printed_values() {
+        let type_of = |value: &str| infer_type(value).map(|value_type| value_type.to_string());
+        assert_eq!(type_of("(ok u5)"), Some("(response uint ...)".to_string()));
+        assert_eq!(type_of("(err 3)"), Some("(response ... int)".to_string()));
+        assert_eq!(type_of("none"), Some("(optional ...)".to_string()));
+        assert_eq!(type_of("[(some 1), none]"), Some("(list 2 (optional int))".to_string()));
+        assert_eq!(type_of("{a: 0x0102, b: \"hi\"}"), Some("(tuple (a (buff 2)) (b (string-ascii 2)))".to_string()));
+        assert_eq!(type_of("u\"\\u{e29d93}\""), Some("(string-utf8 3)".to_string()));
+        assert_eq!(type_of("'ST1PQHQKV0RJXZFY1DGX8MNSNYVE3VGZJSRTPGZGM.token"), Some("principal".to_string()));
+        assert_eq!(type_of("(ok u5"), None);
+        assert_eq!(type_of("u5 u6"), None);
+    }
+
+    #[test]
+    fn values_are_formatted_with_their_type() {
+        colors::disable_color();
+        let value = Value::okay(Value::UInt(5)).unwrap();
+        assert_eq!(format_value(&value), "(ok u5) :: (response uint ...)");
+        let value = Value::list_from(vec![Value::none(), Value::some(Value::Int(1)).unwrap()]).unwrap();
+        assert_eq!(format_value(&value), "[none, (some 1)] :: (list 2 (optional int))");
+        assert_eq!(format_printed_value("(ok u5)"), "(ok u5) :: (response uint ...)");
+        assert_eq!(format_printed_value("(ok"), "(ok");
+    }
+}
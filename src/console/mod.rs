@@ -1,51 +1,125 @@
 use std::fs;
-use std::env;
-use crate::types::{MainConfig, ChainConfig};
-use clarity_repl::{repl, Terminal};
+use std::path::{Path, PathBuf};
+use crate::links::{self, RetryPolicy};
+use crate::types::{MainConfig, ChainConfig, Network, NetworkConfig};
+use crate::outputln;
+use crate::utils::mnemonic;
+use bip39::Mnemonic;
+use clarity_repl::clarity::ast::parser;
+use clarity_repl::repl;
+use sha2::{Digest, Sha256};
 
+mod formatter;
+mod on_chain;
+mod terminal;
 
-pub fn load_session(start_repl: bool, env: String) -> Result<repl::SessionSettings, String> {
-    let mut settings = repl::SessionSettings::default();
-
-    let root_path = env::current_dir().unwrap();
-    let mut project_config_path = root_path.clone();
-    project_config_path.push("Clarinet.toml");
+pub use on_chain::ChainReader;
+use terminal::{evaluate, Terminal};
 
-    let mut chain_config_path = root_path.clone();
-    chain_config_path.push("settings");
+/// Builds the settings of a console session, called again on `::reload`.
+pub type SettingsLoader = Box<dyn Fn() -> Result<repl::SessionSettings, String>>;
 
-    chain_config_path.push(if env == "mocknet" {
-        "Mocknet.toml"
-    } else if env == "testnet" {
-        "Testnet.toml"
+pub fn load_session(manifest_path: &Path, start_repl: bool, env: String) -> Result<repl::SessionSettings, String> {
+    let settings = load_session_settings(manifest_path, &env)?;
+    if start_repl {
+        let manifest_path = manifest_path.to_path_buf();
+        start_console(settings.clone(), Box::new(move || load_session_settings(&manifest_path, &env)), false, None);
     } else {
-        "Development.toml"
-    });
+        let mut session = repl::Session::new(settings.clone());
+        session.check()?;
+    }
+    Ok(settings)
+}
 
-    let mut project_config = MainConfig::from_path(&project_config_path);
-    let chain_config = ChainConfig::from_path(&chain_config_path);
+/// Start an interactive session. With `watch`, the session is reloaded with
+/// `loader` whenever the project files change. With a `chain` reader, the
+/// `contract-call?` entered are read from its node.
+pub fn start_console(settings: repl::SessionSettings, loader: SettingsLoader, watch: bool, chain: Option<ChainReader>) {
+    let mut terminal = Terminal::new(settings, loader, watch, chain);
+    terminal.start();
+}
 
-    let mut deployer_address = None;
-    let mut initial_deployer = None;
+/// Evaluate the top level expressions of `script`, one after the other, in a
+/// session loaded with `settings`, printing the result of each of them. A
+/// failing expression is reported with its line, and stops the evaluation
+/// with `halt_on_error`. Returns the number of expressions that failed.
+pub fn eval_script(settings: repl::SessionSettings, script: &str, halt_on_error: bool) -> Result<usize, String> {
+    let expressions = split_expressions(script)?;
+    let mut session = repl::Session::new(settings);
+    outputln!("{}", session.start());
 
-    for (name, account) in chain_config.accounts.iter() {
-        let account = repl::settings::Account {
-            name: name.clone(),
-            balance: account.balance,
-            address: account.address.clone(),
-            mnemonic: account.mnemonic.clone(),
-            derivation: account.derivation.clone(),
-        };
-        if name == "deployer" {
-            initial_deployer = Some(account.clone());
-            deployer_address = Some(account.address.clone());
+    let mut failures = 0;
+    for (line, expression) in expressions.iter() {
+        match evaluate(&mut session, expression) {
+            Ok(output) => {
+                for output_line in output {
+                    outputln!("{}", output_line);
+                }
+            }
+            Err(output) => {
+                failures += 1;
+                outputln!("Error at line {}:", line);
+                for output_line in output {
+                    outputln!("{}", output_line);
+                }
+                if halt_on_error {
+                    break;
+                }
+            }
         }
-        settings
-            .initial_accounts
-            .push(account);
     }
+    Ok(failures)
+}
+
+/// Source of each top level expression of `script`, with the line it starts at.
+fn split_expressions(script: &str) -> Result<Vec<(u32, String)>, String> {
+    let expressions = parser::parse(script).map_err(|e| {
+        let line = e.diagnostic.spans.first().map_or(0, |span| span.start_line);
+        format!("Error: unable to parse the script at line {}: {}", line, e)
+    })?;
+    let lines: Vec<&str> = script.lines().collect();
+    let mut sources = vec![];
+    for expression in expressions.iter() {
+        let span = &expression.span;
+        let mut source = String::new();
+        for line_number in span.start_line..=span.end_line {
+            let line = lines.get((line_number as usize).saturating_sub(1)).cloned().unwrap_or_default();
+            let start = if line_number == span.start_line { (span.start_column as usize).saturating_sub(1) } else { 0 };
+            let end = if line_number == span.end_line { span.end_column as usize } else { line.chars().count() };
+            if line_number != span.start_line {
+                source.push('\n');
+            }
+            source.extend(line.chars().skip(start).take(end.saturating_sub(start)));
+        }
+        sources.push((span.start_line, source));
+    }
+    Ok(sources)
+}
+
+/// Settings of a session loaded with the contracts of the project described
+/// by the manifest at `manifest_path`, its paths being relative to the
+/// manifest's directory.
+pub fn load_session_settings(manifest_path: &Path, env: &str) -> Result<repl::SessionSettings, String> {
+    let chain_config = load_chain_config(manifest_path, env)?;
+    let mut settings = chain_settings(&chain_config);
+    // Links living on the chain of the environment are fetched from its node.
+    let chain = env.parse::<Network>().map_err(|e| format!("Error: {}", e))?;
+    let link_node = |contract_id: &str| match chain {
+        Network::Mainnet if contract_id.starts_with("SP") => Some(chain_config.network.node_url.clone()),
+        Network::Testnet if contract_id.starts_with("ST") => Some(chain_config.network.node_url.clone()),
+        _ => None,
+    };
+
+    let root_path = project_root(manifest_path);
+    let mut project_config = MainConfig::from_path(manifest_path)?;
+    project_config.validate_paths(&root_path)?;
+    project_config.check_name_collisions()?;
+    if !project_config.has_contracts() {
+        warn!("no contracts defined in {}", manifest_path.display());
+    }
+    let deployer_address = settings.initial_deployer.as_ref().map(|deployer| deployer.address.clone());
 
-    for (name, config) in project_config.ordered_contracts().iter() {
+    for (name, config) in project_config.contracts_for_env(env)?.iter() {
         let mut contract_path = root_path.clone();
         contract_path.push(&config.path);
 
@@ -70,25 +144,163 @@ pub fn load_session(start_repl: bool, env: String) -> Result<repl::SessionSettin
         None => vec![],
     };
 
+    // Links pinned to a block height, or cached, are resolved here, and
+    // deployed ahead of the project's contracts by their original deployer.
+    let mut pinned_contracts = vec![];
     for link_config in links.iter() {
+        let resolved = match (link_config.block_height, link_config.cache) {
+            (Some(block_height), _) => {
+                links::resolve_link_at_height(&root_path, &link_config.contract_id, block_height, &RetryPolicy::default())?
+            }
+            (None, true) => links::resolve_cached_link(&root_path, &link_config.contract_id, &RetryPolicy::default())?,
+            (None, false) => {
+                settings
+                    .initial_links
+                    .push(repl::settings::InitialLink {
+                        contract_id: link_config.contract_id.clone(),
+                        stacks_node_addr: link_node(&link_config.contract_id),
+                        cache: None,
+                });
+                continue;
+            }
+        };
+        for (contract_id, code) in resolved.into_iter() {
+            let components: Vec<&str> = contract_id.split('.').collect();
+            let name = Some(components[1].to_string());
+            if pinned_contracts.iter().any(|c: &repl::settings::InitialContract| c.deployer.as_deref() == Some(components[0]) && c.name == name) {
+                continue;
+            }
+            pinned_contracts.push(repl::settings::InitialContract {
+                code,
+                name,
+                deployer: Some(components[0].to_string()),
+            });
+        }
+    }
+    settings.initial_contracts.splice(0..0, pinned_contracts);
+
+    settings.include_boot_contracts = true;
+
+    Ok(settings)
+}
+
+/// Settings holding the accounts defined in `settings/<Env>.toml`, next to
+/// the manifest at `manifest_path`, with the `deployer` account set as
+/// initial deployer. No contract is loaded.
+pub fn load_chain_settings(manifest_path: &Path, env: &str) -> Result<repl::SessionSettings, String> {
+    let chain_config = load_chain_config(manifest_path, env)?;
+    Ok(chain_settings(&chain_config))
+}
+
+/// RPC endpoint of the node of the environment `env`, from `settings/<Env>.toml`.
+pub fn load_node_url(manifest_path: &Path, env: &str) -> Result<String, String> {
+    Ok(load_chain_config(manifest_path, env)?.network.node_url)
+}
+
+/// `[network]` section of `settings/<Env>.toml`, for the environment `env`.
+pub fn load_network_config(manifest_path: &Path, env: &str) -> Result<NetworkConfig, String> {
+    Ok(load_chain_config(manifest_path, env)?.network)
+}
+
+fn load_chain_config(manifest_path: &Path, env: &str) -> Result<ChainConfig, String> {
+    let mut chain_config_path = project_root(manifest_path);
+    chain_config_path.push("settings");
+
+    let network = env.parse::<Network>().map_err(|e| format!("Error: {}", e))?;
+    chain_config_path.push(network.settings_file());
+
+    debug!("Loading accounts from {}", chain_config_path.display());
+    ChainConfig::from_path(&chain_config_path)
+}
+
+fn chain_settings(chain_config: &ChainConfig) -> repl::SessionSettings {
+    let mut settings = repl::SessionSettings::default();
+    for (name, account) in chain_config.accounts.iter() {
+        let account = repl::settings::Account {
+            name: name.clone(),
+            balance: account.balance,
+            address: account.address.clone(),
+            mnemonic: account.mnemonic.clone(),
+            derivation: account.derivation.clone(),
+        };
+        if name == "deployer" {
+            settings.initial_deployer = Some(account.clone());
+        }
         settings
-            .initial_links
-            .push(repl::settings::InitialLink {
-                contract_id: link_config.contract_id.clone(),
-                stacks_node_addr: None,
-                cache: None,
+            .initial_accounts
+            .push(account);
+    }
+    settings
+}
+
+/// Directory of the manifest, the paths of the project being relative to it.
+fn project_root(manifest_path: &Path) -> PathBuf {
+    manifest_path.parent().map_or_else(|| PathBuf::from("."), Path::to_path_buf)
+}
+
+/// Append `count` accounts deterministically derived from `seed`.
+///
+/// The seed string is hashed with SHA-256, and the 32 bytes digest is used as
+/// the entropy of a 24 words BIP39 mnemonic, shared by all the seeded accounts.
+/// The n-th seeded account (starting at 0) uses the default derivation path
+/// of `network` with `<n>` as its last index (`m/44'/5757'/0'/0/<n>` unless
+/// the network sets another `coin_type` or `derivation`), its address being
+/// encoded for the chain of `network`. It is named `wallet_<k>`, where `k`
+/// continues after the highest `wallet_*` index already defined in the
+/// settings. The same seed always yields the same mnemonic, addresses and names.
+pub fn append_seeded_accounts(
+    settings: &mut repl::SessionSettings,
+    network: &NetworkConfig,
+    seed: &str,
+    count: u32,
+    balance: u64,
+) -> Result<(), String> {
+    let entropy = Sha256::digest(seed.as_bytes());
+    let words = Mnemonic::from_entropy(&entropy)
+        .map_err(|e| format!("Error: unable to derive mnemonic from seed: {:?}", e))?
+        .to_string();
+
+    let next_index = settings
+        .initial_accounts
+        .iter()
+        .filter_map(|account| account.name.strip_prefix("wallet_"))
+        .filter_map(|index| index.parse::<u32>().ok())
+        .max()
+        .map_or(1, |index| index + 1);
+
+    for i in 0..count {
+        let derivation = network.derivation_path(i);
+        let address = mnemonic::get_stx_address_from_mnemonic(&words, &derivation, network.chain().address_version())?;
+        settings.initial_accounts.push(repl::settings::Account {
+            name: format!("wallet_{}", next_index + i),
+            balance,
+            address,
+            mnemonic: words.clone(),
+            derivation,
         });
     }
+    Ok(())
+}
 
-    settings.include_boot_contracts = true;
-    settings.initial_deployer = initial_deployer;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    if start_repl {
-        let mut terminal = Terminal::new(settings.clone());
-        terminal.start();
-    } else {
-        let mut session = repl::Session::new(settings.clone());
-        session.check()?;
+    // check, test, console and deploy all load the project with
+    // `load_session_settings` (through `load_session` for test).
+    #[test]
+    fn project_without_contracts_loads_an_empty_session() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let manifest_path = tempdir.path().join("Clarinet.toml");
+        fs::create_dir_all(tempdir.path().join("settings")).unwrap();
+        fs::write(&manifest_path, "[project]\nname = \"test\"\n").unwrap();
+        fs::write(tempdir.path().join("settings/Development.toml"), "[network]\nname = \"development\"\n").unwrap();
+
+        let settings = load_session_settings(&manifest_path, "development").unwrap();
+        assert!(settings.initial_contracts.is_empty());
+        assert!(settings.initial_links.is_empty());
+
+        let settings = load_session(&manifest_path, false, "development".to_string()).unwrap();
+        assert!(settings.initial_contracts.is_empty());
     }
-    Ok(settings)
 }
@@ -0,0 +1,173 @@
+use std::time::Duration;
+use clarity_repl::clarity::ast::parser;
+use clarity_repl::clarity::database::ClaritySerializable;
+use clarity_repl::clarity::errors::Error;
+use clarity_repl::clarity::representations::{PreSymbolicExpression, PreSymbolicExpressionType};
+use clarity_repl::clarity::types::{PrincipalData, QualifiedContractIdentifier, TupleData, Value};
+use super::formatter::format_value;
+use crate::utils::colors;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Reads the state of the contracts deployed on a node: the `contract-call?`
+/// entered in an on-chain console are sent to the node's `call-read`
+/// endpoint instead of being executed by the local session.
+pub struct ChainReader {
+    client: reqwest::blocking::Client,
+    node_url: String,
+    /// Issuer of the contracts referred to as `.name`
+    deployer: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ReadOnlyCallResponse {
+    okay: bool,
+    result: Option<String>,
+    cause: Option<String>,
+}
+
+impl ChainReader {
+    pub fn new(node_url: &str, deployer: Option<String>) -> Result<ChainReader, String> {
+        let client = reqwest::blocking::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .map_err(|e| format!("Error: unable to build http client: {}", e))?;
+        Ok(ChainReader {
+            client,
+            node_url: node_url.trim_end_matches('/').to_string(),
+            deployer,
+        })
+    }
+
+    pub fn node_url(&self) -> &str {
+        &self.node_url
+    }
+
+    /// Evaluate `snippet` on the node, as `sender`, if it is a single
+    /// `contract-call?`. Returns `None` for any other snippet, left to the
+    /// local session. Public functions are rejected by the node, writes
+    /// requiring a transaction.
+    pub fn evaluate(&self, snippet: &str, sender: &str) -> Option<Result<Vec<String>, Vec<String>>> {
+        let expressions = parser::parse(snippet).ok()?;
+        let call = match expressions.as_slice() {
+            [expression] => expression.match_list()?,
+            _ => return None,
+        };
+        match call.first().and_then(|keyword| keyword.match_atom()) {
+            Some(keyword) if keyword.as_str() == "contract-call?" => {}
+            _ => return None,
+        }
+        let res = self
+            .parse_call(call)
+            .and_then(|(contract_id, function, arguments)| self.call_read_only(&contract_id, &function, &arguments, sender));
+        Some(match res {
+            Ok(value) => Ok(vec![format_value(&value)]),
+            Err(e) => Err(vec![colors::red(e)]),
+        })
+    }
+
+    fn parse_call(&self, call: &[PreSymbolicExpression]) -> Result<(QualifiedContractIdentifier, String, Vec<Value>), String> {
+        if call.len() < 3 {
+            return Err("Error: expected (contract-call? <contract> <function> <args>...)".to_string());
+        }
+        let contract_id = match self.literal_value(&call[1])? {
+            Value::Principal(PrincipalData::Contract(contract_id)) => contract_id,
+            value => return Err(format!("Error: expected a contract, found {}", value)),
+        };
+        let function = match call[2].match_atom() {
+            Some(function) => function.to_string(),
+            None => return Err("Error: expected the name of a function".to_string()),
+        };
+        let arguments = call[3..]
+            .iter()
+            .map(|argument| self.literal_value(argument))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok((contract_id, function, arguments))
+    }
+
+    /// Value of a literal argument. Arguments are not evaluated by the node:
+    /// only literals, optionals, responses, lists and tuples of literals are
+    /// accepted.
+    fn literal_value(&self, expression: &PreSymbolicExpression) -> Result<Value, String> {
+        let invalid = |e: Error| format!("Error: invalid argument: {:?}", e);
+        match &expression.pre_expr {
+            PreSymbolicExpressionType::AtomValue(value) => Ok(value.clone()),
+            PreSymbolicExpressionType::Atom(name) => match name.as_str() {
+                "true" => Ok(Value::Bool(true)),
+                "false" => Ok(Value::Bool(false)),
+                "none" => Ok(Value::none()),
+                name => Err(format!("Error: unable to read {} on chain, arguments must be literals", name)),
+            },
+            PreSymbolicExpressionType::SugaredContractIdentifier(name) => {
+                let deployer = self
+                    .deployer
+                    .as_ref()
+                    .ok_or_else(|| format!("Error: no deployer account to resolve .{}", name))?;
+                QualifiedContractIdentifier::parse(&format!("{}.{}", deployer, name))
+                    .map(|contract_id| Value::Principal(PrincipalData::Contract(contract_id)))
+                    .map_err(invalid)
+            }
+            PreSymbolicExpressionType::List(items) => {
+                let keyword = items.first().and_then(|keyword| keyword.match_atom()).map(|keyword| keyword.to_string());
+                let values = items
+                    .iter()
+                    .skip(1)
+                    .map(|item| self.literal_value(item))
+                    .collect::<Result<Vec<_>, _>>()?;
+                match (keyword.as_deref(), values.len()) {
+                    (Some("some"), 1) => Value::some(values[0].clone()).map_err(invalid),
+                    (Some("ok"), 1) => Value::okay(values[0].clone()).map_err(invalid),
+                    (Some("err"), 1) => Value::error(values[0].clone()).map_err(invalid),
+                    (Some("list"), _) => Value::list_from(values).map_err(invalid),
+                    _ => Err("Error: unable to read on chain, arguments must be literals".to_string()),
+                }
+            }
+            PreSymbolicExpressionType::Tuple(items) => {
+                let mut fields = vec![];
+                for pair in items.chunks(2) {
+                    let name = pair[0]
+                        .match_atom()
+                        .ok_or_else(|| "Error: expected the name of a tuple field".to_string())?;
+                    fields.push((name.clone(), self.literal_value(&pair[1])?));
+                }
+                TupleData::from_data(fields).map(Value::from).map_err(invalid)
+            }
+            _ => Err("Error: unable to read on chain, arguments must be literals".to_string()),
+        }
+    }
+
+    fn call_read_only(
+        &self,
+        contract_id: &QualifiedContractIdentifier,
+        function: &str,
+        arguments: &[Value],
+        sender: &str,
+    ) -> Result<Value, String> {
+        let url = format!(
+            "{}/v2/contracts/call-read/{}/{}/{}",
+            self.node_url, contract_id.issuer, contract_id.name, function
+        );
+        let body = serde_json::json!({
+            "sender": sender,
+            "arguments": arguments.iter().map(|argument| format!("0x{}", argument.serialize())).collect::<Vec<_>>(),
+        });
+        let response: ReadOnlyCallResponse = self
+            .client
+            .post(&url)
+            .json(&body)
+            .send()
+            .and_then(|response| response.error_for_status())
+            .and_then(|response| response.json())
+            .map_err(|e| format!("Error: unable to call {}.{} on {}: {}", contract_id, function, self.node_url, e))?;
+        match (response.okay, response.result) {
+            (true, Some(result)) => Value::try_deserialize_hex_untyped(result.trim_start_matches("0x"))
+                .map_err(|e| format!("Error: unable to decode the result of {}: {:?}", function, e)),
+            _ => Err(format!(
+                "Error: {} rejected the call to {}: {}",
+                self.node_url,
+                function,
+                response.cause.unwrap_or_default()
+            )),
+        }
+    }
+}
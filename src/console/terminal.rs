@@ -0,0 +1,158 @@
+use std::fs;
+use std::path::Path;
+use clarity_repl::repl::{Session, SessionSettings};
+use rustyline::error::ReadlineError;
+use rustyline::Editor;
+use super::formatter::{format_error, format_printed_value};
+use super::on_chain::ChainReader;
+use super::SettingsLoader;
+use crate::utils::watcher::ProjectWatcher;
+use crate::utils::{colors, DEFAULT_CACHE_DIR};
+
+const VERSION: Option<&'static str> = option_env!("CARGO_PKG_VERSION");
+
+pub struct Terminal {
+    session: Session,
+    loader: SettingsLoader,
+    watcher: Option<ProjectWatcher>,
+    chain: Option<ChainReader>,
+}
+
+impl Terminal {
+    pub fn new(session_settings: SessionSettings, loader: SettingsLoader, watch: bool, chain: Option<ChainReader>) -> Terminal {
+        Terminal {
+            session: Session::new(session_settings),
+            loader,
+            watcher: if watch { Some(ProjectWatcher::new()) } else { None },
+            chain,
+        }
+    }
+
+    /// Rebuild the session from the files on disk. On failure, the current
+    /// session is kept.
+    fn reload(&mut self) -> Vec<String> {
+        match (self.loader)() {
+            Ok(settings) => {
+                self.session = Session::new(settings);
+                let res = self.session.start();
+                vec![res, colors::green("Contracts reloaded")]
+            }
+            Err(e) => vec![colors::red(e)],
+        }
+    }
+
+    pub fn start(&mut self) {
+        println!("{}", colors::green(format!("clarinet console v{}", VERSION.unwrap())));
+        println!("{}", colors::gray("Enter \"::help\" for usage hints."));
+        match self.chain {
+            Some(ref chain) => println!(
+                "{}",
+                colors::gray(format!("Reading contract-call? from {}, other expressions use a transient in-memory database.", chain.node_url()))
+            ),
+            None => println!("{}", colors::gray("Connected to a transient in-memory database.")),
+        }
+
+        let res = self.session.start();
+        println!("{}", res);
+
+        let history_path = Path::new(DEFAULT_CACHE_DIR).join("history.txt");
+        let mut editor = Editor::<()>::new();
+        let _ = editor.load_history(&history_path);
+        let mut ctrl_c_acc = 0;
+        loop {
+            let readline = editor.readline(">> ");
+            match readline {
+                Ok(command) => {
+                    let changed = match self.watcher {
+                        Some(ref mut watcher) => watcher.has_changed(),
+                        None => false,
+                    };
+                    if changed {
+                        println!("{}", colors::gray("Project files changed, reloading..."));
+                        for line in self.reload() {
+                            println!("{}", line);
+                        }
+                    }
+                    for line in self.handle_command(&command) {
+                        println!("{}", line);
+                    }
+                    ctrl_c_acc = 0;
+                    editor.add_history_entry(command.as_str());
+                }
+                Err(ReadlineError::Interrupted) => {
+                    ctrl_c_acc += 1;
+                    if ctrl_c_acc == 2 {
+                        break;
+                    } else {
+                        println!("{}", colors::yellow("Hit CTRL-C a second time to quit."));
+                    }
+                }
+                Err(ReadlineError::Eof) => {
+                    println!("CTRL-D");
+                    break;
+                }
+                Err(err) => {
+                    println!("Error: {:?}", err);
+                    break;
+                }
+            }
+        }
+        if fs::create_dir_all(DEFAULT_CACHE_DIR).is_ok() {
+            let _ = editor.save_history(&history_path);
+        }
+    }
+
+    /// Commands (`::help`, `::get_contracts`, ...) are delegated to the session,
+    /// snippets are evaluated here so that results can be printed with their type.
+    pub fn handle_command(&mut self, command: &str) -> Vec<String> {
+        if command.trim() == "::reload" {
+            return self.reload();
+        }
+        if command.starts_with("::") {
+            let mut output = self.session.handle_command(command);
+            if command.trim() == "::help" {
+                output.push(colors::yellow("::reload\t\t\t\t\tReload contracts and settings from disk"));
+            }
+            return output;
+        }
+
+        if let Some(ref chain) = self.chain {
+            if let Some(Ok(output)) | Some(Err(output)) = chain.evaluate(command, &self.session.get_tx_sender()) {
+                return output;
+            }
+        }
+        match evaluate(&mut self.session, command) {
+            Ok(output) | Err(output) => output,
+        }
+    }
+}
+
+/// Evaluate `snippet` in `session`, returning the lines to print, as an error
+/// when the evaluation failed.
+pub fn evaluate(session: &mut Session, snippet: &str) -> Result<Vec<String>, Vec<String>> {
+    let mut output = vec![];
+    match session.interpret(snippet.to_string(), None) {
+        Ok(result) => {
+            if let Some((contract_name, _)) = result.contract {
+                output.push(colors::green(format!(
+                    "→ .{} contract successfully stored. Use (contract-call? ...) for invoking the public functions:",
+                    contract_name
+                )));
+            }
+            if result.events.len() > 0 {
+                output.push(colors::gray("Events emitted"));
+                for event in result.events.iter() {
+                    output.push(colors::gray(format!("{}", event)));
+                }
+            }
+            if let Some(value) = result.result {
+                output.push(format_printed_value(&value));
+            }
+            Ok(output)
+        }
+        Err((message, diagnostic)) => {
+            output.append(&mut format_error(snippet, &message, diagnostic.as_ref()));
+            Err(output)
+        }
+    }
+}
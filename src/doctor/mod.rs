@@ -0,0 +1,148 @@
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use crate::types::{ChainConfig, MainConfig};
+
+/// Settings files checked, and whether the project can't do without them.
+const ENVIRONMENTS: &[(&str, &str, bool)] = &[
+    ("development", "Development.toml", true),
+    ("mocknet", "Mocknet.toml", false),
+    ("testnet", "Testnet.toml", false),
+];
+
+pub enum CheckOutcome {
+    Passed,
+    Failed(String),
+    /// The check doesn't apply to the project, for the given reason
+    Skipped(String),
+}
+
+/// Outcome of one of the checks performed by `clarinet doctor`.
+pub struct DoctorCheck {
+    pub label: String,
+    pub outcome: CheckOutcome,
+}
+
+impl DoctorCheck {
+    fn new(label: String, outcome: Result<(), String>) -> DoctorCheck {
+        let outcome = match outcome {
+            Ok(()) => CheckOutcome::Passed,
+            Err(e) => CheckOutcome::Failed(e),
+        };
+        DoctorCheck { label, outcome }
+    }
+
+    fn skipped(label: String, reason: String) -> DoctorCheck {
+        DoctorCheck {
+            label,
+            outcome: CheckOutcome::Skipped(reason),
+        }
+    }
+
+    pub fn failed(&self) -> bool {
+        matches!(self.outcome, CheckOutcome::Failed(_))
+    }
+}
+
+/// Run every check against the project described by the manifest at
+/// `manifest_path`, reporting all the problems found instead of stopping at
+/// the first one.
+pub fn run_checks(manifest_path: &Path, check_nodes: bool) -> Vec<DoctorCheck> {
+    let mut checks = vec![];
+
+    let root_path = manifest_path.parent().unwrap_or_else(|| Path::new("."));
+    let manifest_label = format!(
+        "{} parses",
+        manifest_path.file_name().map_or("Clarinet.toml".into(), |name| name.to_string_lossy())
+    );
+    match MainConfig::from_path(manifest_path) {
+        Ok(project_config) => {
+            checks.push(DoctorCheck::new(manifest_label, Ok(())));
+            checks.push(DoctorCheck::new(
+                "Contracts dependencies can be ordered".into(),
+                project_config.ordered_contracts().map(|_| ()),
+            ));
+            if let Some(ref contracts) = project_config.contracts {
+                for (name, config) in contracts.iter() {
+                    let outcome = fs::read_to_string(root_path.join(&config.path))
+                        .map(|_| ())
+                        .map_err(|e| format!("unable to read {}: {}", config.path, e));
+                    checks.push(DoctorCheck::new(format!("Contract {} is readable", name), outcome));
+                }
+            }
+        }
+        Err(e) => checks.push(DoctorCheck::new(manifest_label, Err(e))),
+    }
+
+    for (env, file_name, required) in ENVIRONMENTS.iter() {
+        let chain_config_path = root_path.join("settings").join(file_name);
+        let label = format!("settings/{} parses", file_name);
+        if !chain_config_path.exists() {
+            let reason = format!("missing, the {} environment can't be used", env);
+            checks.push(match required {
+                true => DoctorCheck::new(label, Err(reason)),
+                false => DoctorCheck::skipped(label, reason),
+            });
+            continue;
+        }
+        // Accounts are derived one by one, as loading the settings stops at
+        // the first account failing to derive.
+        let addresses = ChainConfig::derive_account_addresses(&chain_config_path);
+        let chain_config = match ChainConfig::from_path(&chain_config_path) {
+            Ok(chain_config) => {
+                checks.push(DoctorCheck::new(label, Ok(())));
+                Some(chain_config)
+            }
+            Err(e) => {
+                checks.push(DoctorCheck::new(label, Err(e)));
+                None
+            }
+        };
+        for (name, address) in addresses.unwrap_or_default().into_iter() {
+            checks.push(DoctorCheck::new(
+                format!("Derivation path of {} ({}) is valid", name, env),
+                address.map(|_| ()),
+            ));
+        }
+        let chain_config = match chain_config {
+            Some(chain_config) => chain_config,
+            None => continue,
+        };
+
+        let shared: Vec<String> = chain_config
+            .accounts_sharing_an_address()
+            .into_iter()
+            .map(|(first, second)| format!("{} and {}", first, second))
+            .collect();
+        checks.push(DoctorCheck::new(
+            format!("Accounts ({}) derive to distinct addresses", env),
+            if shared.is_empty() { Ok(()) } else { Err(format!("shared by {}", shared.join(", "))) },
+        ));
+
+        if check_nodes {
+            let node_url = &chain_config.network.node_url;
+            checks.push(DoctorCheck::new(
+                format!("Node {} ({}) responds", node_url, env),
+                ping_node(node_url),
+            ));
+        }
+    }
+
+    checks
+}
+
+fn ping_node(node_url: &str) -> Result<(), String> {
+    let client = reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(5))
+        .build()
+        .map_err(|e| e.to_string())?;
+    let res = client
+        .get(&format!("{}/v2/info", node_url))
+        .send()
+        .map_err(|e| e.to_string())?;
+    if res.status().is_success() {
+        Ok(())
+    } else {
+        Err(format!("responded with status {}", res.status()))
+    }
+}
@@ -1,27 +1,55 @@
 use std::env;
 use std::fs::{self, File};
 use std::collections::{HashMap, BTreeMap};
-use std::io::{prelude::*, BufReader, Read};
+use std::io::{prelude::*, Read};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
 
-use crate::{generators::{self, changes::{Changes, TOMLEdition}}, utils::mnemonic};
-use crate::types::{MainConfig, MainConfigFile, LinkConfig};
-use crate::console::load_session;
-use crate::test::run_tests;
+use crate::{generators::{self, changes::{Changes, TOMLEdition}, remap_principal, AccountsTemplate, ContractSkeleton, KnownTrait, PrincipalRemap}, utils::{cache::clean_cache_dir, codec, colors, find_project_root, hash::normalized_source_hash, logger, output, version, watcher::ProjectWatcher, DEFAULT_CACHE_DIR}};
+use crate::outputln;
+use crate::types::{Balance, ContractId, Lockfile, MainConfig, MainConfigFile, LinkConfig, Network, ProfileConfig, NODE_URL_VAR};
+use crate::console::{append_seeded_accounts, ChainReader, eval_script, load_chain_settings, load_network_config, load_node_url, load_session, load_session_settings, start_console};
+use crate::analysis::{analyze_contract_traits, build_contract_interfaces, collect_diagnostics, count_contract_definitions, estimate_contract_costs, find_missing_references, find_unused_links, load_baseline, new_diagnostics, save_baseline, ContractCosts, ContractDiagnostic, ContractMetrics, ContractTraits};
+use crate::doctor::{run_checks, CheckOutcome};
+use crate::links::{resolve_link, RetryPolicy};
+use crate::publish::{
+    broadcast_bundle, broadcast_signed_transactions, confirmation_height, deployer_account, export_bundle, export_plan,
+    export_explorer_manifest, export_unsigned_transactions, fetch_account_balances, publish_contracts, wait_for_confirmations, DeployedContract, DeploymentBundle, DeploymentReport, FeeStrategy,
+    PublishOptions, CONFIRMATION_TIMEOUT, DEFAULT_NODE_URL,
+};
+use crate::test::{discover_test_files, run_tests, TestResults};
 
-use clarity_repl::{clarity::{codec::{StacksString, transaction::{RecoverableSignature, SinglesigHashMode, SinglesigSpendingCondition, TransactionVersion}}, util::{StacksAddress, address::AddressHashMode, secp256k1::{Secp256k1PrivateKey, Secp256k1PublicKey}}}, repl};
-use clarity_repl::clarity::codec::transaction::{StacksTransaction, TransactionAnchorMode, TransactionSmartContract, TransactionSpendingCondition, TransactionAuth, TransactionPostConditionMode, TransactionPayload, TransactionPublicKeyEncoding, StacksTransactionSigner};
-use clarity_repl::clarity::codec::StacksMessageCodec;
+use clarity_repl::clarity::ast::parser;
+use clarity_repl::clarity::codec::transaction::TransactionVersion;
+use clarity_repl::clarity::types::PrincipalData;
+use clarity_repl::repl;
 
 use clap::Clap;
-use secp256k1::{PublicKey, SecretKey};
-use tiny_hderive::bip32::ExtendedPrivKey;
 use toml;
 
 #[derive(Clap)]
-#[clap(version = "1.0")]
+#[clap(version)]
 struct Opts {
     #[clap(subcommand)]
     command: Command,
+    /// Verbosity of the messages printed on stderr
+    #[clap(long = "log-level", global = true, default_value = "info", possible_values = &["error", "warn", "info", "debug"])]
+    log_level: String,
+    /// Only print errors and the results of the command (same as --log-level error)
+    #[clap(long = "quiet", short = 'q', global = true)]
+    quiet: bool,
+    /// Path to the project manifest, used instead of the Clarinet.toml found in the current directory or its parents
+    #[clap(long = "manifest-path", global = true)]
+    manifest_path: Option<String>,
+    /// Profile of Clarinet.toml ([profiles.<name>]) overlaid on the default settings
+    #[clap(long = "profile", global = true)]
+    profile: Option<String>,
+    /// Copy the results printed on stdout (deployed contracts, diagnostics, ...) to this file
+    #[clap(long = "output-file", global = true)]
+    output_file: Option<String>,
 }
 
 #[derive(Clap)]
@@ -29,6 +57,9 @@ enum Command {
     /// New subcommand
     #[clap(name = "new")]
     New(GenerateProject),
+    /// Turn the current directory, holding existing contracts, into a project
+    #[clap(name = "init")]
+    Init(InitProject),
     /// Contract subcommand
     #[clap(name = "contract")]
     Contract(Contract),
@@ -44,6 +75,21 @@ enum Command {
     /// Deploy subcommand
     #[clap(name = "deploy")]
     Deploy(Deploy),
+    /// Remove the cache directory (.clarinet): cached links, console history and diagnostics baseline
+    #[clap(name = "clean")]
+    Clean(Clean),
+    /// Doctor subcommand
+    #[clap(name = "doctor")]
+    Doctor(Doctor),
+    /// Encode subcommand
+    #[clap(name = "encode")]
+    Encode(Encode),
+    /// Decode subcommand
+    #[clap(name = "decode")]
+    Decode(Decode),
+    /// Print the version of clarinet, and check for a newer release with --check
+    #[clap(name = "version")]
+    Version(Version),
 }
 
 #[derive(Clap)]
@@ -57,21 +103,88 @@ enum Contract {
     /// Fork contract subcommand
     #[clap(name = "fork")]
     ForkContract(ForkContract),
+    /// List contracts subcommand
+    #[clap(name = "list")]
+    ListContracts(ListContracts),
+    /// Show the dependencies of a contract
+    #[clap(name = "deps")]
+    ContractDeps(ContractDeps),
+    /// Show the chain of dependencies connecting two contracts
+    #[clap(name = "why")]
+    ContractWhy(ContractWhy),
+    /// Print the names of the contracts in deployment order, one per line
+    #[clap(name = "order")]
+    ContractOrder(ContractOrder),
+    /// Print the SHA-256 of the normalized source of a contract
+    #[clap(name = "hash")]
+    ContractHash(ContractHash),
+    /// Print the interface (functions, variables, maps and their types) of a contract as JSON
+    #[clap(name = "interface")]
+    ContractInterface(ContractInterface),
+    /// Report the traits a contract implements and references, and the trait functions it is missing
+    #[clap(name = "analyze")]
+    ContractAnalyze(ContractAnalyze),
 }
 
 #[derive(Clap)]
 struct GenerateProject {
     /// Project's name
     pub name: String,
+    /// Print the changes instead of applying them
+    #[clap(long = "dry-run", alias = "print")]
+    pub dry_run: bool,
     /// Print debug info
     #[clap(short = 'd')]
     pub debug: bool,
+    /// Number of wallet accounts generated in settings/Development.toml (defaults to 9)
+    #[clap(long = "accounts")]
+    pub accounts: Option<u32>,
+    /// Balance of the accounts generated in settings/Development.toml (defaults to 1000000)
+    #[clap(long = "balance")]
+    pub balance: Option<Balance>,
+}
+
+#[derive(Clap)]
+struct InitProject {
+    /// Print the changes instead of applying them
+    #[clap(long = "dry-run", alias = "print")]
+    pub dry_run: bool,
+    /// Print debug info
+    #[clap(short = 'd')]
+    pub debug: bool,
+    /// Number of wallet accounts generated in settings/Development.toml (defaults to 9)
+    #[clap(long = "accounts")]
+    pub accounts: Option<u32>,
+    /// Balance of the accounts generated in settings/Development.toml (defaults to 1000000)
+    #[clap(long = "balance")]
+    pub balance: Option<Balance>,
 }
 
 #[derive(Clap)]
 struct NewContract {
     /// Contract's name
     pub name: String,
+    /// Scaffold a contract implementing a standard trait (sip009, sip010)
+    #[clap(long = "trait")]
+    pub implemented_trait: Option<KnownTrait>,
+    /// Copy the source of the contract from an existing .clar file instead of generating a stub
+    #[clap(long = "from", conflicts_with = "trait")]
+    pub from: Option<String>,
+    /// Scaffold a `value` data-var and its read-only getter
+    #[clap(long = "with-getter", conflicts_with_all = &["trait", "from"])]
+    pub with_getter: bool,
+    /// Scaffold a `value` data-var and its public setter, restricted to the deployer
+    #[clap(long = "with-setter", conflicts_with_all = &["trait", "from"])]
+    pub with_setter: bool,
+    /// Contract of the project the new contract depends on (repeatable)
+    #[clap(long = "depends-on", number_of_values = 1)]
+    pub depends_on: Vec<String>,
+    /// Replace the files of an existing contract, keeping a .bak copy of each of them
+    #[clap(long = "overwrite")]
+    pub overwrite: bool,
+    /// Print the changes instead of applying them
+    #[clap(long = "dry-run", alias = "print")]
+    pub dry_run: bool,
     /// Print debug info
     #[clap(short = 'd')]
     pub debug: bool,
@@ -79,8 +192,11 @@ struct NewContract {
 
 #[derive(Clap)]
 struct LinkContract {
-    /// Contract id
-    pub contract_id: String,
+    /// Contract id (<address>.<contract-name>)
+    pub contract_id: ContractId,
+    /// Pin the contract, and its dependencies, to their state at this block height
+    #[clap(long = "block-height")]
+    pub block_height: Option<u64>,
     /// Print debug info
     #[clap(short = 'd')]
     pub debug: bool,
@@ -88,8 +204,30 @@ struct LinkContract {
 
 #[derive(Clap, Debug)]
 struct ForkContract {
-    /// Contract id
-    pub contract_id: String,
+    /// Contract id (<address>.<contract-name>)
+    pub contract_id: ContractId,
+    /// Replace an address of the source by the address of an account of the
+    /// development settings, or by another address (<address>=<account>, repeatable)
+    #[clap(long = "remap", number_of_values = 1)]
+    pub remap: Vec<PrincipalRemap>,
+    /// Print the changes instead of applying them
+    #[clap(long = "dry-run", alias = "print")]
+    pub dry_run: bool,
+    /// Print the resolved contract, its dependencies and the changes as JSON
+    #[clap(long = "json")]
+    pub json: bool,
+    /// Overwrite the contracts already present in the project instead of skipping them
+    #[clap(long = "force")]
+    pub force: bool,
+    /// Number of attempts made to retrieve each contract before falling back to the cached copy
+    #[clap(long = "retries", default_value = "3")]
+    pub retries: u32,
+    /// Directory of the project the forked contract is written to (defaults to contracts)
+    #[clap(long = "output-dir")]
+    pub output_dir: Option<String>,
+    /// Fork the contract even if some of its dependencies can't be retrieved
+    #[clap(long = "allow-partial")]
+    pub allow_partial: bool,
     /// Print debug info
     #[clap(short = 'd')]
     pub debug: bool,
@@ -98,11 +236,146 @@ struct ForkContract {
     // pub recursive: bool,
 }
 
+#[derive(Clap)]
+struct ListContracts {
+    /// Print debug info
+    #[clap(short = 'd')]
+    pub debug: bool,
+}
+
+#[derive(Clap)]
+struct ContractDeps {
+    /// Contract's name
+    #[clap(required_unless_present = "missing")]
+    pub name: Option<String>,
+    /// List the contracts depending on the contract instead
+    #[clap(long = "reverse")]
+    pub reverse: bool,
+    /// List, for every contract, the contracts it references which are neither a
+    /// contract of the project, a link or one of its dependencies, nor a boot contract
+    #[clap(long = "missing", conflicts_with_all = &["name", "reverse"])]
+    pub missing: bool,
+    /// Print the contracts as JSON
+    #[clap(long = "json")]
+    pub json: bool,
+    /// Print debug info
+    #[clap(short = 'd')]
+    pub debug: bool,
+}
+
+#[derive(Clap)]
+struct ContractWhy {
+    /// Contract's name
+    pub from: String,
+    /// Name of the contract it may depend on
+    pub to: String,
+    /// Print debug info
+    #[clap(short = 'd')]
+    pub debug: bool,
+}
+
+#[derive(Clap)]
+struct ContractOrder {
+    /// Print the contracts in teardown order, dependents first
+    #[clap(long = "reverse")]
+    pub reverse: bool,
+    /// Print debug info
+    #[clap(short = 'd')]
+    pub debug: bool,
+}
+
+#[derive(Clap)]
+struct ContractHash {
+    /// Contract's name
+    #[clap(required_unless_present = "all")]
+    pub name: Option<String>,
+    /// Hash every contract of the project
+    #[clap(long = "all", conflicts_with = "name")]
+    pub all: bool,
+    /// Print the hashes as a JSON object, by contract name
+    #[clap(long = "json")]
+    pub json: bool,
+    /// Print debug info
+    #[clap(short = 'd')]
+    pub debug: bool,
+}
+
+#[derive(Clap)]
+struct ContractInterface {
+    /// Contract's name
+    #[clap(required_unless_present = "all")]
+    pub name: Option<String>,
+    /// Print the interfaces of every contract of the project, as a JSON object by contract name
+    #[clap(long = "all", conflicts_with = "name")]
+    pub all: bool,
+    /// Write the interface of each contract to <out-dir>/<name>.json instead of printing it
+    #[clap(long = "out-dir")]
+    pub out_dir: Option<String>,
+    /// Environment to analyze the contracts in (mocknet, testnet, mainnet or dev)
+    #[clap(long = "network")]
+    pub network: Option<Network>,
+    /// Print debug info
+    #[clap(short = 'd')]
+    pub debug: bool,
+}
+
+#[derive(Clap)]
+struct ContractAnalyze {
+    /// Contract's name
+    #[clap(required_unless_present = "all")]
+    pub name: Option<String>,
+    /// Analyze every contract of the project
+    #[clap(long = "all", conflicts_with = "name")]
+    pub all: bool,
+    /// List the traits implemented (impl-trait) and referenced (use-trait), and check the implemented ones
+    #[clap(long = "traits")]
+    pub traits: bool,
+    /// Print the report as JSON
+    #[clap(long = "json")]
+    pub json: bool,
+    /// Environment to analyze the contracts in (mocknet, testnet, mainnet or dev)
+    #[clap(long = "network")]
+    pub network: Option<Network>,
+    /// Print debug info
+    #[clap(short = 'd')]
+    pub debug: bool,
+}
+
 #[derive(Clap)]
 struct Console {
     /// Print debug info
     #[clap(short = 'd')]
     pub debug: bool,
+    /// Disable colored output (also honors NO_COLOR)
+    #[clap(long = "no-color")]
+    pub no_color: bool,
+    /// Seed used to derive extra deterministic accounts
+    #[clap(long = "seed")]
+    pub seed: Option<String>,
+    /// Number of accounts derived from --seed
+    #[clap(long = "seed-accounts", default_value = "10")]
+    pub seed_accounts: u32,
+    /// Balance of the accounts derived from --seed
+    #[clap(long = "seed-balance", default_value = "1000000")]
+    pub seed_balance: u64,
+    /// Reload the session when contracts or settings change on disk
+    #[clap(long = "watch")]
+    pub watch: bool,
+    /// Environment to load (mocknet, testnet, mainnet or dev)
+    #[clap(long = "network")]
+    pub network: Option<Network>,
+    /// Evaluate the expressions of a Clarity script, one after the other, and exit
+    #[clap(long = "eval-file", conflicts_with = "watch")]
+    pub eval_file: Option<String>,
+    /// Stop at the first expression of --eval-file failing
+    #[clap(long = "halt-on-error", requires = "eval-file")]
+    pub halt_on_error: bool,
+    /// Read the contract-call? entered from the node of the environment, instead of the local session
+    #[clap(long = "on-chain", conflicts_with = "eval-file")]
+    pub on_chain: bool,
+    /// Node to read from with --on-chain (defaults to the node of the environment)
+    #[clap(long = "node-url", requires = "on-chain")]
+    pub node_url: Option<String>,
 }
 
 #[derive(Clap)]
@@ -110,7 +383,37 @@ struct Test {
     /// Print debug info
     #[clap(short = 'd')]
     pub debug: bool,
+    /// Comma separated list of environments to run the test suite against (defaults to development)
+    #[clap(long = "env", conflicts_with = "network")]
+    pub env: Option<String>,
+    /// Environment to run the test suite against (mocknet, testnet, mainnet or dev)
+    #[clap(long = "network")]
+    pub network: Option<Network>,
+    /// Seed used to derive extra deterministic accounts
+    #[clap(long = "seed")]
+    pub seed: Option<String>,
+    /// Number of accounts derived from --seed
+    #[clap(long = "seed-accounts", default_value = "10")]
+    pub seed_accounts: u32,
+    /// Balance of the accounts derived from --seed
+    #[clap(long = "seed-balance", default_value = "1000000")]
+    pub seed_balance: u64,
+    /// Deploy the contracts to the mocknet node, and wait for their confirmation, before running the tests
+    #[clap(long = "seed-contracts")]
+    pub seed_contracts: bool,
+    /// Seconds to wait for the confirmation of the contracts deployed with --seed-contracts
+    #[clap(long = "seed-timeout", default_value = "600")]
+    pub seed_timeout: u64,
+    /// Print the <n> slowest tests once the suite has run
+    #[clap(long = "show-slowest")]
+    pub show_slowest: Option<usize>,
+    /// Format of the results: pretty, or a Test Anything Protocol stream (tap)
+    #[clap(long = "reporter", default_value = "pretty", possible_values = &["pretty", "tap"])]
+    pub reporter: String,
     pub files: Vec<String>,
+    /// Arguments passed to the tests (`Deno.args`), after `--`
+    #[clap(last = true)]
+    pub runtime_args: Vec<String>,
 }
 
 #[derive(Clap)]
@@ -121,9 +424,107 @@ struct Deploy {
     /// Deploy contracts on mocknet, using settings/Mocknet.toml
     #[clap(long = "mocknet", conflicts_with = "testnet")]
     pub mocknet: bool,
-    /// Deploy contracts on mocknet, using settings/Testnet.toml
+    /// Deploy contracts on testnet, using settings/Testnet.toml
     #[clap(long = "testnet", conflicts_with = "mocknet")]
     pub testnet: bool,
+    /// Network to deploy the contracts on (mocknet, testnet, mainnet or dev), instead of --mocknet / --testnet
+    #[clap(long = "network", conflicts_with_all = &["mocknet", "testnet"])]
+    pub network: Option<Network>,
+    /// Deploy a single contract file ("-" for stdin) instead of the contracts listed in Clarinet.toml
+    #[clap(long = "contract")]
+    pub contract: Option<String>,
+    /// Name of the contract deployed with --contract (defaults to the file name)
+    #[clap(long = "name", requires = "contract")]
+    pub name: Option<String>,
+    /// Only deploy the contracts carrying one of these comma separated tags (`*` wildcards allowed)
+    #[clap(long = "tag", conflicts_with = "contract")]
+    pub tag: Option<String>,
+    /// Broadcast the contracts without checking that they analyze first
+    #[clap(long = "skip-check")]
+    pub skip_check: bool,
+    /// Publish the contracts, in order, in a local session instead of a node, and report the ones failing to instantiate
+    #[clap(long = "simulate", conflicts_with_all = &["skip-check", "unsigned-out", "export-bundle", "signed-in", "broadcast-bundle", "emit-plan", "interactive", "json"])]
+    pub simulate: bool,
+    /// Node the transactions are sent to, instead of the node_url of the settings of the network
+    #[clap(long = "node-url")]
+    pub node_url: Option<String>,
+    /// Timeout, in seconds, of each request sent to the node
+    #[clap(long = "request-timeout", default_value = "30")]
+    pub request_timeout: u64,
+    /// How transaction fees are computed: fixed (200 µSTX + 1 µSTX per byte of code, the default), estimate (node's fee rate) or manual (--fee)
+    #[clap(long = "fee-strategy", possible_values = &["fixed", "estimate", "manual"])]
+    pub fee_strategy: Option<String>,
+    /// Fee, in µSTX, of each transaction, with --fee-strategy manual
+    #[clap(long = "fee")]
+    pub fee: Option<u64>,
+    /// Account, from the settings, paying the fees of sponsored transactions
+    #[clap(long = "sponsor")]
+    pub sponsor: Option<String>,
+    /// Account, from the settings, deploying every contract instead of `deployer`
+    #[clap(long = "deployer", conflicts_with_all = &["signed-in", "broadcast-bundle"])]
+    pub deployer: Option<String>,
+    /// Prompt for the account deploying every contract, among the accounts of the settings
+    #[clap(long = "interactive", conflicts_with_all = &["signed-in", "broadcast-bundle", "json"])]
+    pub interactive: bool,
+    /// Never prompt, deploying with `deployer` unless --deployer is set
+    #[clap(long = "yes")]
+    pub yes: bool,
+    /// Nonce of the first transaction of the deployer, instead of fetching it from the node
+    #[clap(long = "nonce", conflicts_with_all = &["signed-in", "broadcast-bundle"])]
+    pub nonce: Option<u64>,
+    /// Version of the transactions, instead of the one of the network. Transactions
+    /// whose version doesn't match the chain they are sent to are rejected by its nodes
+    #[clap(long = "tx-version", possible_values = &["mainnet", "testnet"], conflicts_with_all = &["signed-in", "broadcast-bundle"])]
+    pub tx_version: Option<String>,
+    /// Write the unsigned transactions to this directory instead of signing and broadcasting them
+    #[clap(long = "unsigned-out", conflicts_with = "signed-in")]
+    pub unsigned_out: Option<String>,
+    /// Broadcast the signed transactions (hex encoded .tx files) found in this directory
+    #[clap(long = "signed-in")]
+    pub signed_in: Option<String>,
+    /// Write the signed transactions, with their metadata, to this JSON file instead of broadcasting them
+    #[clap(long = "export-bundle", conflicts_with = "unsigned-out")]
+    pub export_bundle: Option<String>,
+    /// Broadcast the transactions of a bundle written with --export-bundle
+    #[clap(long = "broadcast-bundle", conflicts_with = "signed-in")]
+    pub broadcast_bundle: Option<String>,
+    /// Write the ordered contracts, with their deployer, nonce and fee, to this file (TOML for a .toml file, JSON otherwise), without signing or broadcasting anything
+    #[clap(long = "emit-plan", conflicts_with_all = &["unsigned-out", "export-bundle", "signed-in", "broadcast-bundle"])]
+    pub emit_plan: Option<String>,
+    /// Once deployed, write the txid, source and interface of each contract, keyed by principal, to this JSON file
+    #[clap(long = "export-manifest", conflicts_with_all = &["unsigned-out", "export-bundle", "emit-plan", "simulate", "signed-in", "broadcast-bundle"])]
+    pub export_manifest: Option<String>,
+    /// Print the contracts deployed and the fees paid by each account as JSON
+    #[clap(long = "json", conflicts_with_all = &["unsigned-out", "export-bundle", "emit-plan"])]
+    pub json: bool,
+    /// Wait for the confirmation of the contracts deployed before exiting
+    #[clap(long = "wait", conflicts_with_all = &["unsigned-out", "export-bundle", "emit-plan", "simulate", "signed-in", "broadcast-bundle"])]
+    pub wait: bool,
+    /// Seconds between two checks of the status of the transactions, when waiting for their confirmation
+    #[clap(long = "poll-interval", default_value = "5")]
+    pub poll_interval: u64,
+    /// Blocks a transaction must be buried under to be confirmed, including the one including it
+    #[clap(long = "confirmations", default_value = "1")]
+    pub confirmations: u64,
+    /// Strip the comments and superfluous whitespace of the contracts before deploying them
+    #[clap(long = "minify", conflicts_with_all = &["signed-in", "broadcast-bundle"])]
+    pub minify: bool,
+}
+
+impl Deploy {
+    /// Network selected by --network, by the legacy --mocknet / --testnet
+    /// flags, or by `CLARINET_ENV`.
+    fn target(&self) -> Option<Network> {
+        if self.network.is_some() {
+            self.network
+        } else if self.mocknet {
+            Some(Network::Mocknet)
+        } else if self.testnet {
+            Some(Network::Testnet)
+        } else {
+            env_network()
+        }
+    }
 }
 
 #[derive(Clap)]
@@ -131,63 +532,421 @@ struct Check {
     /// Print debug info
     #[clap(short = 'd')]
     pub debug: bool,
+    /// Report static cost estimates for the functions of each contract
+    #[clap(long = "costs")]
+    pub costs: bool,
+    /// Report the number of functions, data vars, maps and constants of each contract
+    #[clap(long = "metrics", conflicts_with = "costs")]
+    pub metrics: bool,
+    /// Print the cost estimates or the metrics as JSON
+    #[clap(long = "json")]
+    pub json: bool,
+    /// Only report the diagnostics missing from .clarinet/diagnostics-baseline.json
+    #[clap(long = "diff")]
+    pub diff: bool,
+    /// Store the current diagnostics in .clarinet/diagnostics-baseline.json
+    #[clap(long = "update-baseline")]
+    pub update_baseline: bool,
+    /// Warn about the links of Clarinet.toml that no contract references
+    #[clap(long = "warn-unused-links")]
+    pub warn_unused_links: bool,
+    /// Disable colored output (also honors NO_COLOR)
+    #[clap(long = "no-color")]
+    pub no_color: bool,
+    /// Check a snippet read from stdin, deployed after the contracts of the project
+    #[clap(long = "snippet", conflicts_with_all = &["costs", "metrics", "diff", "update-baseline"])]
+    pub snippet: bool,
+    /// Skip loading the boot contracts, for faster checks. Contracts referring
+    /// to a boot contract (e.g. .pox or .bns) are then reported as errors
+    #[clap(long = "no-boot-contracts", conflicts_with_all = &["diff", "update-baseline"])]
+    pub no_boot_contracts: bool,
+    /// Only print the first <n> diagnostics, followed by the number of diagnostics left out
+    #[clap(long = "max-errors", conflicts_with_all = &["diff", "update-baseline"])]
+    pub max_errors: Option<usize>,
+    /// Environment to check the contracts in (mocknet, testnet, mainnet or dev)
+    #[clap(long = "network")]
+    pub network: Option<Network>,
+    /// Only check these comma separated contracts, along with the contracts they depend on
+    #[clap(long = "contracts", conflicts_with_all = &["diff", "update-baseline"])]
+    pub contracts: Option<String>,
+    /// Check the contracts again whenever Clarinet.toml, the contracts or the settings change
+    #[clap(long = "watch", conflicts_with_all = &["snippet", "diff", "update-baseline", "costs", "metrics", "json"])]
+    pub watch: bool,
+    /// Redraw a status line per contract on each check instead of printing the diagnostics
+    #[clap(long = "clear", requires = "watch")]
+    pub clear: bool,
+    /// Print the diagnostics below the status lines of --clear
+    #[clap(long = "verbose", requires = "clear")]
+    pub verbose: bool,
+}
+
+#[derive(Clap)]
+struct Clean {
+    /// Print debug info
+    #[clap(short = 'd')]
+    pub debug: bool,
+    /// Cache directory to remove (defaults to .clarinet)
+    #[clap(long = "cache-dir")]
+    pub cache_dir: Option<String>,
+    /// List the files that would be removed, without removing them
+    #[clap(long = "dry-run")]
+    pub dry_run: bool,
+}
+
+#[derive(Clap)]
+struct Doctor {
+    /// Print debug info
+    #[clap(short = 'd')]
+    pub debug: bool,
+    /// Also check that the nodes configured in settings/*.toml respond
+    #[clap(long = "check-nodes")]
+    pub check_nodes: bool,
+}
+
+#[derive(Clap)]
+struct Encode {
+    /// Clarity value to serialize, e.g. "(tuple (a u1))"
+    pub value: String,
+}
+
+#[derive(Clap)]
+struct Decode {
+    /// Hex encoded, consensus-serialized Clarity value
+    pub hex: String,
+}
+
+#[derive(Clap)]
+struct Version {
+    /// Query GitHub for the latest release, and report whether this build is behind
+    #[clap(long = "check")]
+    pub check: bool,
+    /// Timeout, in seconds, of the request sent with --check
+    #[clap(long = "timeout", default_value = "5")]
+    pub timeout: u64,
+}
+
+/// Contracts failing to analyze, or tests failing.
+const EXIT_FAILURE: i32 = 1;
+/// Invalid or missing Clarinet.toml, settings or project files.
+const EXIT_CONFIG_ERROR: i32 = 2;
+/// Node unreachable, or transactions rejected.
+const EXIT_DEPLOY_ERROR: i32 = 3;
+/// Invalid arguments.
+const EXIT_USAGE_ERROR: i32 = 4;
+
+/// Environment variable selecting the environment when no network is passed.
+const ENV_VAR: &str = "CLARINET_ENV";
+
+/// Delay between two scans of the project files by `check --watch`.
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Failure categories of the commands, each one exiting with its own code.
+enum ClarinetError {
+    Failure(String),
+    Config(String),
+    Deploy(String),
+    Usage(String),
+}
+
+impl ClarinetError {
+    fn exit_code(&self) -> i32 {
+        match self {
+            ClarinetError::Failure(_) => EXIT_FAILURE,
+            ClarinetError::Config(_) => EXIT_CONFIG_ERROR,
+            ClarinetError::Deploy(_) => EXIT_DEPLOY_ERROR,
+            ClarinetError::Usage(_) => EXIT_USAGE_ERROR,
+        }
+    }
+}
+
+/// Print the message of `error` to stderr and exit with the code of its
+/// category. The message isn't copied to the `--output-file`.
+fn exit_with(error: ClarinetError) -> ! {
+    let code = error.exit_code();
+    match error {
+        ClarinetError::Failure(message)
+        | ClarinetError::Config(message)
+        | ClarinetError::Deploy(message)
+        | ClarinetError::Usage(message) => eprintln!("{}", message),
+    }
+    process::exit(code)
 }
 
 pub fn main() {
-    let opts: Opts = Opts::parse();
+    let mut opts: Opts = match Opts::try_parse() {
+        Ok(opts) => opts,
+        Err(e) if e.use_stderr() => {
+            eprintln!("{}", e);
+            process::exit(EXIT_USAGE_ERROR);
+        }
+        Err(e) => e.exit(),
+    };
+
+    let log_level = if opts.quiet { "error" } else { opts.log_level.as_str() };
+    if let Err(e) = logger::init(log_level) {
+        exit_with(ClarinetError::Usage(e));
+    }
+    if let Some(ref path) = opts.output_file {
+        if let Err(e) = output::tee_to(Path::new(path)) {
+            exit_with(ClarinetError::Usage(e));
+        }
+    }
+
+    // Commands run from a subdirectory of a project operate on the nearest
+    // project root, or on the directory of the manifest given with
+    // --manifest-path. Paths given on the command line stay relative to the cwd.
+    let mut manifest_path = None;
+    if !matches!(opts.command, Command::New(_) | Command::Init(_)) {
+        let cwd = env::current_dir().expect("Unable to read current directory");
+        let requires_project = match opts.command {
+            Command::Deploy(ref deploy) => {
+                deploy.contract.is_none() && deploy.signed_in.is_none() && deploy.broadcast_bundle.is_none()
+            }
+            Command::Clean(_) | Command::Doctor(_) | Command::Encode(_) | Command::Decode(_) | Command::Version(_) => false,
+            _ => true,
+        };
+        let manifest = match opts.manifest_path {
+            Some(ref path) if cwd.join(path).is_file() => Ok(cwd.join(path)),
+            Some(ref path) => Err(format!("Error: manifest {} not found", cwd.join(path).display())),
+            None => find_project_root(&cwd).map(|root| root.join("Clarinet.toml")),
+        };
+        match manifest {
+            Ok(manifest) => {
+                make_paths_absolute(&mut opts.command, &cwd);
+                env::set_current_dir(manifest.parent().unwrap()).expect("Unable to change directory");
+                manifest_path = Some(manifest);
+            }
+            Err(e) if requires_project || opts.manifest_path.is_some() || opts.profile.is_some() => {
+                exit_with(ClarinetError::Config(e))
+            }
+            Err(_) => {}
+        }
+    }
 
     let current_path = {
         let current_dir = env::current_dir().expect("Unable to read current directory");
         current_dir.to_str().unwrap().to_owned()
     };
+    let manifest_path = manifest_path.unwrap_or_else(|| PathBuf::from(&current_path).join("Clarinet.toml"));
+    let profile = match opts.profile {
+        Some(ref name) => match MainConfig::from_path(&manifest_path).and_then(|config| config.profile(name)) {
+            Ok(profile) => profile,
+            Err(e) => exit_with(ClarinetError::Config(e)),
+        },
+        None => ProfileConfig::default(),
+    };
 
     match opts.command {
         Command::New(project_opts) => {
-            let changes = generators::get_changes_for_new_project(current_path, project_opts.name);
-            execute_changes(changes);
+            let accounts = match AccountsTemplate::new(project_opts.accounts, project_opts.balance) {
+                Ok(accounts) => accounts,
+                Err(e) => exit_with(ClarinetError::Config(e)),
+            };
+            let changes = generators::get_changes_for_new_project(current_path, project_opts.name, accounts);
+            if let Err(e) = execute_or_print_changes(changes, project_opts.dry_run) {
+                exit_with(ClarinetError::Config(e));
+            }
+        }
+        Command::Init(init_opts) => {
+            let accounts = match AccountsTemplate::new(init_opts.accounts, init_opts.balance) {
+                Ok(accounts) => accounts,
+                Err(e) => exit_with(ClarinetError::Config(e)),
+            };
+            let contracts = match generators::detect_contracts(Path::new(&current_path)) {
+                Ok(contracts) => contracts,
+                Err(e) => exit_with(ClarinetError::Config(e)),
+            };
+            let changes = match generators::get_changes_for_existing_project(current_path.clone(), &contracts, accounts) {
+                Ok(changes) => changes,
+                Err(e) => exit_with(ClarinetError::Config(e)),
+            };
+            outputln!("Detected {} contract(s)", contracts.len());
+            for (name, path) in contracts.iter() {
+                outputln!("  {} ({})", name, path);
+            }
+            let created: Vec<String> = changes
+                .iter()
+                .map(|change| match change {
+                    Changes::AddFile(options) | Changes::OverwriteFile(options) => format!("Created {}", options.path),
+                    Changes::AddDirectory(options) => format!("Created directory {}", options.path),
+                    Changes::EditTOML(options) => {
+                        let mut names: Vec<&str> = options.contracts_to_add.keys().map(|name| name.as_str()).collect();
+                        names.sort();
+                        format!("Added {} to {}", names.join(", "), options.path)
+                    }
+                })
+                .collect();
+            if let Err(e) = execute_or_print_changes(changes, init_opts.dry_run) {
+                exit_with(ClarinetError::Config(e));
+            }
+            if init_opts.dry_run {
+                return;
+            }
+            if created.is_empty() {
+                outputln!("Nothing to create, the project is already set up");
+            }
+            for line in created.iter() {
+                outputln!("{}", line);
+            }
         }
         Command::Contract(subcommand) => match subcommand {
             Contract::NewContract(new_contract) => {
-                let changes =
-                    generators::get_changes_for_new_contract(current_path, new_contract.name, None, true, vec![]);
-                execute_changes(changes);
+                if !new_contract.depends_on.is_empty() {
+                    let contracts = match MainConfig::from_path(&manifest_path) {
+                        Ok(config) => config.contracts.unwrap_or_default(),
+                        Err(e) => exit_with(ClarinetError::Config(e)),
+                    };
+                    for dependency in new_contract.depends_on.iter() {
+                        if !contracts.contains_key(dependency) {
+                            exit_with(ClarinetError::Usage(format!("Error: unknown contract {} (--depends-on)", dependency)));
+                        }
+                    }
+                }
+                let source = match new_contract.from {
+                    Some(ref from) => match read_contract_source(Path::new(from)) {
+                        Ok(source) => Some(source),
+                        Err(e) => exit_with(ClarinetError::Usage(e)),
+                    },
+                    None => new_contract
+                        .implemented_trait
+                        .map(|known_trait| known_trait.contract_template(&new_contract.name)),
+                };
+                let skeleton = ContractSkeleton {
+                    getter: new_contract.with_getter,
+                    setter: new_contract.with_setter,
+                };
+                let mut changes =
+                    generators::get_changes_for_new_contract(current_path.clone(), manifest_path.to_string_lossy().to_string(), "contracts".to_string(), new_contract.name, source, skeleton, true, new_contract.depends_on);
+                if new_contract.overwrite {
+                    changes = changes
+                        .into_iter()
+                        .map(|change| match change {
+                            Changes::AddFile(options) => Changes::OverwriteFile(options),
+                            change => change,
+                        })
+                        .collect();
+                }
+                if let Some(known_trait) = new_contract.implemented_trait {
+                    changes.append(&mut generators::get_changes_for_new_link(
+                        manifest_path.to_string_lossy().to_string(),
+                        known_trait.contract_id().to_string(),
+                        None,
+                    ));
+                }
+                if let Err(e) = execute_or_print_changes(changes, new_contract.dry_run) {
+                    exit_with(ClarinetError::Config(e));
+                }
             }
             Contract::LinkContract(link_contract) => {
-                let path = format!("{}/Clarinet.toml", current_path);
+                let path = manifest_path.to_string_lossy().to_string();
 
                 let change = TOMLEdition {
                     comment: format!("Indexing link {} in Clarinet.toml", link_contract.contract_id),
                     path,
                     contracts_to_add: HashMap::new(),
                     links_to_add: vec![LinkConfig {
-                        contract_id: link_contract.contract_id.clone(),
+                        contract_id: link_contract.contract_id.to_string(),
+                        block_height: link_contract.block_height,
+                        name: None,
+                        cache: false,
                     }],
                 };
-                execute_changes(vec![Changes::EditTOML(change)]);
+                if let Err(e) = execute_changes(vec![Changes::EditTOML(change)]) {
+                    exit_with(ClarinetError::Config(e));
+                }
             }
             Contract::ForkContract(fork_contract) => {
-                let path = format!("{}/Clarinet.toml", current_path);
+                let path = manifest_path.to_string_lossy().to_string();
+                let contracts_dir = match fork_contract.output_dir {
+                    Some(ref output_dir) => match project_relative_dir(Path::new(&current_path), Path::new(output_dir)) {
+                        Ok(dir) => dir,
+                        Err(e) => exit_with(ClarinetError::Usage(e)),
+                    },
+                    None => "contracts".to_string(),
+                };
 
-                println!("Resolving {} and its dependencies...", fork_contract.contract_id);
+                info!("Resolving {} and its dependencies...", fork_contract.contract_id);
 
-                let settings = repl::SessionSettings::default();
-                let mut session = repl::Session::new(settings);
+                let retries = RetryPolicy {
+                    attempts: fork_contract.retries,
+                    ..RetryPolicy::default()
+                };
+                let resolution = match resolve_link(Path::new(&current_path), &fork_contract.contract_id.to_string(), &retries) {
+                    Ok(resolution) => resolution,
+                    Err(e) => exit_with(ClarinetError::Failure(e)),
+                };
+                let unresolved = resolution.unresolved;
+                if !unresolved.is_empty() && !fork_contract.allow_partial {
+                    let missing: Vec<String> = unresolved
+                        .iter()
+                        .map(|contract| format!("  {}: {}", contract.contract_id, contract.reason))
+                        .collect();
+                    exit_with(ClarinetError::Failure(format!(
+                        "Error: unable to resolve {} dependencies of {} (use --allow-partial to fork it anyway):\n{}",
+                        unresolved.len(),
+                        fork_contract.contract_id,
+                        missing.join("\n")
+                    )));
+                }
+                let contracts = resolution.contracts;
 
-                let res = session.resolve_link(&repl::settings::InitialLink {
-                    contract_id: fork_contract.contract_id.clone(),
-                    stacks_node_addr: None,
-                    cache: None,
-                });
-                let contracts = res.unwrap();
-                let mut changes = vec![];
-                for (contract_id, code, deps) in contracts.into_iter() {
-                    let components: Vec<&str> = contract_id.split('.').collect();
-                    let contract_name = components.last().unwrap();
+                let mut remaps = vec![];
+                if !fork_contract.remap.is_empty() {
+                    let accounts = match load_chain_settings(&manifest_path, "development") {
+                        Ok(settings) => settings.initial_accounts,
+                        Err(e) => exit_with(ClarinetError::Config(e)),
+                    };
+                    for remap in fork_contract.remap.iter() {
+                        let address = match accounts.iter().find(|account| account.name == remap.to) {
+                            Some(account) => account.address.clone(),
+                            None if PrincipalData::parse_standard_principal(&remap.to).is_ok() => remap.to.clone(),
+                            None => exit_with(ClarinetError::Usage(format!("Error: {} is neither an account of settings/Development.toml nor an address", remap.to))),
+                        };
+                        remaps.push((remap, address));
+                    }
+                }
 
-                    if &contract_id == &fork_contract.contract_id {
-                        let mut change_set =
-                            generators::get_changes_for_new_contract(current_path.clone(), contract_name.to_string(), Some(code), false, vec![]);
-                        changes.append(&mut change_set);
+                let local_contracts = match MainConfig::from_path(&manifest_path) {
+                    Ok(config) => config.contracts.unwrap_or_default(),
+                    Err(e) => exit_with(ClarinetError::Config(e)),
+                };
+
+                let mut changes = vec![];
+                let mut resolution = None;
+                let mut skipped = vec![];
+                for (contract_id, mut code, deps) in contracts.into_iter() {
+                    if contract_id == fork_contract.contract_id.to_string() {
+                        let name = fork_contract.contract_id.name.clone();
+                        let exists = local_contracts.contains_key(&name)
+                            || PathBuf::from(&current_path).join(&contracts_dir).join(format!("{}.clar", name)).exists();
+                        if exists && !fork_contract.force {
+                            info!("Skipping {}: contract {} already exists in the project (use --force to overwrite it)", contract_id, name);
+                            skipped.push(name);
+                            continue;
+                        }
+                        let mut applied_remaps = vec![];
+                        for (remap, address) in remaps.iter() {
+                            let (remapped, count) = remap_principal(&code, &remap.from, address);
+                            info!("Replaced {} occurrence(s) of {} by {} ({})", count, remap.from, address, remap.to);
+                            code = remapped;
+                            applied_remaps.push(serde_json::json!({
+                                "from": remap.from,
+                                "to": address,
+                                "occurrences": count,
+                            }));
+                        }
+                        resolution = Some(serde_json::json!({
+                            "contract_id": contract_id,
+                            "source": code,
+                            "dependencies": deps,
+                            "remaps": applied_remaps,
+                        }));
+                        let change_set =
+                            generators::get_changes_for_new_contract(current_path.clone(), manifest_path.to_string_lossy().to_string(), contracts_dir.clone(), name, Some(code), ContractSkeleton::default(), false, vec![]);
+                        changes.extend(change_set.into_iter().map(|change| match change {
+                            Changes::AddFile(options) if exists => Changes::OverwriteFile(options),
+                            change => change,
+                        }));
 
                         for dep in deps.iter() {
                             let mut change_set =
@@ -196,230 +955,1680 @@ pub fn main() {
                         }
                     }
                 }
-                execute_changes(changes);
-            }
-        },
-        Command::Console(_) => {
-            let start_repl = true;
-            load_session(start_repl, "development".into()).expect("Unable to start REPL");
-        },
-        Command::Check(_) => {
-            let start_repl = false;
-            let res = load_session(start_repl, "development".into());
-            if let Err(e) = res {
-                println!("{}", e);
-                return;
+                if !fork_contract.json {
+                    if let Err(e) = execute_or_print_changes(changes, fork_contract.dry_run) {
+                        exit_with(ClarinetError::Config(e));
+                    }
+                    if !skipped.is_empty() {
+                        outputln!("Skipped existing contract(s): {} (use --force to overwrite)", skipped.join(", "));
+                    }
+                    if !unresolved.is_empty() {
+                        outputln!("Unresolved dependencies:");
+                        for contract in unresolved.iter() {
+                            outputln!("  {}: {}", contract.contract_id, contract.reason);
+                        }
+                    }
+                    return;
+                }
+                let described_changes = describe_changes(&changes);
+                let res = if fork_contract.dry_run { validate_changes(&changes) } else { execute_changes(changes) };
+                if let Err(e) = res {
+                    exit_with(ClarinetError::Config(e));
+                }
+                let mut output = resolution.unwrap_or_default();
+                output["changes"] = serde_json::Value::Array(described_changes);
+                output["applied"] = serde_json::Value::Bool(!fork_contract.dry_run);
+                output["skipped"] = serde_json::json!(skipped);
+                output["unresolved"] = serde_json::json!(unresolved);
+                outputln!("{}", serde_json::to_string_pretty(&output).unwrap());
             }
-        },
-        Command::Test(test) => {
-            let start_repl = false;
-            let res = load_session(start_repl, "development".into());
-            if let Err(e) = res {
-                println!("{}", e);
-                return;
+            Contract::ListContracts(_) => {
+                let project_config = match MainConfig::from_path(&manifest_path) {
+                    Ok(config) => config,
+                    Err(e) => exit_with(ClarinetError::Config(e)),
+                };
+                let contracts = match project_config.ordered_contracts() {
+                    Ok(contracts) => contracts,
+                    Err(e) => exit_with(ClarinetError::Config(e)),
+                };
+                for (name, config) in contracts.iter() {
+                    match config.description {
+                        Some(ref description) => outputln!("{} ({}): {}", name, config.path, description),
+                        None => outputln!("{} ({})", name, config.path),
+                    }
+                }
             }
-            run_tests(test.files);
-        },
-        Command::Deploy(deploy) => {
-            let start_repl = false;
-            let mode = if deploy.mocknet == true {
-                "mocknet"
-            } else if deploy.testnet == true {
-                "testnet"
-            } else {
-                panic!("Target deployment must be specified with --mocknet or --testnet")
-            };
-            let res = load_session(start_repl, mode.into());
-            if let Err(e) = res {
-                println!("{}", e);
-                return;
+            Contract::ContractDeps(contract_deps) => {
+                let project_config = match MainConfig::from_path(&manifest_path) {
+                    Ok(config) => config,
+                    Err(e) => exit_with(ClarinetError::Config(e)),
+                };
+                if contract_deps.missing {
+                    let settings = match load_session_settings(&manifest_path, env_network().unwrap_or_default().env_name()) {
+                        Ok(settings) => settings,
+                        Err(e) => exit_with(ClarinetError::Config(e)),
+                    };
+                    let missing = match find_missing_references(&settings) {
+                        Ok(missing) => missing,
+                        Err(e) => exit_with(ClarinetError::Config(e)),
+                    };
+                    if contract_deps.json {
+                        outputln!("{}", serde_json::to_string_pretty(&missing).unwrap());
+                    } else if missing.is_empty() {
+                        outputln!("No missing references");
+                    } else {
+                        for (contract, references) in missing.iter() {
+                            outputln!("{}:", contract);
+                            for reference in references.iter() {
+                                outputln!("  {}", reference);
+                            }
+                        }
+                    }
+                    if !missing.is_empty() {
+                        process::exit(EXIT_FAILURE);
+                    }
+                    return;
+                }
+                let name = contract_deps.name.unwrap_or_default();
+                let contracts = match project_config.dependencies_of(&name, contract_deps.reverse) {
+                    Ok(contracts) => contracts,
+                    Err(e) => exit_with(ClarinetError::Config(e)),
+                };
+                if contract_deps.json {
+                    outputln!("{}", serde_json::to_string_pretty(&contracts).unwrap());
+                } else {
+                    for contract in contracts.iter() {
+                        outputln!("{}", contract);
+                    }
+                }
             }
-            let settings = res.unwrap();
-
-            let mut deployers_nonces = BTreeMap::new();
-            let mut deployers_lookup = BTreeMap::new();
-            for account in settings.initial_accounts.iter() {
-                if account.name == "deployer" {
-                    deployers_lookup.insert("*", account.clone());
+            Contract::ContractWhy(contract_why) => {
+                let project_config = match MainConfig::from_path(&manifest_path) {
+                    Ok(config) => config,
+                    Err(e) => exit_with(ClarinetError::Config(e)),
+                };
+                let (from, to) = (&contract_why.from, &contract_why.to);
+                if from == to {
+                    exit_with(ClarinetError::Usage("Error: expected two different contracts".to_string()));
+                }
+                let path = project_config
+                    .dependency_path(from, to)
+                    .and_then(|path| match path {
+                        Some(path) => Ok(Some(path)),
+                        None => project_config.dependency_path(to, from),
+                    });
+                match path {
+                    Ok(Some(path)) => {
+                        outputln!("{} depends on {}:", path[0], path[path.len() - 1]);
+                        outputln!("  {}", path.join(" -> "));
+                    }
+                    Ok(None) => outputln!("{} and {} are unrelated: neither depends on the other", from, to),
+                    Err(e) => exit_with(ClarinetError::Usage(e)),
                 }
             }
-
-            #[derive(Deserialize, Debug)]
-            struct Balance {
-                balance: String,
-                nonce: u64,
-                balance_proof: String,
-                nonce_proof: String,               
+            Contract::ContractOrder(contract_order) => {
+                let mut names: Vec<String> = match MainConfig::from_path(&manifest_path).and_then(|config| config.ordered_contracts()) {
+                    Ok(contracts) => contracts.into_iter().map(|(name, _)| name).collect(),
+                    Err(e) => exit_with(ClarinetError::Config(e)),
+                };
+                if contract_order.reverse {
+                    names.reverse();
+                }
+                for name in names.iter() {
+                    outputln!("{}", name);
+                }
             }
-
-            for initial_contract in settings.initial_contracts.iter() {
-                let contract_name = initial_contract.name.clone().unwrap();
-                let host = "http://localhost:20443";
-
-                let payload = TransactionSmartContract {
-                    name: contract_name.as_str().into(),
-                    code_body: StacksString::from_string(&initial_contract.code).unwrap()
+            Contract::ContractHash(contract_hash) => {
+                let project_config = match MainConfig::from_path(&manifest_path) {
+                    Ok(config) => config,
+                    Err(e) => exit_with(ClarinetError::Config(e)),
                 };
-
-                let deployer = match deployers_lookup.get(contract_name.as_str()) {
-                    Some(deployer) => deployer,
-                    None => deployers_lookup.get("*").unwrap()
+                let contracts = project_config.contracts.unwrap_or_default();
+                let names: Vec<&String> = match contract_hash.name {
+                    Some(ref name) if contracts.contains_key(name) => vec![name],
+                    Some(ref name) => exit_with(ClarinetError::Usage(format!("Error: unknown contract {}", name))),
+                    None => contracts.keys().collect(),
                 };
-
-                let bip39_seed = match mnemonic::get_bip39_seed_from_mnemonic(&deployer.mnemonic, "") {
-                    Ok(bip39_seed) => bip39_seed,
-                    Err(_) => panic!(),
+                let mut hashes = BTreeMap::new();
+                for name in names.into_iter() {
+                    let path = PathBuf::from(&current_path).join(&contracts[name].path);
+                    match fs::read_to_string(&path) {
+                        Ok(code) => hashes.insert(name.clone(), normalized_source_hash(&code)),
+                        Err(e) => exit_with(ClarinetError::Config(format!("Error: unable to read {}: {}", path.display(), e))),
+                    };
+                }
+                if contract_hash.json {
+                    outputln!("{}", serde_json::to_string_pretty(&hashes).unwrap());
+                } else if contract_hash.all {
+                    for (name, hash) in hashes.iter() {
+                        outputln!("{}  {}", hash, name);
+                    }
+                } else {
+                    for hash in hashes.values() {
+                        outputln!("{}", hash);
+                    }
+                }
+            }
+            Contract::ContractInterface(contract_interface) => {
+                let settings = match load_session_settings(&manifest_path, contract_interface.network.or_else(env_network).unwrap_or_default().env_name()) {
+                    Ok(settings) => settings,
+                    Err(e) => exit_with(ClarinetError::Config(e)),
                 };
-                let ext = ExtendedPrivKey::derive(&bip39_seed[..], deployer.derivation.as_str()).unwrap();
-                let secret_key = SecretKey::parse_slice(&ext.secret()).unwrap();
-                let public_key = PublicKey::from_secret_key(&secret_key);
-                
-                let wrapped_public_key = Secp256k1PublicKey::from_slice(&public_key.serialize_compressed()).unwrap();
-                let wrapped_secret_key = Secp256k1PrivateKey::from_slice(&ext.secret()).unwrap();
-
-                let anchor_mode = TransactionAnchorMode::Any;
-                let tx_fee = 200 + initial_contract.code.len() as u64;
-
-                let nonce = match deployers_nonces.get(&deployer.name) {
-                    Some(nonce) => {
-                        *nonce
-                    },
+                let mut interfaces = match build_contract_interfaces(&settings) {
+                    Ok(interfaces) => interfaces,
+                    Err(e) => exit_with(ClarinetError::Failure(e)),
+                };
+                if let Some(ref name) = contract_interface.name {
+                    interfaces = match interfaces.remove(name) {
+                        Some(interface) => vec![(name.clone(), interface)].into_iter().collect(),
+                        None => exit_with(ClarinetError::Usage(format!("Error: unknown contract {}", name))),
+                    };
+                }
+                match contract_interface.out_dir {
+                    Some(ref out_dir) => {
+                        let out_dir = Path::new(out_dir);
+                        if let Err(e) = fs::create_dir_all(out_dir) {
+                            exit_with(ClarinetError::Failure(format!("Error: unable to create {}: {}", out_dir.display(), e)));
+                        }
+                        for (name, interface) in interfaces.iter() {
+                            let path = out_dir.join(format!("{}.json", name));
+                            if let Err(e) = fs::write(&path, serde_json::to_string_pretty(interface).unwrap()) {
+                                exit_with(ClarinetError::Failure(format!("Error: unable to write {}: {}", path.display(), e)));
+                            }
+                            outputln!("Wrote {}", path.display());
+                        }
+                    }
+                    None if contract_interface.all => outputln!("{}", serde_json::to_string_pretty(&interfaces).unwrap()),
                     None => {
-                        let request_url = format!(
-                            "{host}/v2/accounts/{addr}",
-                            host = host,
-                            addr = deployer.address,
-                        );
-                
-                        let response: Balance = reqwest::blocking::get(&request_url)
-                            .expect("Unable to retrieve account")
-                            .json()
-                            .expect("Unable to parse contract");
-                        let nonce = response.nonce;
-                        deployers_nonces.insert(deployer.name.clone(), nonce);
-                        nonce
+                        for interface in interfaces.values() {
+                            outputln!("{}", serde_json::to_string_pretty(interface).unwrap());
+                        }
                     }
+                }
+            }
+            Contract::ContractAnalyze(contract_analyze) => {
+                if !contract_analyze.traits {
+                    exit_with(ClarinetError::Usage("Error: nothing to analyze, pass --traits".to_string()));
+                }
+                let settings = match load_session_settings(&manifest_path, contract_analyze.network.or_else(env_network).unwrap_or_default().env_name()) {
+                    Ok(settings) => settings,
+                    Err(e) => exit_with(ClarinetError::Config(e)),
                 };
-
-                let signer_addr = StacksAddress::from_public_keys(0, &AddressHashMode::SerializeP2PKH, 1, &vec![wrapped_public_key]).unwrap();
-        
-                let spending_condition = TransactionSpendingCondition::Singlesig(
-                    SinglesigSpendingCondition {
-                        signer: signer_addr.bytes.clone(),
-                        nonce: nonce,
-                        tx_fee: tx_fee,
-                        hash_mode: SinglesigHashMode::P2PKH,
-                        key_encoding: TransactionPublicKeyEncoding::Compressed,
-                        signature: RecoverableSignature::empty(),
-                    },
-                );
-
-                let auth = TransactionAuth::Standard(spending_condition);
-                let unsigned_tx = StacksTransaction {
-                    version: TransactionVersion::Testnet,
-                    chain_id: 0x80000000, // MAINNET=0x00000001
-                    auth: auth,
-                    anchor_mode: anchor_mode,
-                    post_condition_mode: TransactionPostConditionMode::Deny,
-                    post_conditions: vec![],
-                    payload: TransactionPayload::SmartContract(payload),
+                let mut reports = match analyze_contract_traits(&settings) {
+                    Ok(reports) => reports,
+                    Err(e) => exit_with(ClarinetError::Failure(e)),
                 };
-            
-                let mut unsigned_tx_bytes = vec![];
-                unsigned_tx
-                    .consensus_serialize(&mut unsigned_tx_bytes)
-                    .expect("FATAL: invalid transaction");
-
-                let mut tx_signer = StacksTransactionSigner::new(&unsigned_tx);
-                tx_signer.sign_origin(&wrapped_secret_key).unwrap();
-                let signed_tx = tx_signer.get_tx().unwrap();
-
-                let tx_bytes = signed_tx.serialize_to_vec();
-                let client = reqwest::blocking::Client::new();
-                let path = format!("{}/v2/transactions", "http://localhost:20443");
-                let res = client
-                    .post(&path)
-                    .header("Content-Type", "application/octet-stream")
-                    .body(tx_bytes)
-                    .send()
-                    .unwrap();
-        
-                if !res.status().is_success() {
-                    println!("{}", res.text().unwrap());
-                    panic!()
-                }        
-                let txid: String = res.json().unwrap();
-
-                println!("Deploying {} (txid: {}, nonce: {})", contract_name, txid, nonce);
-                deployers_nonces.insert(deployer.name.clone(), nonce + 1);
-            }
-
-            // If mocknet, we should be pulling all the links.
-            // Get ordered list of contracts
-            // For each contract, get the nonce of the account deploying (if unknown)
-            // Create a StacksTransaction with the contract, the name.
-            // Sign the transaction
-            // Send the transaction
-        }
-    };
-}
-  
-fn execute_changes(changes: Vec<Changes>) {
-    for mut change in changes.into_iter() {
-        match change {
-            Changes::AddFile(options) => {
-                println!("{}", options.comment);
-                let mut file = File::create(options.path.clone()).expect("Unable to create file");
-                file.write_all(options.content.as_bytes())
-                    .expect("Unable to write file");
+                if let Some(ref name) = contract_analyze.name {
+                    reports.retain(|report| &report.contract == name);
+                    if reports.is_empty() {
+                        exit_with(ClarinetError::Usage(format!("Error: unknown contract {}", name)));
+                    }
+                }
+                if contract_analyze.json {
+                    outputln!("{}", serde_json::to_string_pretty(&reports).unwrap());
+                } else {
+                    print_contract_traits(&reports);
+                }
+                if !reports.iter().all(|report| report.is_compliant()) {
+                    process::exit(EXIT_FAILURE);
+                }
             }
-            Changes::AddDirectory(options) => {
-                println!("{}", options.comment);
-                fs::create_dir_all(options.path.clone()).expect("Unable to create directory");
-            }
-            Changes::EditTOML(ref mut options) => {
-                let file = File::open(options.path.clone()).unwrap();
-                let mut config_file_reader = BufReader::new(file);
-                let mut config_file = vec![];
-                config_file_reader.read_to_end(&mut config_file).unwrap();
-                let config_file: MainConfigFile = toml::from_slice(&config_file[..]).unwrap();
-                let mut config: MainConfig = MainConfig::from_config_file(config_file);
-                let mut dirty = false;
-                println!("BEFORE: {:?}", config);
-
-                let mut links = match config.links.take() {
-                    Some(links) => links,
+        },
+        Command::Console(console) => {
+            if console.no_color {
+                colors::disable_color();
+            }
+            let Console { seed, seed_accounts, seed_balance, watch, network, eval_file, halt_on_error, on_chain, node_url, .. } = console;
+            let network = network.or_else(env_network);
+            let env = network.unwrap_or_default().env_name();
+            let chain_node_url = if on_chain {
+                match resolve_node_url(node_url.as_deref(), &profile, &manifest_path, Some(network.unwrap_or_default())) {
+                    Ok(node_url) => Some(node_url),
+                    Err(e) => exit_with(ClarinetError::Config(e)),
+                }
+            } else {
+                None
+            };
+            let loader = move || {
+                let mut settings = load_session_settings(&manifest_path, env)?;
+                if let Some(ref seed) = seed {
+                    let network = load_network_config(&manifest_path, env)?;
+                    append_seeded_accounts(&mut settings, &network, seed, seed_accounts, seed_balance)?;
+                }
+                Ok(settings)
+            };
+            let settings = match loader() {
+                Ok(settings) => settings,
+                Err(e) => exit_with(ClarinetError::Config(e)),
+            };
+            if let Some(eval_file) = eval_file {
+                let script = match fs::read_to_string(&eval_file) {
+                    Ok(script) => script,
+                    Err(e) => exit_with(ClarinetError::Usage(format!("Error: unable to read {}: {}", eval_file, e))),
+                };
+                match eval_script(settings, &script, halt_on_error) {
+                    Ok(0) => {}
+                    Ok(_) => process::exit(EXIT_FAILURE),
+                    Err(e) => exit_with(ClarinetError::Failure(e)),
+                }
+                return;
+            }
+            let chain = chain_node_url.map(|node_url| {
+                let deployer = settings.initial_deployer.as_ref().map(|deployer| deployer.address.clone());
+                match ChainReader::new(&node_url, deployer) {
+                    Ok(reader) => reader,
+                    Err(e) => exit_with(ClarinetError::Failure(e)),
+                }
+            });
+            start_console(settings, Box::new(loader), watch, chain);
+        },
+        Command::Check(check) => {
+            if check.no_color {
+                colors::disable_color();
+            }
+            if check.watch {
+                let names: Vec<String> = match check.contracts {
+                    Some(ref contracts) => contracts.split(',').map(|name| name.trim().to_string()).filter(|name| !name.is_empty()).collect(),
                     None => vec![],
                 };
-                for link in options.links_to_add.drain(..) {
-                    if links.contains(&link) {
-                        links.push(link);
-                        dirty = true;
+                let load = || -> Result<repl::SessionSettings, String> {
+                    let mut settings = load_session_settings(&manifest_path, check.network.or_else(env_network).unwrap_or_default().env_name())?;
+                    if check.no_boot_contracts {
+                        settings.include_boot_contracts = false;
                     }
-                }
-                config.links = Some(links);
-
-                let mut contracts = match config.contracts.take() {
-                    Some(contracts) => contracts,
-                    None => BTreeMap::new(),
+                    if !names.is_empty() {
+                        select_contracts(&mut settings, &manifest_path, &names)?;
+                    }
+                    Ok(settings)
                 };
-                for (contract_name, contract_config) in options.contracts_to_add.iter() {
-                    let res = contracts.insert(contract_name.clone(), contract_config.clone());
-                    if res.is_none() {
-                        dirty = true;
+                watch_contracts(load, check.max_errors, check.clear, check.verbose);
+                return;
+            }
+            let mut settings = match load_session_settings(&manifest_path, check.network.or_else(env_network).unwrap_or_default().env_name()) {
+                Ok(settings) => settings,
+                Err(e) => exit_with(ClarinetError::Config(e)),
+            };
+            if check.no_boot_contracts {
+                settings.include_boot_contracts = false;
+            }
+            if let Some(ref contracts) = check.contracts {
+                let names: Vec<String> = contracts.split(',').map(|name| name.trim().to_string()).filter(|name| !name.is_empty()).collect();
+                if let Err(e) = select_contracts(&mut settings, &manifest_path, &names) {
+                    exit_with(ClarinetError::Usage(e));
+                }
+            }
+            if check.snippet {
+                let mut code = String::new();
+                if let Err(e) = std::io::stdin().read_to_string(&mut code) {
+                    exit_with(ClarinetError::Usage(format!("Error: unable to read the snippet from stdin: {}", e)));
+                }
+                let mut name = "snippet".to_string();
+                let mut index = 1;
+                while settings.initial_contracts.iter().any(|c| c.name.as_deref() == Some(name.as_str())) {
+                    index += 1;
+                    name = format!("snippet-{}", index);
+                }
+                let deployer = settings.initial_deployer.as_ref().map(|deployer| deployer.address.clone());
+                settings.initial_contracts.push(repl::settings::InitialContract {
+                    code,
+                    name: Some(name),
+                    deployer,
+                });
+            }
+            if check.diff || check.update_baseline {
+                let baseline_path = PathBuf::from(&current_path).join(DEFAULT_CACHE_DIR).join("diagnostics-baseline.json");
+                match check_against_baseline(&settings, &baseline_path, check.diff, check.update_baseline) {
+                    Ok(added) if added > 0 => process::exit(EXIT_FAILURE),
+                    Ok(_) => {}
+                    Err(e) => exit_with(ClarinetError::Config(e)),
+                }
+                return;
+            }
+            match collect_diagnostics(&settings) {
+                Ok(diagnostics) if diagnostics.is_empty() => {}
+                Ok(diagnostics) => {
+                    print_diagnostics(&diagnostics, &settings, check.max_errors);
+                    process::exit(EXIT_FAILURE);
+                }
+                Err(e) => exit_with(ClarinetError::Failure(e)),
+            }
+            if check.warn_unused_links {
+                let unused = MainConfig::from_path(&manifest_path)
+                    .and_then(|config| find_unused_links(&config, Path::new(&current_path)));
+                match unused {
+                    Ok(unused) => {
+                        for contract_id in unused.iter() {
+                            outputln!("Warning: link {} is not referenced by any contract", contract_id);
+                        }
                     }
+                    Err(e) => exit_with(ClarinetError::Config(e)),
                 }
-                config.contracts = Some(contracts);
-
-                println!("AFTER: {:?}", config);
-
-                if dirty {
-                    let toml = toml::to_string(&config).unwrap();
-                    let mut file = File::create(options.path.clone()).unwrap();
-                    file.write_all(&toml.as_bytes()).unwrap();    
-                } 
-                println!("{}", options.comment);
             }
+            match Lockfile::from_path(&manifest_path.with_extension("lock")) {
+                Ok(lockfile) => {
+                    for contract in settings.initial_contracts.iter() {
+                        let name = contract.name.clone().unwrap_or_default();
+                        for environment in lockfile.drifted_environments(&name, &contract.code).iter() {
+                            warn!("contract {} differs from the version deployed on {} (Clarinet.lock)", name, environment);
+                        }
+                    }
+                }
+                Err(e) => exit_with(ClarinetError::Config(e)),
+            }
+            if check.costs {
+                let mut reports = vec![];
+                for contract in settings.initial_contracts.iter() {
+                    let name = contract.name.clone().unwrap_or_default();
+                    match estimate_contract_costs(&name, &contract.code) {
+                        Ok(report) => reports.push(report),
+                        Err(e) => exit_with(ClarinetError::Failure(e)),
+                    }
+                }
+                if check.json {
+                    outputln!("{}", serde_json::to_string_pretty(&reports).unwrap());
+                } else {
+                    print_costs(&reports);
+                }
+            }
+            if check.metrics {
+                let mut reports = vec![];
+                for contract in settings.initial_contracts.iter() {
+                    let name = contract.name.clone().unwrap_or_default();
+                    match count_contract_definitions(&name, &contract.code) {
+                        Ok(report) => reports.push(report),
+                        Err(e) => exit_with(ClarinetError::Failure(e)),
+                    }
+                }
+                if check.json {
+                    outputln!("{}", serde_json::to_string_pretty(&reports).unwrap());
+                } else {
+                    print_metrics(&reports);
+                }
+            }
+        },
+        Command::Test(test) => {
+            let start_repl = false;
+            let env = match (&test.env, test.network) {
+                (Some(env), _) => env.clone(),
+                (None, Some(network)) => network.env_name().to_string(),
+                (None, None) => env_network().unwrap_or_default().env_name().to_string(),
+            };
+            let mut environments = vec![];
+            for name in env.split(',').map(|e| e.trim()).filter(|e| !e.is_empty()) {
+                match name.parse::<Network>() {
+                    Ok(network) => environments.push(network.env_name()),
+                    Err(e) => exit_with(ClarinetError::Usage(format!("Error: {} (--env)", e))),
+                }
+            }
+            let files = if test.files.is_empty() {
+                let test_dir = match MainConfig::from_path(&manifest_path) {
+                    Ok(config) => PathBuf::from(&current_path).join(config.project.test_dir()),
+                    Err(e) => exit_with(ClarinetError::Config(e)),
+                };
+                match discover_test_files(&test_dir) {
+                    Ok(files) if files.is_empty() => {
+                        outputln!("No test files found in {}", test_dir.display());
+                        return;
+                    }
+                    Ok(files) => files,
+                    Err(e) => exit_with(ClarinetError::Config(format!("Error: unable to search {} for test files: {}", test_dir.display(), e))),
+                }
+            } else {
+                test.files.clone()
+            };
+            if test.seed_contracts {
+                let timeout = Duration::from_secs(test.seed_timeout);
+                let res = resolve_node_url(None, &profile, &manifest_path, Some(Network::Mocknet))
+                    .and_then(|node_url| seed_contracts(&manifest_path, &node_url, timeout));
+                if let Err(e) = res {
+                    exit_with(ClarinetError::Deploy(format!("{}\nDeployment failed, tests not run", e)));
+                }
+            }
+            let mut reports = vec![];
+            for env in environments.iter() {
+                if environments.len() > 1 {
+                    info!("Running tests against {} environment", env);
+                }
+                let mut settings = match load_session(&manifest_path, start_repl, env.to_string()) {
+                    Ok(settings) => settings,
+                    Err(e) => exit_with(ClarinetError::Config(e)),
+                };
+                if let Some(ref seed) = test.seed {
+                    let res = load_network_config(&manifest_path, env)
+                        .and_then(|network| append_seeded_accounts(&mut settings, &network, seed, test.seed_accounts, test.seed_balance));
+                    if let Err(e) = res {
+                        exit_with(ClarinetError::Config(e));
+                    }
+                }
+                let results = match run_tests(files.clone(), settings, test.runtime_args.clone(), test.reporter == "tap") {
+                    Ok(results) => results,
+                    Err(e) => exit_with(ClarinetError::Failure(e.to_string())),
+                };
+                reports.push((env, results));
+            }
+
+            for (env, results) in reports.iter() {
+                if results.used_only {
+                    warn!("Test cases marked `only` in {} environment, the other test cases were not run", env);
+                }
+            }
+            let success = reports.iter().all(|(_, results)| results.success());
+            if test.reporter == "tap" {
+                print_tap_report(&reports);
+                if !success {
+                    process::exit(EXIT_FAILURE);
+                }
+                return;
+            }
+            if reports.len() > 1 {
+                outputln!("\nTest results by environment:");
+                for (env, results) in reports.iter() {
+                    outputln!(
+                        "  {}: {}. {} passed; {} failed; {} ignored",
+                        env,
+                        if results.success() { "ok" } else { "FAILED" },
+                        results.passed,
+                        results.failed,
+                        results.ignored
+                    );
+                }
+                outputln!("Overall: {}", if success { "ok" } else { "FAILED" });
+            }
+            print_test_durations(&reports, test.show_slowest);
+            if !success {
+                process::exit(EXIT_FAILURE);
+            }
+        },
+        Command::Deploy(deploy) => {
+            let node_url = match resolve_node_url(deploy.node_url.as_deref(), &profile, &manifest_path, deploy.target()) {
+                Ok(node_url) => node_url,
+                Err(e) => exit_with(ClarinetError::Config(e)),
+            };
+            if let Some(ref signed_in) = deploy.signed_in {
+                let options = PublishOptions {
+                    node_url: node_url.clone(),
+                    request_timeout: Duration::from_secs(deploy.request_timeout),
+                    ..PublishOptions::default()
+                };
+                let mut report = DeploymentReport::default();
+                let res = broadcast_signed_transactions(Path::new(signed_in), &options, &mut report);
+                print_deployment_report(&report, deploy.json);
+                match deploy.target() {
+                    Some(network) => {
+                        update_lockfile(&manifest_path, network.env_name(), &report.contracts, &options)
+                    }
+                    None if !report.contracts.is_empty() => {
+                        warn!("Clarinet.lock not updated: pass --network to record the deployment")
+                    }
+                    None => {}
+                }
+                if let Err(e) = res {
+                    exit_with(ClarinetError::Deploy(e));
+                }
+                return;
+            }
+            if let Some(ref bundle) = deploy.broadcast_bundle {
+                let options = PublishOptions {
+                    node_url: node_url.clone(),
+                    request_timeout: Duration::from_secs(deploy.request_timeout),
+                    ..PublishOptions::default()
+                };
+                let mut report = DeploymentReport::default();
+                let res = broadcast_bundle(Path::new(bundle), &options, &mut report);
+                print_deployment_report(&report, deploy.json);
+                let environment = fs::read_to_string(bundle)
+                    .ok()
+                    .and_then(|content| serde_json::from_str::<DeploymentBundle>(&content).ok())
+                    .map(|bundle| bundle.environment);
+                if let Some(environment) = environment {
+                    update_lockfile(&manifest_path, &environment, &report.contracts, &options);
+                }
+                if let Err(e) = res {
+                    exit_with(ClarinetError::Deploy(e));
+                }
+                return;
+            }
+            let network = match deploy.target() {
+                Some(network) => network,
+                None => exit_with(ClarinetError::Usage("Error: target deployment must be specified with --network, --mocknet or --testnet".to_string())),
+            };
+            let mode = network.env_name();
+            let res = match deploy.contract {
+                Some(ref contract_path) => load_single_contract_settings(&manifest_path, mode, contract_path, deploy.name.clone()),
+                None => load_session_settings(&manifest_path, mode),
+            };
+            if let Err(e) = res {
+                exit_with(ClarinetError::Config(e));
+            }
+            let mut settings = res.unwrap();
+
+            if deploy.simulate {
+                // Contracts are deployed with their top level expressions
+                // evaluated, so runtime failures are reported along with the
+                // analysis errors.
+                let diagnostics = match collect_diagnostics(&settings) {
+                    Ok(diagnostics) => diagnostics,
+                    Err(e) => exit_with(ClarinetError::Failure(e)),
+                };
+                for contract in settings.initial_contracts.iter() {
+                    let name = contract.name.clone().unwrap_or_default();
+                    match diagnostics.iter().find(|diagnostic| diagnostic.contract == name) {
+                        Some(diagnostic) => outputln!("{} {}: {}", colors::red("fail"), name, diagnostic.message),
+                        None => outputln!("{} {}", colors::green("ok"), name),
+                    }
+                }
+                if !diagnostics.is_empty() {
+                    exit_with(ClarinetError::Failure(format!(
+                        "Error: {} contract(s) failed to instantiate",
+                        diagnostics.len()
+                    )));
+                }
+                return;
+            }
+
+            if !deploy.skip_check {
+                match collect_diagnostics(&settings) {
+                    Ok(diagnostics) if diagnostics.is_empty() => {}
+                    Ok(diagnostics) => {
+                        for diagnostic in diagnostics.iter() {
+                            outputln!("Error: contract {} failed to analyze: {}", diagnostic.contract, diagnostic.message);
+                        }
+                        exit_with(ClarinetError::Failure(
+                            "Deployment aborted, nothing was broadcasted (use --skip-check to deploy anyway)".to_string(),
+                        ));
+                    }
+                    Err(e) => exit_with(ClarinetError::Failure(e)),
+                }
+            }
+
+            // Interfaces are computed while the links are still loaded.
+            let interfaces = match deploy.export_manifest {
+                Some(_) => match build_contract_interfaces(&settings) {
+                    Ok(interfaces) => interfaces,
+                    Err(e) => exit_with(ClarinetError::Failure(e)),
+                },
+                None => BTreeMap::new(),
+            };
+
+            // Links pinned to a block height, or cached, are loaded as
+            // contracts for the checks above, but are already on chain.
+            let deployer_address = settings.initial_deployer.as_ref().map(|deployer| deployer.address.clone());
+            settings.initial_contracts.retain(|contract| contract.deployer == deployer_address);
+            if settings.initial_contracts.is_empty() {
+                outputln!("Nothing to deploy");
+                return;
+            }
+
+            if let Some(ref tag) = deploy.tag {
+                let patterns: Vec<String> = tag.split(',').map(|t| t.trim().to_string()).filter(|t| !t.is_empty()).collect();
+                let project_config = match MainConfig::from_path(&manifest_path) {
+                    Ok(config) => config,
+                    Err(e) => exit_with(ClarinetError::Config(e)),
+                };
+                let contracts = project_config.contracts.unwrap_or_default();
+                settings.initial_contracts.retain(|contract| {
+                    match contract.name.as_ref().and_then(|name| contracts.get(name)) {
+                        Some(config) => config.has_tag_matching(&patterns),
+                        None => false,
+                    }
+                });
+                if settings.initial_contracts.is_empty() {
+                    exit_with(ClarinetError::Usage(format!("Error: no contract tagged {}", tag)));
+                }
+            }
+
+            let tx_version = match deploy.tx_version.as_deref() {
+                Some("mainnet") => Some(TransactionVersion::Mainnet),
+                Some(_) => Some(TransactionVersion::Testnet),
+                None => None,
+            };
+            if let Some(version) = tx_version {
+                if (version == TransactionVersion::Mainnet) != network.is_mainnet() {
+                    warn!("Building {:?} transactions for {}, whose nodes will reject them", version, network);
+                }
+            }
+            // The fee settings of the profile only apply when none is given on the command line.
+            let (strategy, fee) = if deploy.fee_strategy.is_some() || deploy.fee.is_some() {
+                (deploy.fee_strategy.as_deref(), deploy.fee)
+            } else {
+                (profile.fee_strategy.as_deref(), profile.fee)
+            };
+            if deploy.confirmations == 0 || deploy.poll_interval == 0 {
+                exit_with(ClarinetError::Usage("Error: --confirmations and --poll-interval must be at least 1".to_string()));
+            }
+            let fee_strategy = match FeeStrategy::from_options(strategy.unwrap_or("fixed"), fee) {
+                Ok(fee_strategy) => fee_strategy,
+                Err(e) => exit_with(ClarinetError::Usage(e)),
+            };
+            // post_deploy calls and fundings can't be signed ahead of the deployment.
+            let exporting = deploy.unsigned_out.is_some() || deploy.export_bundle.is_some();
+            let post_deploy = match deploy.contract {
+                Some(_) => BTreeMap::new(),
+                None => match post_deploy_calls(&manifest_path) {
+                    Ok(calls) if exporting && !calls.is_empty() => {
+                        warn!("post_deploy calls are not exported, send them once the contracts are deployed");
+                        BTreeMap::new()
+                    }
+                    Ok(calls) => calls,
+                    Err(e) => exit_with(ClarinetError::Config(e)),
+                },
+            };
+            let initial_funding = match deploy.contract {
+                Some(_) => BTreeMap::new(),
+                None => match initial_fundings(&manifest_path) {
+                    Ok(fundings) if exporting && !fundings.is_empty() => {
+                        warn!("initial_funding transfers are not exported, send them once the contracts are deployed");
+                        BTreeMap::new()
+                    }
+                    Ok(fundings) => fundings,
+                    Err(e) => exit_with(ClarinetError::Config(e)),
+                },
+            };
+            let deploy_stages = match deploy.contract {
+                Some(_) => BTreeMap::new(),
+                None => match MainConfig::from_path(&manifest_path) {
+                    Ok(config) => config.deploy_stages(),
+                    Err(e) => exit_with(ClarinetError::Config(e)),
+                },
+            };
+            let mut options = PublishOptions {
+                node_url,
+                request_timeout: Duration::from_secs(deploy.request_timeout),
+                fee_strategy,
+                sponsor: deploy.sponsor.clone(),
+                deployer: deploy.deployer.clone().or(profile.deployer),
+                start_nonce: deploy.nonce,
+                network,
+                tx_version,
+                post_deploy,
+                initial_funding,
+                poll_interval: Duration::from_secs(deploy.poll_interval),
+                confirmations: deploy.confirmations,
+                minify: deploy.minify,
+                deploy_stages,
+            };
+            if deploy.interactive && options.deployer.is_none() && !deploy.yes {
+                match pick_deployer(&settings, &options) {
+                    Ok(name) => options.deployer = Some(name),
+                    Err(e) => exit_with(ClarinetError::Usage(e)),
+                }
+            }
+            if let Err(e) = deployer_account(&settings, &options) {
+                exit_with(ClarinetError::Config(e));
+            }
+            let res = match (&deploy.unsigned_out, &deploy.export_bundle, &deploy.emit_plan) {
+                (Some(dir), _, _) => export_unsigned_transactions(&settings, &options, Path::new(dir)),
+                (None, None, Some(file)) => {
+                    let (project, contract_paths) = match deploy.contract {
+                        Some(ref contract_path) => {
+                            let name = settings.initial_contracts[0].name.clone().unwrap_or_default();
+                            let mut contract_paths = BTreeMap::new();
+                            if contract_path != "-" {
+                                contract_paths.insert(name, contract_path.clone());
+                            }
+                            (None, contract_paths)
+                        }
+                        None => match MainConfig::from_path(&manifest_path) {
+                            Ok(config) => {
+                                let contract_paths = config
+                                    .contracts
+                                    .unwrap_or_default()
+                                    .into_iter()
+                                    .map(|(name, contract)| (name, contract.path))
+                                    .collect();
+                                (Some(config.project.name), contract_paths)
+                            }
+                            Err(e) => exit_with(ClarinetError::Config(e)),
+                        },
+                    };
+                    export_plan(&settings, &options, project, mode, &contract_paths, Path::new(file))
+                }
+                (None, Some(file), _) => {
+                    let project = match deploy.contract {
+                        Some(_) => None,
+                        None => MainConfig::from_path(&manifest_path)
+                            .ok()
+                            .map(|config| config.project.name),
+                    };
+                    export_bundle(&settings, &options, project, mode, Path::new(file))
+                }
+                (None, None, None) => {
+                    let mut report = DeploymentReport::default();
+                    let res = publish_contracts(&settings, &options, &mut report);
+                    print_deployment_report(&report, deploy.json);
+                    if deploy.contract.is_none() {
+                        update_lockfile(&manifest_path, mode, &report.contracts, &options);
+                    }
+                    if let Some(ref file) = deploy.export_manifest {
+                        if let Err(e) = export_explorer_manifest(&settings, &report.contracts, &interfaces, Path::new(file)) {
+                            error!("{}", e);
+                        }
+                    }
+                    match res {
+                        Ok(()) if deploy.wait => {
+                            info!("Waiting for the confirmation of {} contract(s)", report.contracts.len());
+                            wait_for_confirmations(&report.contracts, &options, CONFIRMATION_TIMEOUT)
+                        }
+                        res => res,
+                    }
+                }
+            };
+            if let Err(e) = res {
+                exit_with(ClarinetError::Deploy(e));
+            }
+        }
+        Command::Clean(clean) => {
+            let cache_dir = match clean.cache_dir {
+                Some(ref cache_dir) => PathBuf::from(cache_dir),
+                None => PathBuf::from(&current_path).join(DEFAULT_CACHE_DIR),
+            };
+            // The contracts of the project are never removed, whatever --cache-dir is.
+            let protected: Vec<PathBuf> = match MainConfig::from_path(&manifest_path) {
+                Ok(config) => config
+                    .contracts
+                    .unwrap_or_default()
+                    .values()
+                    .map(|contract| PathBuf::from(&current_path).join(&contract.path))
+                    .collect(),
+                Err(e) if manifest_path.exists() => exit_with(ClarinetError::Config(e)),
+                Err(_) => vec![],
+            };
+            match clean_cache_dir(&PathBuf::from(&current_path), &cache_dir, &protected, clean.dry_run) {
+                Ok(entries) if entries.is_empty() => outputln!("Nothing to clean"),
+                Ok(entries) => {
+                    let action = if clean.dry_run { "Would remove" } else { "Removed" };
+                    for entry in entries.iter() {
+                        outputln!("{} {}", action, entry.display());
+                    }
+                }
+                Err(e) => exit_with(ClarinetError::Config(e)),
+            }
+        }
+        Command::Doctor(doctor) => {
+            let checks = run_checks(&manifest_path, doctor.check_nodes);
+            for check in checks.iter() {
+                match check.outcome {
+                    CheckOutcome::Passed => outputln!("{} {}", colors::green("ok"), check.label),
+                    CheckOutcome::Failed(ref e) => outputln!("{} {}: {}", colors::red("fail"), check.label, e),
+                    CheckOutcome::Skipped(ref reason) => outputln!("{} {}: {}", colors::yellow("skip"), check.label, reason),
+                }
+            }
+            let failures = checks.iter().filter(|check| check.failed()).count();
+            if failures > 0 {
+                exit_with(ClarinetError::Failure(format!("\n{} problem(s) found", failures)));
+            }
+            outputln!("\nNo problem found");
+        }
+        Command::Encode(encode) => match codec::encode_value(&encode.value) {
+            Ok(hex) => outputln!("{}", hex),
+            Err(e) => exit_with(ClarinetError::Usage(e)),
+        },
+        Command::Decode(decode) => match codec::decode_value(&decode.hex) {
+            Ok(value) => outputln!("{}", value),
+            Err(e) => exit_with(ClarinetError::Usage(e)),
+        },
+        Command::Version(version_opts) => {
+            outputln!("clarinet {}", version::CURRENT_VERSION);
+            if !version_opts.check {
+                return;
+            }
+            match version::fetch_latest_version(Duration::from_secs(version_opts.timeout)) {
+                Ok(latest) if version::is_newer(&latest, version::CURRENT_VERSION) => {
+                    outputln!("clarinet {} is available, upgrade with: cargo install clarinet --locked", latest)
+                }
+                Ok(_) => outputln!("clarinet is up to date"),
+                Err(e) => exit_with(ClarinetError::Failure(e)),
+            }
+        }
+    };
+}
+
+/// Compare the diagnostics of the contracts of `settings` with the baseline
+/// (`diff`) and/or store them as the new baseline (`update_baseline`),
+/// returning the number of diagnostics missing from the baseline.
+fn check_against_baseline(settings: &repl::SessionSettings, baseline_path: &Path, diff: bool, update_baseline: bool) -> Result<usize, String> {
+    let diagnostics = collect_diagnostics(settings)?;
+
+    let mut added = vec![];
+    if diff {
+        let baseline = load_baseline(baseline_path)?;
+        added = new_diagnostics(diagnostics.clone(), &baseline);
+        if added.is_empty() {
+            info!("No new diagnostics");
+        }
+        for diagnostic in added.iter() {
+            let position = match (diagnostic.line, diagnostic.column) {
+                (Some(line), Some(column)) => format!(" (line {}, column {})", line, column),
+                _ => "".to_string(),
+            };
+            outputln!("{}{}: {}", diagnostic.contract, position, diagnostic.message);
+        }
+    }
+
+    if update_baseline {
+        save_baseline(baseline_path, &diagnostics)?;
+        info!("Baseline updated with {} diagnostic(s)", diagnostics.len());
+    }
+    Ok(added.len())
+}
+
+/// Print the total duration of the test runs, with the number of tests run
+/// per second, followed by the `slowest` slowest tests when requested.
+fn print_test_durations(reports: &[(&&str, TestResults)], slowest: Option<usize>) {
+    let duration: u64 = reports.iter().map(|(_, results)| results.duration).sum();
+    let count: usize = reports
+        .iter()
+        .map(|(_, results)| results.tests.iter().filter(|test| test.status != "ignored").count())
+        .sum();
+    let throughput = if duration > 0 { count as f64 * 1000.0 / duration as f64 } else { 0.0 };
+    outputln!("\nRan {} test(s) in {} ({:.1} tests/s)", count, format_duration(duration), throughput);
+
+    let slowest = match slowest {
+        Some(slowest) if slowest > 0 => slowest,
+        _ => return,
+    };
+    let mut tests: Vec<(String, u64)> = vec![];
+    for (env, results) in reports.iter() {
+        for test in results.tests.iter().filter(|test| test.status != "ignored") {
+            let name = if reports.len() > 1 { format!("{} ({})", test.name, env) } else { test.name.clone() };
+            tests.push((name, test.duration));
+        }
+    }
+    tests.sort_by(|a, b| b.1.cmp(&a.1));
+    outputln!("\nSlowest tests:");
+    for (name, duration) in tests.iter().take(slowest) {
+        outputln!("  {:>8}  {}", format_duration(*duration), name);
+    }
+}
+
+/// Results of the test cases as a Test Anything Protocol (version 13) stream.
+/// Test cases run against several environments are suffixed with the name
+/// of the environment.
+fn print_tap_report(reports: &[(&&str, TestResults)]) {
+    let count: usize = reports.iter().map(|(_, results)| results.tests.len()).sum();
+    outputln!("TAP version 13");
+    outputln!("1..{}", count);
+    let mut index = 0;
+    for (env, results) in reports.iter() {
+        for test in results.tests.iter() {
+            index += 1;
+            let name = if reports.len() > 1 { format!("{} ({})", test.name, env) } else { test.name.clone() };
+            let name = name.replace('#', "\\#");
+            match test.status.as_str() {
+                "passed" => outputln!("ok {} - {}", index, name),
+                "ignored" => outputln!("ok {} - {} # SKIP", index, name),
+                _ => {
+                    let reason = test.error.as_deref().and_then(|error| error.lines().next()).unwrap_or(&test.status);
+                    outputln!("not ok {} - {} # {}", index, name, reason);
+                }
+            }
+        }
+    }
+}
+
+/// `duration`, in milliseconds, as `850ms`, `1.25s` or `2m05s`.
+fn format_duration(duration: u64) -> String {
+    if duration < 1000 {
+        format!("{}ms", duration)
+    } else if duration < 60_000 {
+        format!("{:.2}s", duration as f64 / 1000.0)
+    } else {
+        format!("{}m{:02}s", duration / 60_000, duration % 60_000 / 1000)
+    }
+}
+
+/// Node the transactions are sent to: the one given with --node-url, or by the
+/// profile, or else the `node_url` of the settings of `network`.
+fn resolve_node_url(
+    node_url: Option<&str>,
+    profile: &ProfileConfig,
+    manifest_path: &Path,
+    network: Option<Network>,
+) -> Result<String, String> {
+    // The environment overrides the profile, the settings applying it on load.
+    let env_node_url = std::env::var(NODE_URL_VAR).ok().filter(|node_url| !node_url.trim().is_empty());
+    if let Some(node_url) = node_url.or(env_node_url.as_deref()).or(profile.node_url.as_deref()) {
+        return Ok(node_url.trim_end_matches('/').to_string());
+    }
+    match network {
+        Some(network) if manifest_path.is_file() => load_node_url(manifest_path, network.env_name()),
+        Some(network) => Ok(network.default_node_url().to_string()),
+        None => Ok(DEFAULT_NODE_URL.to_string()),
+    }
+}
+
+/// Keep, among the contracts of the project loaded in `settings`, the
+/// contracts `names` and the ones they depend on. Contracts of other
+/// deployers, such as pinned links, are kept.
+fn select_contracts(settings: &mut repl::SessionSettings, manifest_path: &Path, names: &[String]) -> Result<(), String> {
+    let selected = MainConfig::from_path(manifest_path)?.contracts_with_dependencies(names)?;
+    let deployer_address = settings.initial_deployer.as_ref().map(|deployer| deployer.address.clone());
+    settings.initial_contracts.retain(|contract| {
+        contract.deployer != deployer_address || contract.name.as_ref().map_or(false, |name| selected.contains(name))
+    });
+    Ok(())
+}
+
+/// Check the contracts loaded by `load`, then again whenever the project
+/// files change, until interrupted. With `clear`, the screen is redrawn with
+/// a status line per contract, and pressing Enter shows or hides the
+/// diagnostics.
+fn watch_contracts<F>(load: F, max_errors: Option<usize>, clear: bool, verbose: bool) -> !
+where
+    F: Fn() -> Result<repl::SessionSettings, String>,
+{
+    let (keys, presses) = mpsc::channel();
+    if clear {
+        thread::spawn(move || {
+            let stdin = std::io::stdin();
+            for line in stdin.lock().lines() {
+                if line.is_err() || keys.send(()).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    let check = || load().and_then(|settings| collect_diagnostics(&settings).map(|diagnostics| (settings, diagnostics)));
+    let mut watcher = ProjectWatcher::new();
+    let mut verbose = verbose;
+    let mut result = check();
+    loop {
+        if clear {
+            print_check_dashboard(&result, max_errors, verbose);
+        } else {
+            match result {
+                Ok((_, ref diagnostics)) if diagnostics.is_empty() => outputln!("{}", colors::green("No errors found")),
+                Ok((ref settings, ref diagnostics)) => print_diagnostics(diagnostics, settings, max_errors),
+                Err(ref e) => outputln!("{}", colors::red(e)),
+            }
+        }
+        loop {
+            thread::sleep(WATCH_INTERVAL);
+            if watcher.has_changed() {
+                if !clear {
+                    outputln!("{}", colors::gray("Project files changed, checking again..."));
+                }
+                result = check();
+                break;
+            }
+            if presses.try_recv().is_ok() {
+                verbose = !verbose;
+                break;
+            }
+        }
+    }
+}
+
+/// Clear the screen (when stdout is a terminal accepting ANSI sequences) and
+/// print a status line per contract, followed by the diagnostics when
+/// `verbose` is set.
+fn print_check_dashboard(
+    result: &Result<(repl::SessionSettings, Vec<ContractDiagnostic>), String>,
+    max_errors: Option<usize>,
+    verbose: bool,
+) {
+    if colors::use_color() {
+        print!("\x1b[2J\x1b[H");
+    }
+    match result {
+        Ok((settings, diagnostics)) => {
+            for contract in settings.initial_contracts.iter() {
+                let name = contract.name.clone().unwrap_or_default();
+                let errors = diagnostics.iter().filter(|d| d.contract == name && d.level == "Error").count();
+                let warnings = diagnostics.iter().filter(|d| d.contract == name && d.level == "Warning").count();
+                match (errors, warnings) {
+                    (0, 0) => outputln!("{} {}", colors::green("✓"), name),
+                    (0, warnings) => outputln!("{} {} {}", colors::yellow("✓"), name, colors::gray(format!("({} warning(s))", warnings))),
+                    (errors, _) => outputln!("{} {} {}", colors::red("✗"), name, colors::gray(format!("({} error(s))", errors))),
+                }
+            }
+            if verbose && !diagnostics.is_empty() {
+                outputln!();
+                print_diagnostics(diagnostics, settings, max_errors);
+            }
+        }
+        Err(e) => outputln!("{}", colors::red(e)),
+    }
+    outputln!();
+    let toggle = if verbose { "hide" } else { "show" };
+    outputln!("{}", colors::gray(format!("Watching for changes. Press Enter to {} the diagnostics, Ctrl-C to quit.", toggle)));
+}
+
+/// Environment selected by `CLARINET_ENV`, if set.
+fn env_network() -> Option<Network> {
+    let env = std::env::var(ENV_VAR).ok().filter(|env| !env.trim().is_empty())?;
+    match env.trim().parse::<Network>() {
+        Ok(network) => Some(network),
+        Err(e) => exit_with(ClarinetError::Usage(format!("Error: invalid {}: {}", ENV_VAR, e))),
+    }
+}
+
+/// Deploy the contracts of the project to the mocknet node, and wait until
+/// they are all confirmed, for `clarinet test --seed-contracts`.
+fn seed_contracts(manifest_path: &Path, node_url: &str, timeout: Duration) -> Result<(), String> {
+    let mut settings = load_session_settings(manifest_path, "mocknet")?;
+    let diagnostics = collect_diagnostics(&settings)?;
+    if !diagnostics.is_empty() {
+        print_diagnostics(&diagnostics, &settings, None);
+        return Err("Error: contracts failed to analyze".to_string());
+    }
+    let deployer_address = settings.initial_deployer.as_ref().map(|deployer| deployer.address.clone());
+    settings.initial_contracts.retain(|contract| contract.deployer == deployer_address);
+
+    let options = PublishOptions {
+        node_url: node_url.to_string(),
+        post_deploy: post_deploy_calls(manifest_path)?,
+        initial_funding: initial_fundings(manifest_path)?,
+        ..PublishOptions::default()
+    };
+    let mut report = DeploymentReport::default();
+    let res = publish_contracts(&settings, &options, &mut report);
+    update_lockfile(manifest_path, "mocknet", &report.contracts, &options);
+    res?;
+    info!("Waiting for the confirmation of {} contract(s)", report.contracts.len());
+    wait_for_confirmations(&report.contracts, &options, timeout)
+}
+
+/// `post_deploy` calls of the contracts of the project, by contract name.
+fn post_deploy_calls(manifest_path: &Path) -> Result<BTreeMap<String, Vec<String>>, String> {
+    let config = MainConfig::from_path(manifest_path)?;
+    Ok(config
+        .contracts
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|(name, contract)| contract.post_deploy.map(|calls| (name, calls)))
+        .collect())
+}
+
+/// `initial_funding` of the contracts of the project, by contract name.
+fn initial_fundings(manifest_path: &Path) -> Result<BTreeMap<String, u64>, String> {
+    let config = MainConfig::from_path(manifest_path)?;
+    Ok(config
+        .contracts
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|(name, contract)| contract.initial_funding.map(|amount| (name, amount)))
+        .collect())
+}
+
+/// Print the contracts and `post_deploy` calls broadcasted, followed by the
+/// fees paid by each account and its estimated remaining balance.
+fn print_deployment_report(report: &DeploymentReport, json: bool) {
+    let spending = report.spending();
+    if json {
+        let output = serde_json::json!({
+            "contracts": report.contracts,
+            "post_deploy": report.calls,
+            "accounts": spending,
+        });
+        outputln!("{}", serde_json::to_string_pretty(&output).unwrap());
+        return;
+    }
+    for contract in report.contracts.iter() {
+        outputln!("Deploying {} (txid: {}, nonce: {})", contract.contract_name, contract.txid, contract.nonce);
+    }
+    for call in report.calls.iter() {
+        outputln!("Calling {} (txid: {}, nonce: {})", call.expression, call.txid, call.nonce);
+    }
+    if spending.is_empty() {
+        return;
+    }
+    outputln!("\nFees paid:");
+    for account in spending.iter() {
+        match (account.starting_balance, account.remaining_balance) {
+            (Some(starting_balance), Some(remaining_balance)) => outputln!(
+                "  {}: {} µSTX (balance: {}, {} remaining)",
+                account.address, account.fees, starting_balance, remaining_balance
+            ),
+            _ => outputln!("  {}: {} µSTX (balance unknown)", account.address, account.fees),
+        }
+    }
+}
+
+/// Record `deployed` in the lockfile of the project (`Clarinet.lock` next to
+/// `Clarinet.toml`, or the manifest name with a `.lock` extension), and fill
+/// in the confirmation height of the contracts previously deployed on
+/// `environment` that have been mined since. Nothing is written outside of a
+/// project, or when nothing was deployed.
+fn update_lockfile(manifest_path: &Path, environment: &str, deployed: &[DeployedContract], options: &PublishOptions) {
+    if deployed.is_empty() || !manifest_path.exists() {
+        return;
+    }
+    let path = manifest_path.with_extension("lock");
+    let mut lockfile = match Lockfile::from_path(&path) {
+        Ok(lockfile) => lockfile,
+        Err(e) => {
+            error!("{}", e);
+            return;
+        }
+    };
+    if let Some(locked) = lockfile.environments.get_mut(environment) {
+        for contract in locked.contracts.values_mut() {
+            if contract.confirmation_height.is_none() {
+                contract.confirmation_height = confirmation_height(&contract.txid, options);
+            }
+        }
+    }
+    for contract in deployed.iter() {
+        lockfile.record(environment, &contract.contract_name, &contract.txid, &contract.source_hash);
+    }
+    match lockfile.write(&path) {
+        Ok(()) => debug!("Updated {}", path.display()),
+        Err(e) => error!("{}", e),
+    }
+}
+
+/// Print the diagnostics grouped by contract, stopping after the first
+/// `max_errors` of them when set.
+fn print_diagnostics(diagnostics: &[ContractDiagnostic], settings: &repl::SessionSettings, max_errors: Option<usize>) {
+    let total = diagnostics.len();
+    let diagnostics = &diagnostics[..max_errors.unwrap_or(total).min(total)];
+    let mut contracts: Vec<&str> = vec![];
+    for diagnostic in diagnostics.iter() {
+        if !contracts.contains(&diagnostic.contract.as_str()) {
+            contracts.push(&diagnostic.contract);
+        }
+    }
+
+    for contract in contracts.into_iter() {
+        outputln!("{}", colors::bold(contract));
+        let code = settings
+            .initial_contracts
+            .iter()
+            .find(|c| c.name.as_deref() == Some(contract))
+            .map(|c| c.code.as_str())
+            .unwrap_or("");
+        for diagnostic in diagnostics.iter().filter(|d| d.contract == contract) {
+            let level = match diagnostic.level.as_str() {
+                "Error" => colors::red("error"),
+                "Warning" => colors::yellow("warning"),
+                level => level.to_lowercase(),
+            };
+            outputln!("  {}: {}", level, diagnostic.message);
+            if let (Some(line), Some(column)) = (diagnostic.line, diagnostic.column) {
+                let source = code.lines().nth((line as usize).saturating_sub(1)).unwrap_or("");
+                let gutter = line.to_string();
+                outputln!("  {} {}", colors::gray(format!("{} |", gutter)), source);
+                outputln!(
+                    "  {} {}{}",
+                    colors::gray(format!("{} |", " ".repeat(gutter.len()))),
+                    " ".repeat((column as usize).saturating_sub(1)),
+                    colors::red("^")
+                );
+            }
+        }
+        outputln!();
+    }
+    if diagnostics.len() < total {
+        outputln!("... and {} more", total - diagnostics.len());
+    }
+    outputln!("{} diagnostic(s) found", total);
+}
+
+fn print_costs(reports: &[ContractCosts]) {
+    for report in reports.iter() {
+        outputln!("{}", report.contract);
+        let width = report
+            .functions
+            .iter()
+            .map(|f| f.name.len())
+            .max()
+            .unwrap_or(0)
+            .max("function".len());
+        outputln!(
+            "  {:<width$}  {:<9}  {:>7}  {:>5}  {:>6}  {:>5}",
+            "function", "access", "runtime", "reads", "writes", "calls",
+            width = width
+        );
+        for f in report.functions.iter() {
+            outputln!(
+                "  {:<width$}  {:<9}  {:>7}  {:>5}  {:>6}  {:>5}",
+                f.name, f.access, f.runtime, f.read_count, f.write_count, f.contract_calls,
+                width = width
+            );
+        }
+        outputln!();
+    }
+}
+
+fn print_metrics(reports: &[ContractMetrics]) {
+    let width = reports
+        .iter()
+        .map(|r| r.contract.len())
+        .max()
+        .unwrap_or(0)
+        .max("contract".len());
+    outputln!(
+        "{:<width$}  {:>6}  {:>9}  {:>7}  {:>9}  {:>4}  {:>9}",
+        "contract", "public", "read-only", "private", "data-vars", "maps", "constants",
+        width = width
+    );
+    for r in reports.iter() {
+        outputln!(
+            "{:<width$}  {:>6}  {:>9}  {:>7}  {:>9}  {:>4}  {:>9}",
+            r.contract, r.public_functions, r.read_only_functions, r.private_functions, r.data_vars, r.maps, r.constants,
+            width = width
+        );
+    }
+}
+
+/// Source of the contract stored at `path`, which must parse.
+fn read_contract_source(path: &Path) -> Result<String, String> {
+    let source = fs::read_to_string(path).map_err(|e| format!("Error: unable to read {}: {}", path.display(), e))?;
+    parser::parse(&source).map_err(|e| {
+        let line = e.diagnostic.spans.first().map_or(0, |span| span.start_line);
+        format!("Error: unable to parse {} at line {}: {}", path.display(), line, e)
+    })?;
+    Ok(source)
+}
+
+/// Prompt for the account deploying the contracts, among the accounts of the
+/// settings, listed with their address and balance. The `deployer` account is
+/// selected when nothing is entered. The prompt is written to stderr, out of
+/// the output of the command.
+fn pick_deployer(settings: &repl::SessionSettings, options: &PublishOptions) -> Result<String, String> {
+    let accounts = fetch_account_balances(settings, options)?;
+    if accounts.is_empty() {
+        return Err("Error: no account found in the settings".to_string());
+    }
+    for (index, (name, address, balance)) in accounts.iter().enumerate() {
+        match balance {
+            Some(balance) => eprintln!("  {}) {} {} ({})", index + 1, name, address, balance),
+            None => eprintln!("  {}) {} {} (balance unknown)", index + 1, name, address),
+        }
+    }
+    let default = accounts.iter().position(|(name, _, _)| name == "deployer");
+    loop {
+        match default {
+            Some(index) => eprint!("Deployer account [{}]: ", index + 1),
+            None => eprint!("Deployer account: "),
+        }
+        std::io::stderr()
+            .flush()
+            .map_err(|e| format!("Error: unable to write to stderr: {}", e))?;
+        let mut input = String::new();
+        let read = std::io::stdin()
+            .read_line(&mut input)
+            .map_err(|e| format!("Error: unable to read the selected account: {}", e))?;
+        if read == 0 {
+            return Err("Error: no deployer account selected".to_string());
+        }
+        let input = input.trim();
+        let selected = if input.is_empty() {
+            default
+        } else {
+            input
+                .parse::<usize>()
+                .ok()
+                .and_then(|number| number.checked_sub(1))
+                .filter(|index| *index < accounts.len())
+                .or_else(|| accounts.iter().position(|(name, _, _)| name == input))
+        };
+        match selected {
+            Some(index) => return Ok(accounts[index].0.clone()),
+            None => eprintln!("Enter a number between 1 and {}, or the name of an account", accounts.len()),
+        }
+    }
+}
+
+/// Settings holding the accounts of `settings/<Mode>.toml` and the single
+/// contract read from `contract_path` (or stdin, with "-"), deployed by `deployer`.
+fn load_single_contract_settings(manifest_path: &Path, mode: &str, contract_path: &str, name: Option<String>) -> Result<repl::SessionSettings, String> {
+    let code = if contract_path == "-" {
+        let mut code = String::new();
+        std::io::stdin()
+            .read_to_string(&mut code)
+            .map_err(|e| format!("Error: unable to read contract from stdin: {}", e))?;
+        code
+    } else {
+        fs::read_to_string(contract_path)
+            .map_err(|e| format!("Error: unable to read {}: {}", contract_path, e))?
+    };
+
+    let name = match name {
+        Some(name) => name,
+        None if contract_path == "-" => {
+            return Err("Error: --name is required when reading the contract from stdin".into())
+        }
+        None => match Path::new(contract_path).file_stem() {
+            Some(stem) => stem.to_string_lossy().to_string(),
+            None => return Err(format!("Error: unable to infer a contract name from {}", contract_path)),
+        },
+    };
+
+    let mut settings = load_chain_settings(manifest_path, mode)?;
+    let deployer = match settings.initial_deployer {
+        Some(ref deployer) => deployer.address.clone(),
+        None => return Err(format!("Error: no deployer account found in the {} settings", mode)),
+    };
+    settings.initial_contracts.push(repl::settings::InitialContract {
+        code,
+        name: Some(name),
+        deployer: Some(deployer),
+    });
+    settings.include_boot_contracts = true;
+    Ok(settings)
+}
+
+fn make_paths_absolute(command: &mut Command, cwd: &Path) {
+    let absolute = |path: &str| cwd.join(path).to_string_lossy().to_string();
+    match command {
+        Command::Test(test) => {
+            test.files = test.files.iter().map(|file| absolute(file)).collect();
+        }
+        Command::Deploy(deploy) => {
+            if let Some(ref contract) = deploy.contract {
+                if contract != "-" {
+                    deploy.contract = Some(absolute(contract));
+                }
+            }
+            deploy.unsigned_out = deploy.unsigned_out.as_ref().map(|dir| absolute(dir));
+            deploy.signed_in = deploy.signed_in.as_ref().map(|dir| absolute(dir));
+            deploy.export_bundle = deploy.export_bundle.as_ref().map(|file| absolute(file));
+            deploy.broadcast_bundle = deploy.broadcast_bundle.as_ref().map(|file| absolute(file));
+            deploy.emit_plan = deploy.emit_plan.as_ref().map(|file| absolute(file));
+            deploy.export_manifest = deploy.export_manifest.as_ref().map(|file| absolute(file));
+        }
+        Command::Contract(Contract::ContractInterface(contract_interface)) => {
+            contract_interface.out_dir = contract_interface.out_dir.as_ref().map(|dir| absolute(dir));
+        }
+        Command::Contract(Contract::ForkContract(fork_contract)) => {
+            fork_contract.output_dir = fork_contract.output_dir.as_ref().map(|dir| absolute(dir));
+        }
+        Command::Console(console) => {
+            console.eval_file = console.eval_file.as_ref().map(|file| absolute(file));
+        }
+        Command::Clean(clean) => {
+            clean.cache_dir = clean.cache_dir.as_ref().map(|dir| absolute(dir));
+        }
+        _ => {}
+    }
+}
+
+/// `dir`, an absolute path, relative to the project root, as written in the
+/// `path` of the contracts of Clarinet.toml.
+fn project_relative_dir(project_root: &Path, dir: &Path) -> Result<String, String> {
+    let outside = || format!("Error: {} is not a directory of the project", dir.display());
+    let relative = dir.strip_prefix(project_root).map_err(|_| outside())?;
+    let mut components = vec![];
+    for component in relative.components() {
+        match component {
+            std::path::Component::Normal(name) => components.push(name.to_string_lossy().to_string()),
+            std::path::Component::CurDir => {}
+            _ => return Err(outside()),
+        }
+    }
+    if components.is_empty() {
+        return Err("Error: contracts can't be written to the root of the project".to_string());
+    }
+    Ok(components.join("/"))
+}
+
+/// Traits implemented and referenced by each contract, flagging the trait
+/// functions a contract misses or defines with other signatures.
+fn print_contract_traits(reports: &[ContractTraits]) {
+    for report in reports.iter() {
+        outputln!("{}", colors::bold(&report.contract));
+        if report.implemented.is_empty() && report.referenced.is_empty() {
+            outputln!("  no traits");
+        }
+        for implemented in report.implemented.iter() {
+            let mismatched = &implemented.mismatched_functions;
+            let status = match implemented.missing_functions {
+                Some(ref missing) if missing.is_empty() && mismatched.is_empty() => colors::green("ok"),
+                Some(ref missing) if missing.is_empty() => colors::red(format!("wrong signature for {}", mismatched.join(", "))),
+                Some(ref missing) if mismatched.is_empty() => colors::red(format!("missing {}", missing.join(", "))),
+                Some(ref missing) => colors::red(format!(
+                    "missing {}, wrong signature for {}",
+                    missing.join(", "),
+                    mismatched.join(", ")
+                )),
+                None => colors::yellow("unknown trait"),
+            };
+            outputln!("  implements {}: {}", implemented.trait_id, status);
+        }
+        for (alias, trait_id) in report.referenced.iter() {
+            outputln!("  uses <{}> {}", alias, trait_id);
+        }
+        if let Some(ref error) = report.error {
+            outputln!("  {}: {}", colors::red("error"), error);
+        }
+    }
+}
+
+/// JSON description of the changes, as printed by `--json`.
+fn describe_changes(changes: &[Changes]) -> Vec<serde_json::Value> {
+    changes
+        .iter()
+        .map(|change| match change {
+            Changes::AddFile(options) => serde_json::json!({ "action": "create_file", "path": options.path }),
+            Changes::OverwriteFile(options) => serde_json::json!({ "action": "overwrite_file", "path": options.path }),
+            Changes::AddDirectory(options) => serde_json::json!({ "action": "create_directory", "path": options.path }),
+            Changes::EditTOML(options) => {
+                let mut contracts: Vec<&String> = options.contracts_to_add.keys().collect();
+                contracts.sort();
+                let links: Vec<&String> = options.links_to_add.iter().map(|link| &link.contract_id).collect();
+                serde_json::json!({
+                    "action": "edit",
+                    "path": options.path,
+                    "contracts_to_add": contracts,
+                    "links_to_add": links,
+                })
+            }
+        })
+        .collect()
+}
+
+/// Changes already written to disk, kept so that they can be undone.
+enum AppliedChange {
+    CreatedFile(String),
+    CreatedDirectory(String),
+    EditedFile(String, Vec<u8>),
+}
+
+/// Apply the changes or, with `dry_run`, only validate and print them.
+fn execute_or_print_changes(changes: Vec<Changes>, dry_run: bool) -> Result<(), String> {
+    if !dry_run {
+        return execute_changes(changes);
+    }
+    validate_changes(&changes)?;
+    for change in changes.iter() {
+        match change {
+            Changes::AddDirectory(options) => outputln!("create directory {}", options.path),
+            Changes::AddFile(options) => outputln!("create file {}", options.path),
+            Changes::OverwriteFile(options) if Path::new(&options.path).exists() => {
+                outputln!("replace file {} (backup: {})", options.path, backup_path(&options.path))
+            }
+            Changes::OverwriteFile(options) => outputln!("create file {}", options.path),
+            Changes::EditTOML(options) => {
+                outputln!("edit {}", options.path);
+                for name in options.contracts_to_add.keys() {
+                    outputln!("  add contract {}", name);
+                }
+                for link in options.links_to_add.iter() {
+                    outputln!("  add link {}", link.contract_id);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Apply the changes in order. The changes are validated before anything is
+/// written, and if one of them fails anyway, the ones already applied are
+/// rolled back, so that the project is left untouched.
+fn execute_changes(changes: Vec<Changes>) -> Result<(), String> {
+    validate_changes(&changes)?;
+
+    let mut applied = vec![];
+    for change in changes.into_iter() {
+        if let Err(e) = apply_change(change, &mut applied) {
+            rollback_changes(applied);
+            return Err(format!("{}\nNo changes were applied", e));
+        }
+    }
+    Ok(())
+}
+
+fn validate_changes(changes: &[Changes]) -> Result<(), String> {
+    let mut created_directories = vec![];
+    for change in changes.iter() {
+        match change {
+            Changes::AddFile(options) | Changes::OverwriteFile(options) => {
+                let path = Path::new(&options.path);
+                if path.exists() && (matches!(change, Changes::AddFile(_)) || !path.is_file()) {
+                    return Err(format!("Error: {} already exists", options.path));
+                }
+                let parent_exists = match path.parent() {
+                    Some(parent) => parent.is_dir() || created_directories.iter().any(|dir: &PathBuf| dir == parent),
+                    None => true,
+                };
+                if !parent_exists {
+                    return Err(format!("Error: unable to create {}, parent directory missing", options.path));
+                }
+            }
+            Changes::AddDirectory(options) => {
+                let path = Path::new(&options.path);
+                if path.exists() && !path.is_dir() {
+                    return Err(format!("Error: {} already exists and is not a directory", options.path));
+                }
+                created_directories.push(path.to_path_buf());
+            }
+            Changes::EditTOML(options) => {
+                if !Path::new(&options.path).is_file() {
+                    return Err(format!("Error: unable to find {}", options.path));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn apply_change(change: Changes, applied: &mut Vec<AppliedChange>) -> Result<(), String> {
+    match change {
+        Changes::AddFile(options) => {
+            info!("{}", options.comment);
+            let mut file = File::create(&options.path)
+                .map_err(|e| format!("Error: unable to create {}: {}", options.path, e))?;
+            applied.push(AppliedChange::CreatedFile(options.path.clone()));
+            file.write_all(options.content.as_bytes())
+                .map_err(|e| format!("Error: unable to write {}: {}", options.path, e))?;
+        }
+        Changes::OverwriteFile(options) => {
+            let path = Path::new(&options.path);
+            if !path.exists() {
+                return apply_change(Changes::AddFile(options), applied);
+            }
+            let original = fs::read(path)
+                .map_err(|e| format!("Error: unable to read {}: {}", options.path, e))?;
+            let backup = backup_path(&options.path);
+            let previous_backup = fs::read(&backup).ok();
+            fs::write(&backup, &original)
+                .map_err(|e| format!("Error: unable to write {}: {}", backup, e))?;
+            applied.push(match previous_backup {
+                Some(previous_backup) => AppliedChange::EditedFile(backup.clone(), previous_backup),
+                None => AppliedChange::CreatedFile(backup.clone()),
+            });
+            info!("Backed up {} to {}", options.path, backup);
+            applied.push(AppliedChange::EditedFile(options.path.clone(), original));
+            fs::write(path, options.content.as_bytes())
+                .map_err(|e| format!("Error: unable to write {}: {}", options.path, e))?;
+            info!("{}", options.comment.replacen("Creating", "Replacing", 1));
+        }
+        Changes::AddDirectory(options) => {
+            info!("{}", options.comment);
+            let mut missing = vec![];
+            let mut path = Some(Path::new(&options.path));
+            while let Some(dir) = path {
+                if dir.as_os_str().is_empty() || dir.exists() {
+                    break;
+                }
+                missing.push(dir.to_string_lossy().to_string());
+                path = dir.parent();
+            }
+            fs::create_dir_all(&options.path)
+                .map_err(|e| format!("Error: unable to create directory {}: {}", options.path, e))?;
+            for dir in missing.into_iter().rev() {
+                applied.push(AppliedChange::CreatedDirectory(dir));
+            }
+        }
+        Changes::EditTOML(mut options) => {
+            let original = fs::read(&options.path)
+                .map_err(|e| format!("Error: unable to read {}: {}", options.path, e))?;
+            let config_file: MainConfigFile = toml::from_slice(&original[..])
+                .map_err(|e| format!("Error: unable to parse {}: {}", options.path, e))?;
+            let mut config = MainConfig::from_config_file(config_file)?;
+            let mut dirty = false;
+
+            let mut links = match config.links.take() {
+                Some(links) => links,
+                None => vec![],
+            };
+            for link in options.links_to_add.drain(..) {
+                // Re-linking a contract only updates its entry when pinning it
+                // to another block height; an existing pin is otherwise kept.
+                match links.iter_mut().find(|l| l.contract_id == link.contract_id) {
+                    Some(existing) if link.block_height.is_none() || *existing == link => {}
+                    Some(existing) => {
+                        *existing = link;
+                        dirty = true;
+                    }
+                    None => {
+                        links.push(link);
+                        dirty = true;
+                    }
+                }
+            }
+            config.links = Some(links);
+
+            let mut contracts = match config.contracts.take() {
+                Some(contracts) => contracts,
+                None => BTreeMap::new(),
+            };
+            for (contract_name, contract_config) in options.contracts_to_add.iter() {
+                let mut contract_config = contract_config.clone();
+                match contracts.remove(contract_name) {
+                    // Keys unknown to this version are kept on the updated entry.
+                    Some(previous) => contract_config.extra_keys.extend(previous.extra_keys),
+                    None => dirty = true,
+                }
+                contracts.insert(contract_name.clone(), contract_config);
+            }
+            config.contracts = Some(contracts);
+
+            if dirty {
+                let toml = config
+                    .to_toml()
+                    .map_err(|e| format!("Error: unable to serialize {}: {}", options.path, e))?;
+                applied.push(AppliedChange::EditedFile(options.path.clone(), original));
+                fs::write(&options.path, toml.as_bytes())
+                    .map_err(|e| format!("Error: unable to write {}: {}", options.path, e))?;
+            }
+            info!("{}", options.comment);
+        }
+    }
+    Ok(())
+}
+
+/// Copy of a file kept by `contract new --overwrite`.
+fn backup_path(path: &str) -> String {
+    format!("{}.bak", path)
+}
+
+fn rollback_changes(applied: Vec<AppliedChange>) {
+    for change in applied.into_iter().rev() {
+        let res = match change {
+            AppliedChange::CreatedFile(ref path) => fs::remove_file(path),
+            AppliedChange::CreatedDirectory(ref path) => fs::remove_dir(path),
+            AppliedChange::EditedFile(ref path, ref original) => fs::write(path, original),
+        };
+        if let Err(e) = res {
+            let path = match change {
+                AppliedChange::CreatedFile(path)
+                | AppliedChange::CreatedDirectory(path)
+                | AppliedChange::EditedFile(path, _) => path,
+            };
+            error!("Unable to roll back changes made to {}: {}", path, e);
         }
     }
 }
@@ -27,6 +27,8 @@ pub struct TOMLEdition {
 #[derive(Clone, Debug)]
 pub enum Changes {
     AddFile(FileCreation),
+    /// Create a file, or replace it after copying it to `<path>.bak`
+    OverwriteFile(FileCreation),
     AddDirectory(DirectoryCreation),
     EditTOML(TOMLEdition),
 }
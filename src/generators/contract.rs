@@ -1,20 +1,38 @@
-use super::changes::{Changes, FileCreation, TOMLEdition};
-use std::collections::HashMap;
+use super::changes::{Changes, DirectoryCreation, FileCreation, TOMLEdition};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
 use crate::types::ContractConfig;
 
+/// Definitions scaffolded in the stub of a new contract, around a `value`
+/// data-var.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ContractSkeleton {
+    /// Read-only `get-value`
+    pub getter: bool,
+    /// Public `set-value`, restricted to the deployer of the contract
+    pub setter: bool,
+}
+
 pub struct GetChangesForNewContract {
     project_path: String,
+    manifest_path: String,
+    /// Directory of the contract, relative to the project root
+    contracts_dir: String,
     contract_name: String,
     source: Option<String>,
+    skeleton: ContractSkeleton,
     changes: Vec<Changes>,
 }
 
 impl GetChangesForNewContract {
-    pub fn new(project_path: String, contract_name: String, source: Option<String>) -> Self {
+    pub fn new(project_path: String, manifest_path: String, contracts_dir: String, contract_name: String, source: Option<String>, skeleton: ContractSkeleton) -> Self {
         Self {
             project_path,
+            manifest_path,
+            contracts_dir: contracts_dir.trim_end_matches('/').to_string(),
             contract_name,
             source,
+            skeleton,
             changes: vec![],
         }
     }
@@ -32,6 +50,34 @@ impl GetChangesForNewContract {
         let content = if let Some(ref source) = self.source {
             source.to_string()
         } else {
+            let skeleton = self.skeleton;
+            let mut constants = String::new();
+            let mut vars = String::new();
+            let mut read_only_functions = String::new();
+            let mut public_functions = String::new();
+            if skeleton.getter || skeleton.setter {
+                vars.push_str("(define-data-var value uint u0)\n");
+            }
+            if skeleton.getter {
+                read_only_functions.push_str(
+                    r#"
+;; read only functions
+;;
+(define-read-only (get-value)
+    (var-get value))
+"#,
+                );
+            }
+            if skeleton.setter {
+                constants.push_str("(define-constant contract-owner tx-sender)\n(define-constant err-not-authorized (err u100))\n");
+                public_functions.push_str(
+                    r#"(define-public (set-value (new-value uint))
+    (begin
+        (asserts! (is-eq tx-sender contract-owner) err-not-authorized)
+        (ok (var-set value new-value))))
+"#,
+                );
+            }
             format!(
                 r#"
 ;; {}
@@ -39,23 +85,32 @@ impl GetChangesForNewContract {
 
 ;; constants
 ;;
-
+{}
 ;; data maps and vars
 ;;
-
+{}
 ;; private functions
 ;;
-
+{}
 ;; public functions
 ;;
-"#, self.contract_name
+{}"#, self.contract_name, constants, vars, read_only_functions, public_functions
             )
         };
             
+        let dir = format!("{}/{}", self.project_path, self.contracts_dir);
+        if !Path::new(&dir).exists() {
+            let change = DirectoryCreation {
+                comment: format!("Creating directory {}", self.contracts_dir),
+                name: self.contracts_dir.clone(),
+                path: dir.clone(),
+            };
+            self.changes.push(Changes::AddDirectory(change));
+        }
         let name = format!("{}.clar", self.contract_name);
-        let path = format!("{}/contracts/{}", self.project_path, name);
+        let path = format!("{}/{}", dir, name);
         let change = FileCreation {
-            comment: format!("Creating file contracts/{}", name),
+            comment: format!("Creating file {}/{}", self.contracts_dir, name),
             name,
             content,
             path,
@@ -106,11 +161,19 @@ Clarinet.test({{
 
     fn index_contract_in_clarinet_toml(&mut self, deps: Vec<String>) {
         let contract_file_name = format!("{}.clar", self.contract_name);
-        let path = format!("{}/Clarinet.toml", self.project_path);
+        let path = self.manifest_path.clone();
 
         let contract_config = ContractConfig {
             depends_on: deps,
-            path: format!("contracts/{}", contract_file_name),
+            path: format!("{}/{}", self.contracts_dir, contract_file_name),
+            derived_path: false,
+            environments: None,
+            excluded_environments: None,
+            tags: None,
+            description: None,
+            post_deploy: None,
+            initial_funding: None,
+            extra_keys: BTreeMap::new(),
         };
         let mut contracts_to_add = HashMap::new();
         contracts_to_add.insert(self.contract_name.clone(), contract_config);
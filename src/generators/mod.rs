@@ -2,21 +2,34 @@ pub mod changes;
 mod contract;
 mod notebook;
 mod project;
+mod remap;
+mod traits;
 
 pub use changes::{Changes, DirectoryCreation, FileCreation, TOMLEdition};
 use contract::GetChangesForNewContract;
+pub use contract::ContractSkeleton;
 use notebook::GetChangesForNewNotebook;
-use project::GetChangesForNewProject;
-use std::collections::HashMap;
+use project::{GetChangesForExistingProject, GetChangesForNewProject};
+pub use project::{detect_contracts, AccountsTemplate};
+pub use remap::{remap_principal, PrincipalRemap};
+pub use traits::KnownTrait;
+use std::collections::{BTreeMap, HashMap};
 use crate::types::LinkConfig;
 
-pub fn get_changes_for_new_project(project_path: String, project_name: String) -> Vec<Changes> {
-    let mut command = GetChangesForNewProject::new(project_path, project_name);
+pub fn get_changes_for_new_project(project_path: String, project_name: String, accounts: AccountsTemplate) -> Vec<Changes> {
+    let mut command = GetChangesForNewProject::new(project_path, project_name, accounts);
     command.run()
 }
 
-pub fn get_changes_for_new_contract(project_path: String, contract_name: String, source: Option<String>, include_test: bool, deps: Vec<String>) -> Vec<Changes> {
-    let mut command = GetChangesForNewContract::new(project_path, contract_name, source);
+/// `contracts` are the contracts detected in `project_path`, by name, with
+/// their path relative to it (see `detect_contracts`).
+pub fn get_changes_for_existing_project(project_path: String, contracts: &BTreeMap<String, String>, accounts: AccountsTemplate) -> Result<Vec<Changes>, String> {
+    let mut command = GetChangesForExistingProject::new(project_path, accounts);
+    command.run(contracts)
+}
+
+pub fn get_changes_for_new_contract(project_path: String, manifest_path: String, contracts_dir: String, contract_name: String, source: Option<String>, skeleton: ContractSkeleton, include_test: bool, deps: Vec<String>) -> Vec<Changes> {
+    let mut command = GetChangesForNewContract::new(project_path, manifest_path, contracts_dir, contract_name, source, skeleton);
     command.run(include_test, deps)
 }
 
@@ -27,6 +40,9 @@ pub fn get_changes_for_new_link(project_path: String, contract_id: String, _sour
         contracts_to_add: HashMap::new(),
         links_to_add: vec![LinkConfig {
             contract_id: contract_id.clone(),
+            block_height: None,
+            name: None,
+            cache: false,
         }],
     };
     vec![Changes::EditTOML(change)]
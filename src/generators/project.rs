@@ -1,16 +1,192 @@
-use super::changes::{Changes, DirectoryCreation, FileCreation};
+use super::changes::{Changes, DirectoryCreation, FileCreation, TOMLEdition};
+use crate::types::{Balance, ContractConfig, MainConfig};
+use bip39::Mnemonic;
+use clarity_repl::clarity::representations::ContractName;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::path::Path;
+
+/// Mnemonics of the deployer and of the first wallets of Development.toml,
+/// kept stable so that generated projects share the same dev accounts.
+const DEV_MNEMONICS: [&str; 10] = [
+    "fetch outside black test wash cover just actual execute nice door want airport betray quantum stamp fish act pen trust portion fatigue scissors vague",
+    "spoil sock coyote include verify comic jacket gain beauty tank flush victory illness edge reveal shallow plug hobby usual juice harsh pact wreck eight",
+    "arrange scale orient half ugly kid bike twin magnet joke hurt fiber ethics super receive version wreck media fluid much abstract reward street alter",
+    "glide clown kitchen picnic basket hidden asset beyond kid plug carbon talent drama wet pet rhythm hero nest purity baby bicycle ghost sponsor dragon",
+    "pulp when detect fun unaware reduce promote tank success lecture cool cheese object amazing hunt plug wing month hello tunnel detect connect floor brush",
+    "replace swing shove congress smoke banana tired term blanket nominee leave club myself swing egg virus answer bulk useful start decrease family energy february",
+    "apology together shy taxi glare struggle hip camp engage lion possible during squeeze hen exotic marriage misery kiwi once quiz enough exhibit immense tooth",
+    "antenna bitter find rely gadget father exact excuse cross easy elbow alcohol injury loud silk bird crime cabbage winter fit wide screen update october",
+    "east load echo merit ignore hip tag obvious truly adjust smart panther deer aisle north hotel process frown lock property catch bless notice topple",
+    "market ocean tortoise venue vivid coach machine category conduct enable insect jump fog file test core book chaos crucial burst version curious prosper fever",
+];
+
+/// Balance of the accounts generated in settings/Development.toml, unless
+/// `clarinet new --balance` says otherwise.
+pub const DEFAULT_ACCOUNT_BALANCE: Balance = Balance(1_000_000);
+
+/// Accounts generated in settings/Development.toml: a deployer, and `wallets`
+/// wallet accounts, all funded with `balance`.
+#[derive(Clone, Debug)]
+pub struct AccountsTemplate {
+    pub wallets: u32,
+    pub balance: Balance,
+}
+
+impl Default for AccountsTemplate {
+    fn default() -> Self {
+        AccountsTemplate {
+            wallets: 9,
+            balance: DEFAULT_ACCOUNT_BALANCE,
+        }
+    }
+}
+
+impl AccountsTemplate {
+    pub fn new(wallets: Option<u32>, balance: Option<Balance>) -> Result<AccountsTemplate, String> {
+        let default = AccountsTemplate::default();
+        let balance = balance.unwrap_or(default.balance);
+        // Sessions hold balances as u64
+        balance.to_u64().map_err(|e| format!("Error: invalid --balance: {}, the maximum is {}", e, u64::MAX))?;
+        Ok(AccountsTemplate {
+            wallets: wallets.unwrap_or(default.wallets),
+            balance,
+        })
+    }
+}
+
+/// Format a balance with `_` separators (`1_000_000`). Balances exceeding
+/// the signed 64 bits integers of TOML are written as strings.
+fn format_balance(balance: Balance) -> String {
+    let digits = balance.micro_stx().to_string();
+    if balance.micro_stx() > i64::MAX as u128 {
+        return format!("\"{}\"", digits);
+    }
+    let mut formatted = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            formatted.push('_');
+        }
+        formatted.push(c);
+    }
+    formatted
+}
+
+/// Clarinet.toml of a project registering `contracts`, by name, as contracts
+/// without dependencies.
+fn clarinet_toml(project_name: &str, contracts: &BTreeMap<String, String>) -> String {
+    let mut content = format!(
+        r#"
+[project]
+name = "{}"
+
+[contracts]
+"#,
+        project_name
+    );
+    for (name, path) in contracts.iter() {
+        content.push_str(&format!(
+            r#"
+[contracts.{}]
+path = "{}"
+depends_on = []
+"#,
+            name, path
+        ));
+    }
+    content.push_str("\n[notebooks]\n");
+    content
+}
+
+fn mocknet_toml() -> String {
+    format!(
+        r#"[network]
+name = "mocknet"
+node_url = "http://localhost:20443"
+
+[accounts.deployer]
+mnemonic = "point approve language letter cargo rough similar wrap focus edge polar task olympic tobacco cinnamon drop lawn boring sort trade senior screen tiger climb"
+"#
+    )
+}
+
+fn development_toml(accounts: &AccountsTemplate) -> String {
+    let mut content = format!(
+        r#"[network]
+name = "Development"
+"#
+    );
+    let balance = format_balance(accounts.balance);
+    for index in 0..=accounts.wallets as usize {
+        let name = match index {
+            0 => "deployer".to_string(),
+            index => format!("wallet_{}", index),
+        };
+        let mnemonic = match DEV_MNEMONICS.get(index) {
+            Some(mnemonic) => mnemonic.to_string(),
+            None => Mnemonic::from_entropy(&rand::random::<[u8; 32]>()).unwrap().to_string(),
+        };
+        content.push_str(&format!(
+            r#"
+[accounts.{}]
+mnemonic = "{}"
+balance = {}
+"#,
+            name, mnemonic, balance
+        ));
+    }
+    content
+}
+
+/// `.clar` files found under `root`, by contract name (their file name),
+/// with their path relative to `root`. Hidden directories and
+/// `node_modules` are not searched, and files whose name isn't a valid
+/// contract name are left out, with a warning.
+pub fn detect_contracts(root: &Path) -> Result<BTreeMap<String, String>, String> {
+    let mut contracts = BTreeMap::new();
+    let walker = walkdir::WalkDir::new(root)
+        .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+        .into_iter()
+        .filter_entry(|entry| {
+            let name = entry.file_name().to_string_lossy();
+            entry.depth() == 0 || !(name.starts_with('.') || (entry.file_type().is_dir() && name == "node_modules"))
+        });
+    for entry in walker.filter_map(|entry| entry.ok()) {
+        if !entry.file_type().is_file() || entry.path().extension().map_or(true, |extension| extension != "clar") {
+            continue;
+        }
+        let path = match entry.path().strip_prefix(root) {
+            Ok(path) => path.to_string_lossy().replace('\\', "/"),
+            Err(_) => continue,
+        };
+        let name = match entry.path().file_stem() {
+            Some(stem) => stem.to_string_lossy().to_string(),
+            None => continue,
+        };
+        if ContractName::try_from(name.clone()).is_err() {
+            warn!("Skipping {}: {} is not a valid contract name", path, name);
+            continue;
+        }
+        if let Some(other) = contracts.insert(name.clone(), path.clone()) {
+            return Err(format!("Error: {} and {} would both be named {}, rename one of them", other, path, name));
+        }
+    }
+    Ok(contracts)
+}
 
 pub struct GetChangesForNewProject {
     project_path: String,
     project_name: String,
+    accounts: AccountsTemplate,
     changes: Vec<Changes>,
 }
 
 impl GetChangesForNewProject {
-    pub fn new(project_path: String, project_name: String) -> Self {
+    pub fn new(project_path: String, project_name: String, accounts: AccountsTemplate) -> Self {
         Self {
             project_path,
             project_name,
+            accounts,
             changes: vec![],
         }
     }
@@ -93,17 +269,7 @@ impl GetChangesForNewProject {
     }
 
     fn create_clarinet_toml(&mut self) {
-        let content = format!(
-            r#"
-[project]
-name = "{}"
-
-[contracts]
-
-[notebooks]
-"#,
-            self.project_name
-        );
+        let content = clarinet_toml(&self.project_name, &BTreeMap::new());
         let name = format!("Clarinet.toml");
         let path = format!("{}/{}/{}", self.project_path, self.project_name, name);
         let change = FileCreation {
@@ -120,7 +286,7 @@ name = "{}"
         let content = format!(
             r#"[network]
 name = "mainnet"
-node_rpc_address = "http://stacks-node-api.blockstack.org:20443"
+node_url = "https://stacks-node-api.mainnet.stacks.co"
 "#
         );
         let name = format!("Mainnet.toml");
@@ -138,15 +304,7 @@ node_rpc_address = "http://stacks-node-api.blockstack.org:20443"
     }
 
     fn create_environment_mocknet_toml(&mut self) {
-        let content = format!(
-            r#"[network]
-name = "mocknet"
-node_rpc_address = "http://localhost:20443"
-
-[accounts.deployer]
-mnemonic = "point approve language letter cargo rough similar wrap focus edge polar task olympic tobacco cinnamon drop lawn boring sort trade senior screen tiger climb"
-"#
-        );
+        let content = mocknet_toml();
         let name = format!("Mocknet.toml");
         let path = format!(
             "{}/{}/settings/{}",
@@ -166,7 +324,7 @@ mnemonic = "point approve language letter cargo rough similar wrap focus edge po
         let content = format!(
             r#"[network]
 name = "testnet"
-node_rpc_address = "http://xenon.blockstack.org:20443"
+node_url = "https://stacks-node-api.testnet.stacks.co"
 "#
         );
         let name = format!("Testnet.toml");
@@ -185,51 +343,7 @@ node_rpc_address = "http://xenon.blockstack.org:20443"
 
 
     fn create_environment_dev_toml(&mut self) {
-        let content = format!(
-            r#"[network]
-name = "Development"
-
-[accounts.deployer]
-mnemonic = "fetch outside black test wash cover just actual execute nice door want airport betray quantum stamp fish act pen trust portion fatigue scissors vague"
-balance = 1_000_000
-
-[accounts.wallet_1]
-mnemonic = "spoil sock coyote include verify comic jacket gain beauty tank flush victory illness edge reveal shallow plug hobby usual juice harsh pact wreck eight"
-balance = 1_000_000
-
-[accounts.wallet_2]
-mnemonic = "arrange scale orient half ugly kid bike twin magnet joke hurt fiber ethics super receive version wreck media fluid much abstract reward street alter"
-balance = 1_000_000
-
-[accounts.wallet_3]
-mnemonic = "glide clown kitchen picnic basket hidden asset beyond kid plug carbon talent drama wet pet rhythm hero nest purity baby bicycle ghost sponsor dragon"
-balance = 1_000_000
-
-[accounts.wallet_4]
-mnemonic = "pulp when detect fun unaware reduce promote tank success lecture cool cheese object amazing hunt plug wing month hello tunnel detect connect floor brush"
-balance = 1_000_000
-
-[accounts.wallet_5]
-mnemonic = "replace swing shove congress smoke banana tired term blanket nominee leave club myself swing egg virus answer bulk useful start decrease family energy february"
-balance = 1_000_000
-
-[accounts.wallet_6]
-mnemonic = "apology together shy taxi glare struggle hip camp engage lion possible during squeeze hen exotic marriage misery kiwi once quiz enough exhibit immense tooth"
-balance = 1_000_000
-
-[accounts.wallet_7]
-mnemonic = "antenna bitter find rely gadget father exact excuse cross easy elbow alcohol injury loud silk bird crime cabbage winter fit wide screen update october"
-balance = 1_000_000
-
-[accounts.wallet_8]
-mnemonic = "east load echo merit ignore hip tag obvious truly adjust smart panther deer aisle north hotel process frown lock property catch bless notice topple"
-balance = 1_000_000
-
-[accounts.wallet_9]
-mnemonic = "market ocean tortoise venue vivid coach machine category conduct enable insect jump fog file test core book chaos crucial burst version curious prosper fever"
-balance = 1_000_000
-"#
-        );
+        let content = development_toml(&self.accounts);
         let name = format!("Development.toml");
         let path = format!(
             "{}/{}/settings/{}",
@@ -254,3 +368,193 @@ balance = 1_000_000
         Changes::AddDirectory(change)
     }
 }
+
+/// Changes turning an existing directory of contracts, `project_path`, into
+/// a project: a Clarinet.toml registering the contracts detected, and the
+/// settings of a new project. Existing files are kept, the contracts missing
+/// from an existing Clarinet.toml being added to it.
+pub struct GetChangesForExistingProject {
+    project_path: String,
+    accounts: AccountsTemplate,
+    changes: Vec<Changes>,
+}
+
+impl GetChangesForExistingProject {
+    pub fn new(project_path: String, accounts: AccountsTemplate) -> Self {
+        Self {
+            project_path,
+            accounts,
+            changes: vec![],
+        }
+    }
+
+    pub fn run(&mut self, contracts: &BTreeMap<String, String>) -> Result<Vec<Changes>, String> {
+        self.index_contracts_in_clarinet_toml(contracts)?;
+        self.create_settings_directory();
+        self.create_file_if_missing("settings/Development.toml", development_toml(&self.accounts));
+        self.create_file_if_missing("settings/Mocknet.toml", mocknet_toml());
+        Ok(self.changes.clone())
+    }
+
+    fn index_contracts_in_clarinet_toml(&mut self, contracts: &BTreeMap<String, String>) -> Result<(), String> {
+        let manifest_path = format!("{}/Clarinet.toml", self.project_path);
+        if !Path::new(&manifest_path).exists() {
+            let project_name = Path::new(&self.project_path)
+                .file_name()
+                .map_or_else(|| "project".to_string(), |name| name.to_string_lossy().to_string());
+            self.create_file_if_missing("Clarinet.toml", clarinet_toml(&project_name, contracts));
+            return Ok(());
+        }
+
+        let registered = MainConfig::from_path(Path::new(&manifest_path))?.contracts.unwrap_or_default();
+        let contracts_to_add: HashMap<String, ContractConfig> = contracts
+            .iter()
+            .filter(|(name, path)| {
+                !registered.contains_key(*name) && !registered.values().any(|contract| &contract.path == *path)
+            })
+            .map(|(name, path)| {
+                let contract_config = ContractConfig {
+                    path: path.clone(),
+                    derived_path: false,
+                    depends_on: vec![],
+                    environments: None,
+                    excluded_environments: None,
+                    tags: None,
+                    description: None,
+                    post_deploy: None,
+                    initial_funding: None,
+                    extra_keys: BTreeMap::new(),
+                };
+                (name.clone(), contract_config)
+            })
+            .collect();
+        if !contracts_to_add.is_empty() {
+            self.changes.push(Changes::EditTOML(TOMLEdition {
+                comment: format!("Adding {} contract(s) to Clarinet.toml", contracts_to_add.len()),
+                path: manifest_path,
+                contracts_to_add,
+                links_to_add: vec![],
+            }));
+        }
+        Ok(())
+    }
+
+    fn create_settings_directory(&mut self) {
+        let path = format!("{}/settings", self.project_path);
+        if Path::new(&path).is_dir() {
+            return;
+        }
+        self.changes.push(Changes::AddDirectory(DirectoryCreation {
+            comment: "Creating directory settings".to_string(),
+            name: "settings".to_string(),
+            path,
+        }));
+    }
+
+    fn create_file_if_missing(&mut self, name: &str, content: String) {
+        let path = format!("{}/{}", self.project_path, name);
+        if Path::new(&path).exists() {
+            info!("Keeping the existing {}", name);
+            return;
+        }
+        self.changes.push(Changes::AddFile(FileCreation {
+            comment: format!("Creating file {}", name),
+            name: name.rsplit('/').next().unwrap_or(name).to_string(),
+            content,
+            path,
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ChainConfig;
+    use clarity_repl::repl::{settings::Account, Session, SessionSettings};
+
+    fn development_settings(accounts: AccountsTemplate) -> ChainConfig {
+        let mut command = GetChangesForNewProject::new("/tmp".to_string(), "project".to_string(), accounts);
+        let content = command
+            .run()
+            .into_iter()
+            .find_map(|change| match change {
+                Changes::AddFile(file) if file.name == "Development.toml" => Some(file.content),
+                _ => None,
+            })
+            .unwrap();
+        let config_file = toml::from_str(&content).unwrap();
+        ChainConfig::from_config_file(config_file).unwrap()
+    }
+
+    #[test]
+    fn generated_accounts_seed_a_session() {
+        let accounts = AccountsTemplate::new(Some(2), Some(Balance(5_000_000))).unwrap();
+        let config = development_settings(accounts);
+        assert_eq!(config.accounts.len(), 3);
+
+        let mut settings = SessionSettings::default();
+        for (name, account) in config.accounts.iter() {
+            assert_eq!(account.balance, 5_000_000);
+            settings.initial_accounts.push(Account {
+                name: name.clone(),
+                balance: account.balance,
+                address: account.address.clone(),
+                mnemonic: account.mnemonic.clone(),
+                derivation: account.derivation.clone(),
+            });
+        }
+        let mut session = Session::new(settings);
+        session.start();
+        let snippet = format!("(stx-get-balance '{})", config.accounts["wallet_2"].address);
+        let result = session.interpret(snippet, None).unwrap_or_else(|(message, _)| panic!("{}", message));
+        assert_eq!(result.result, Some("u5000000".to_string()));
+    }
+
+    #[test]
+    fn existing_contracts_are_registered_without_overwriting_files() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let root = tempdir.path();
+        std::fs::create_dir_all(root.join("src/tokens")).unwrap();
+        std::fs::create_dir_all(root.join(".clarinet")).unwrap();
+        std::fs::create_dir_all(root.join("settings")).unwrap();
+        std::fs::write(root.join("src/market.clar"), "").unwrap();
+        std::fs::write(root.join("src/tokens/token.clar"), "").unwrap();
+        std::fs::write(root.join(".clarinet/cached.clar"), "").unwrap();
+        std::fs::write(root.join("settings/Development.toml"), "").unwrap();
+
+        let project_path = root.to_string_lossy().to_string();
+        let res = detect_contracts(root).and_then(|contracts| {
+            let changes = GetChangesForExistingProject::new(project_path.clone(), AccountsTemplate::default()).run(&contracts)?;
+            Ok((contracts, changes))
+        });
+        let (contracts, changes) = res.unwrap();
+
+        assert_eq!(contracts.keys().collect::<Vec<_>>(), vec!["market", "token"]);
+        assert_eq!(contracts["token"], "src/tokens/token.clar");
+        let created: Vec<String> = changes
+            .iter()
+            .map(|change| match change {
+                Changes::AddFile(file) => file.name.clone(),
+                change => panic!("unexpected change {:?}", change),
+            })
+            .collect();
+        assert_eq!(created, vec!["Clarinet.toml", "Mocknet.toml"]);
+
+        let manifest = match &changes[0] {
+            Changes::AddFile(file) => file.content.clone(),
+            _ => unreachable!(),
+        };
+        let config = MainConfig::from_config_file(toml::from_str(&manifest).unwrap()).unwrap();
+        let registered = config.contracts.unwrap();
+        assert_eq!(registered["market"].path, "src/market.clar");
+        assert!(registered["token"].depends_on.is_empty());
+    }
+
+    #[test]
+    fn balances_beyond_toml_integers_are_written_as_strings() {
+        let balance = Balance(u128::from(u64::MAX));
+        let config = development_settings(AccountsTemplate::new(Some(1), Some(balance)).unwrap());
+        assert_eq!(config.accounts["deployer"].balance, u64::MAX);
+        assert!(AccountsTemplate::new(None, Some(Balance(u128::from(u64::MAX) + 1))).is_err());
+    }
+}
@@ -0,0 +1,56 @@
+use std::str::FromStr;
+use clarity_repl::clarity::types::PrincipalData;
+
+/// Principal substitution applied to the source of a forked contract
+/// (`--remap <address>=<account>`). The target is either the name of an
+/// account of the settings, or an address.
+#[derive(Debug, Clone)]
+pub struct PrincipalRemap {
+    pub from: String,
+    pub to: String,
+}
+
+impl FromStr for PrincipalRemap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (from, to) = match s.find('=') {
+            Some(index) => (&s[..index], &s[index + 1..]),
+            None => return Err(format!("invalid remap '{}': expected <address>=<account>", s)),
+        };
+        if PrincipalData::parse_standard_principal(from).is_err() {
+            return Err(format!("invalid remap '{}': '{}' is not a valid Stacks address", s, from));
+        }
+        if to.is_empty() {
+            return Err(format!("invalid remap '{}': missing account", s));
+        }
+        Ok(PrincipalRemap {
+            from: from.to_string(),
+            to: to.to_string(),
+        })
+    }
+}
+
+/// Replace the occurrences of the address `from` by `to` in `source`, leaving
+/// longer addresses starting with `from` untouched. Returns the new source and
+/// the number of substitutions.
+pub fn remap_principal(source: &str, from: &str, to: &str) -> (String, usize) {
+    let mut remapped = String::with_capacity(source.len());
+    let mut count = 0;
+    let mut rest = source;
+    while let Some(index) = rest.find(from) {
+        let before = if index > 0 { rest[..index].chars().last() } else { remapped.chars().last() };
+        let after = rest[index + from.len()..].chars().next();
+        let is_token = |c: Option<char>| c.map_or(true, |c| !c.is_ascii_alphanumeric());
+        remapped.push_str(&rest[..index]);
+        if is_token(before) && is_token(after) {
+            remapped.push_str(to);
+            count += 1;
+        } else {
+            remapped.push_str(from);
+        }
+        rest = &rest[index + from.len()..];
+    }
+    remapped.push_str(rest);
+    (remapped, count)
+}
@@ -0,0 +1,142 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Standard traits that `clarinet contract new --trait` can scaffold.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KnownTrait {
+    Sip009,
+    Sip010,
+}
+
+impl KnownTrait {
+    pub const ALL: &'static [KnownTrait] = &[KnownTrait::Sip009, KnownTrait::Sip010];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            KnownTrait::Sip009 => "sip009",
+            KnownTrait::Sip010 => "sip010",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            KnownTrait::Sip009 => "SIP-009 non-fungible token",
+            KnownTrait::Sip010 => "SIP-010 fungible token",
+        }
+    }
+
+    /// Contract defining the trait, linked in Clarinet.toml so that the
+    /// generated `impl-trait` can be resolved.
+    pub fn contract_id(&self) -> &'static str {
+        match self {
+            KnownTrait::Sip009 => "SP2PABAF9FTAJYNFZH93XENAJ8FVY99RRM50D2JG9.nft-trait",
+            KnownTrait::Sip010 => "SP3FBR2AGK5H9QBDH3EEN6DF8EK8JY7RKQQ8PTN5V.sip-010-trait-ft-standard",
+        }
+    }
+
+    pub fn contract_template(&self, contract_name: &str) -> String {
+        match self {
+            KnownTrait::Sip009 => format!(
+                r#"
+;; {name}
+;; SIP-009 non-fungible token
+
+(impl-trait '{contract_id}.nft-trait)
+
+;; constants
+;;
+(define-constant err-not-token-owner (err u100))
+
+;; data maps and vars
+;;
+(define-non-fungible-token {name} uint)
+(define-data-var last-token-id uint u0)
+
+;; public functions
+;;
+(define-read-only (get-last-token-id)
+    (ok (var-get last-token-id)))
+
+(define-read-only (get-token-uri (token-id uint))
+    (ok none))
+
+(define-read-only (get-owner (token-id uint))
+    (ok (nft-get-owner? {name} token-id)))
+
+(define-public (transfer (token-id uint) (sender principal) (recipient principal))
+    (begin
+        (asserts! (is-eq tx-sender sender) err-not-token-owner)
+        (nft-transfer? {name} token-id sender recipient)))
+"#,
+                name = contract_name,
+                contract_id = self.contract_id()
+            ),
+            KnownTrait::Sip010 => format!(
+                r#"
+;; {name}
+;; SIP-010 fungible token
+
+(impl-trait '{contract_id}.sip-010-trait)
+
+;; constants
+;;
+(define-constant err-not-token-owner (err u100))
+
+;; data maps and vars
+;;
+(define-fungible-token {name})
+
+;; public functions
+;;
+(define-public (transfer (amount uint) (sender principal) (recipient principal) (memo (optional (buff 34))))
+    (begin
+        (asserts! (is-eq tx-sender sender) err-not-token-owner)
+        (ft-transfer? {name} amount sender recipient)))
+
+(define-read-only (get-name)
+    (ok "{name}"))
+
+(define-read-only (get-symbol)
+    (ok "TKN"))
+
+(define-read-only (get-decimals)
+    (ok u6))
+
+(define-read-only (get-balance (who principal))
+    (ok (ft-get-balance {name} who)))
+
+(define-read-only (get-total-supply)
+    (ok (ft-get-supply {name})))
+
+(define-read-only (get-token-uri)
+    (ok none))
+"#,
+                name = contract_name,
+                contract_id = self.contract_id()
+            ),
+        }
+    }
+}
+
+impl FromStr for KnownTrait {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        for known_trait in KnownTrait::ALL.iter() {
+            if known_trait.name() == s.to_lowercase() {
+                return Ok(*known_trait);
+            }
+        }
+        let known: Vec<String> = KnownTrait::ALL
+            .iter()
+            .map(|t| format!("  {}\t{}", t.name(), t.description()))
+            .collect();
+        Err(format!("unknown trait '{}', expected one of:\n{}", s, known.join("\n")))
+    }
+}
+
+impl fmt::Display for KnownTrait {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
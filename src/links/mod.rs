@@ -0,0 +1,387 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+use clarity_repl::clarity::types::StandardPrincipalData;
+use clarity_repl::repl::ClarityInterpreter;
+use crate::utils::DEFAULT_CACHE_DIR;
+
+fn default_node_for(contract_id: &str) -> &'static str {
+    if contract_id.starts_with("SP") {
+        "https://stacks-node-api.mainnet.stacks.co"
+    } else {
+        "https://stacks-node-api.testnet.stacks.co"
+    }
+}
+
+/// Number of attempts made to retrieve a linked contract, and the delay
+/// between two attempts, before giving up on the node.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub attempts: u32,
+    pub delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            attempts: 3,
+            delay: Duration::from_secs(1),
+        }
+    }
+}
+
+impl RetryPolicy {
+    fn run<T>(&self, mut attempt: impl FnMut() -> Result<T, String>) -> Result<T, String> {
+        let mut remaining = self.attempts.max(1);
+        loop {
+            remaining -= 1;
+            match attempt() {
+                Ok(value) => return Ok(value),
+                Err(e) if remaining > 0 => {
+                    warn!("{}, retrying in {}s ({} attempt(s) left)", e, self.delay.as_secs(), remaining);
+                    thread::sleep(self.delay);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// Cached copy of `contract_id`, under `.clarinet/links/<block_height>/` for
+/// a pinned link, or `.clarinet/links/tip/` for a link following the chain tip.
+fn cache_path(root: &Path, contract_id: &str, block_height: Option<u64>) -> PathBuf {
+    let dir = block_height.map_or_else(|| "tip".to_string(), |block_height| block_height.to_string());
+    root.join(DEFAULT_CACHE_DIR)
+        .join("links")
+        .join(dir)
+        .join(format!("{}.clar", contract_id))
+}
+
+fn write_cache(path: &Path, code: &str) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Error: unable to create {:?}: {}", dir, e))?;
+    }
+    fs::write(path, code)
+        .map_err(|e| format!("Error: unable to write {:?}: {}", path, e))
+}
+
+/// Index block hash of the block mined at `block_height`, as expected by the
+/// `tip` parameter of the node's RPC endpoints.
+fn index_block_hash_at(node: &str, block_height: u64) -> Result<String, String> {
+    #[derive(Deserialize, Debug)]
+    struct Block {
+        index_block_hash: String,
+    }
+
+    let url = format!("{}/extended/v1/block/by_height/{}", node, block_height);
+    let block: Block = reqwest::blocking::get(&url)
+        .and_then(|response| response.error_for_status())
+        .and_then(|response| response.json())
+        .map_err(|e| format!("Error: unable to retrieve block {} from {}: {}", block_height, node, e))?;
+    Ok(block.index_block_hash.trim_start_matches("0x").to_string())
+}
+
+fn fetch_contract_source(node: &str, contract_id: &str, tip: Option<&str>) -> Result<String, String> {
+    #[derive(Deserialize, Debug)]
+    struct Contract {
+        source: String,
+    }
+
+    let components: Vec<&str> = contract_id.split('.').collect();
+    let (address, name) = match components.as_slice() {
+        [address, name] => (address, name),
+        _ => return Err(format!("Error: invalid contract id {}", contract_id)),
+    };
+    let mut url = format!(
+        "{host}/v2/contracts/source/{addr}/{name}?proof=0",
+        host = node,
+        addr = address,
+        name = name,
+    );
+    if let Some(tip) = tip {
+        url.push_str(&format!("&tip={}", tip));
+    }
+    let contract: Contract = reqwest::blocking::get(&url)
+        .and_then(|response| response.error_for_status())
+        .and_then(|response| response.json())
+        .map_err(|e| format!("Error: unable to retrieve contract {}: {}", contract_id, e))?;
+    Ok(contract.source)
+}
+
+/// Dependency of a link which couldn't be retrieved, nor loaded from the cache.
+#[derive(Debug, Clone, Serialize)]
+pub struct UnresolvedContract {
+    pub contract_id: String,
+    pub reason: String,
+}
+
+/// Contracts retrieved by `resolve_link`, along with the dependencies it
+/// couldn't retrieve.
+#[derive(Debug, Clone, Default)]
+pub struct LinkResolution {
+    /// `(contract_id, code, dependencies)`, in deployment order
+    pub contracts: Vec<(String, String, Vec<String>)>,
+    pub unresolved: Vec<UnresolvedContract>,
+}
+
+/// Fetch `contract_id` and every contract it depends on, as they are at the
+/// chain tip, returning `(contract_id, code, dependencies)` in deployment
+/// order (dependencies first). Each retrieval is retried according to
+/// `retries`; when a contract can't be retrieved, the copy cached by a
+/// previous resolution under `.clarinet/links/tip/` in `root` is used instead,
+/// with a warning.
+///
+/// The dependencies which can't be retrieved either way are reported in
+/// `unresolved`, and the resolution goes on with the others: only failing to
+/// retrieve `contract_id` itself is an error.
+pub fn resolve_link(
+    root: &Path,
+    contract_id: &str,
+    retries: &RetryPolicy,
+) -> Result<LinkResolution, String> {
+    let node = default_node_for(contract_id);
+    resolve_dependencies_partially(contract_id, |contract_id| {
+        info!("Retrieving {}", contract_id);
+        fetch_or_read_cache(&cache_path(root, contract_id, None), || {
+            retries.run(|| fetch_contract_source(node, contract_id, None))
+        })
+    })
+}
+
+/// Source returned by `fetch`, stored in the cache at `path`, or the copy
+/// cached at `path` when `fetch` fails.
+fn fetch_or_read_cache(path: &Path, fetch: impl FnOnce() -> Result<String, String>) -> Result<String, String> {
+    match fetch() {
+        Ok(code) => {
+            if let Err(e) = write_cache(path, &code) {
+                warn!("{}", e);
+            }
+            Ok(code)
+        }
+        Err(e) => match fs::read_to_string(path) {
+            Ok(code) => {
+                warn!("{}: using the copy cached in {}, which may be outdated", e, path.display());
+                Ok(code)
+            }
+            Err(_) => Err(e),
+        },
+    }
+}
+
+/// Same as `resolve_link`, except that the copies cached under
+/// `.clarinet/links/tip/` in `root` are used when present, the node only
+/// being queried for the contracts missing from the cache. Used by the links
+/// declared with `cache = true`.
+pub fn resolve_cached_link(
+    root: &Path,
+    contract_id: &str,
+    retries: &RetryPolicy,
+) -> Result<Vec<(String, String)>, String> {
+    let node = default_node_for(contract_id);
+    let resolved = resolve_dependencies(contract_id, |contract_id| {
+        let path = cache_path(root, contract_id, None);
+        if let Ok(code) = fs::read_to_string(&path) {
+            return Ok(code);
+        }
+        info!("Retrieving {}", contract_id);
+        let code = retries.run(|| fetch_contract_source(node, contract_id, None))?;
+        write_cache(&path, &code)?;
+        Ok(code)
+    })?;
+    Ok(resolved.into_iter().map(|(contract_id, code, _)| (contract_id, code)).collect())
+}
+
+/// Fetch `contract_id` and every contract it depends on, as they were at
+/// `block_height`, returning `(contract_id, code)` pairs in deployment order
+/// (dependencies first). Sources are cached under `.clarinet/links/<block_height>/`
+/// in `root`, so a pinned link is only downloaded once.
+pub fn resolve_link_at_height(
+    root: &Path,
+    contract_id: &str,
+    block_height: u64,
+    retries: &RetryPolicy,
+) -> Result<Vec<(String, String)>, String> {
+    let node = default_node_for(contract_id);
+    let mut tip = None;
+    let resolved = resolve_dependencies(contract_id, |contract_id| {
+        let path = cache_path(root, contract_id, Some(block_height));
+        if let Ok(code) = fs::read_to_string(&path) {
+            return Ok(code);
+        }
+        if tip.is_none() {
+            tip = Some(retries.run(|| index_block_hash_at(node, block_height))?);
+        }
+        info!("Retrieving {} at block height {}", contract_id, block_height);
+        let code = retries.run(|| fetch_contract_source(node, contract_id, tip.as_deref()))?;
+        write_cache(&path, &code)?;
+        Ok(code)
+    })?;
+    Ok(resolved.into_iter().map(|(contract_id, code, _)| (contract_id, code)).collect())
+}
+
+/// Load `contract_id` and the contracts it depends on with `load`, returning
+/// `(contract_id, code, dependencies)` in deployment order.
+fn resolve_dependencies(
+    contract_id: &str,
+    load: impl FnMut(&str) -> Result<String, String>,
+) -> Result<Vec<(String, String, Vec<String>)>, String> {
+    let resolution = load_dependencies(contract_id, load, false)?;
+    Ok(resolution.contracts)
+}
+
+/// Same as `resolve_dependencies`, except that the dependencies failing to
+/// load are reported as unresolved instead of failing the resolution.
+fn resolve_dependencies_partially(
+    contract_id: &str,
+    load: impl FnMut(&str) -> Result<String, String>,
+) -> Result<LinkResolution, String> {
+    load_dependencies(contract_id, load, true)
+}
+
+fn load_dependencies(
+    root_contract_id: &str,
+    mut load: impl FnMut(&str) -> Result<String, String>,
+    partial: bool,
+) -> Result<LinkResolution, String> {
+    let interpreter = ClarityInterpreter::new(StandardPrincipalData::transient());
+    let mut sources = BTreeMap::new();
+    let mut resolved: Vec<(String, String, Vec<String>)> = Vec::new();
+    // Depth first: a contract is pushed back on the stack behind its
+    // dependencies, and emitted once they all have been.
+    let mut stack = vec![(root_contract_id.to_string(), false)];
+    let mut unresolved = vec![];
+
+    while let Some((contract_id, expanded)) = stack.pop() {
+        if expanded {
+            if !resolved.iter().any(|(id, _, _)| id == &contract_id) {
+                let (code, dependencies) = &sources[&contract_id];
+                resolved.push((contract_id, code.clone(), dependencies.clone()));
+            }
+            continue;
+        }
+        if sources.contains_key(&contract_id) || unresolved.iter().any(|u: &UnresolvedContract| u.contract_id == contract_id) {
+            continue;
+        }
+
+        let loaded = load(&contract_id)
+            .and_then(|code| Ok((code.clone(), interpreter.detect_dependencies(contract_id.clone(), code)?)));
+        let (code, dependencies) = match loaded {
+            Ok(loaded) => loaded,
+            Err(reason) if partial && contract_id != root_contract_id => {
+                warn!("Unable to resolve {}: {}", contract_id, reason);
+                unresolved.push(UnresolvedContract { contract_id, reason });
+                continue;
+            }
+            Err(reason) => return Err(reason),
+        };
+        sources.insert(contract_id.clone(), (code, dependencies.iter().cloned().collect()));
+        stack.push((contract_id, true));
+        for dependency in dependencies.into_iter() {
+            if !sources.contains_key(&dependency) {
+                stack.push((dependency, false));
+            }
+        }
+    }
+
+    Ok(LinkResolution {
+        contracts: resolved,
+        unresolved,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[test]
+    fn failed_attempts_are_retried_after_the_delay() {
+        let retries = RetryPolicy { attempts: 3, delay: Duration::from_millis(20) };
+        let mut calls = 0;
+        let start = Instant::now();
+        let res = retries.run(|| {
+            calls += 1;
+            if calls < 3 {
+                Err("Error: node unreachable".to_string())
+            } else {
+                Ok(calls)
+            }
+        });
+        assert_eq!(res, Ok(3));
+        assert!(start.elapsed() >= Duration::from_millis(40));
+    }
+
+    #[test]
+    fn retries_give_up_after_the_last_attempt() {
+        let retries = RetryPolicy { attempts: 3, delay: Duration::from_millis(1) };
+        let mut calls = 0;
+        let res: Result<(), String> = retries.run(|| {
+            calls += 1;
+            Err(format!("Error: attempt {} failed", calls))
+        });
+        assert_eq!(res, Err("Error: attempt 3 failed".to_string()));
+        assert_eq!(calls, 3);
+
+        // A policy without attempts still tries once.
+        let retries = RetryPolicy { attempts: 0, delay: Duration::from_millis(1) };
+        let mut calls = 0;
+        let res: Result<(), String> = retries.run(|| {
+            calls += 1;
+            Err("Error: node unreachable".to_string())
+        });
+        assert!(res.is_err());
+        assert_eq!(calls, 1);
+    }
+
+    const TOKEN: &str = "ST1PQHQKV0RJXZFY1DGX8MNSNYVE3VGZJSRTPGZGM.token";
+    const ORACLE: &str = "ST1PQHQKV0RJXZFY1DGX8MNSNYVE3VGZJSRTPGZGM.oracle";
+    const MARKET: &str = "ST1PQHQKV0RJXZFY1DGX8MNSNYVE3VGZJSRTPGZGM.market";
+
+    fn calling(contract_ids: &[&str]) -> String {
+        let calls: Vec<String> = contract_ids
+            .iter()
+            .map(|contract_id| format!("(contract-call? '{} get-value)", contract_id))
+            .collect();
+        format!("(define-public (run) (begin {} (ok true)))", calls.join(" "))
+    }
+
+    #[test]
+    fn cached_copies_are_used_when_the_node_is_unreachable() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = cache_path(tempdir.path(), TOKEN, None);
+        let unreachable = || Err("Error: unable to retrieve contract".to_string());
+
+        assert!(fetch_or_read_cache(&path, unreachable).is_err());
+        assert_eq!(fetch_or_read_cache(&path, || Ok("(define-data-var v int 1)".to_string())).unwrap(), "(define-data-var v int 1)");
+        assert_eq!(fetch_or_read_cache(&path, unreachable).unwrap(), "(define-data-var v int 1)");
+
+        // Links with `cache = true` don't query the node for cached contracts.
+        write_cache(&cache_path(tempdir.path(), MARKET, None), &calling(&[TOKEN])).unwrap();
+        write_cache(&cache_path(tempdir.path(), TOKEN, None), "(define-read-only (get-value) (ok u1))").unwrap();
+        let resolved = resolve_cached_link(tempdir.path(), MARKET, &RetryPolicy::default()).unwrap();
+        let contract_ids: Vec<&str> = resolved.iter().map(|(contract_id, _)| contract_id.as_str()).collect();
+        assert_eq!(contract_ids, vec![TOKEN, MARKET]);
+    }
+
+    #[test]
+    fn unreachable_dependencies_are_reported_in_partial_resolutions() {
+        let load = |contract_id: &str| match contract_id {
+            MARKET => Ok(calling(&[TOKEN, ORACLE])),
+            TOKEN => Ok("(define-read-only (get-value) (ok u1))".to_string()),
+            _ => Err("Error: node unreachable".to_string()),
+        };
+
+        let resolution = resolve_dependencies_partially(MARKET, load).unwrap();
+        let contract_ids: Vec<&str> = resolution.contracts.iter().map(|(contract_id, _, _)| contract_id.as_str()).collect();
+        assert_eq!(contract_ids, vec![TOKEN, MARKET]);
+        assert_eq!(resolution.unresolved.len(), 1);
+        assert_eq!(resolution.unresolved[0].contract_id, ORACLE);
+        assert_eq!(resolution.unresolved[0].reason, "Error: node unreachable");
+
+        assert!(resolve_dependencies(MARKET, load).is_err());
+        assert!(resolve_dependencies_partially(ORACLE, load).is_err());
+    }
+}
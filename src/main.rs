@@ -6,8 +6,14 @@ extern crate serde_derive;
 #[macro_use]
 extern crate lazy_static;
 
+#[macro_use]
+extern crate log;
+
+mod analysis;
+mod doctor;
 mod frontend;
 mod generators;
+mod links;
 mod publish;
 mod types;
 mod utils;
@@ -0,0 +1,81 @@
+/// How the fee of a contract-publish transaction is computed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FeeStrategy {
+    /// 200 µSTX plus 1 µSTX per byte of contract source.
+    Fixed,
+    /// Fee rate (µSTX per byte) returned by the node, times the transaction length.
+    Estimate,
+    /// Fee given on the command line, used for every transaction.
+    Manual(u64),
+}
+
+impl Default for FeeStrategy {
+    fn default() -> Self {
+        FeeStrategy::Fixed
+    }
+}
+
+impl FeeStrategy {
+    pub fn from_options(strategy: &str, fee: Option<u64>) -> Result<FeeStrategy, String> {
+        match (strategy, fee) {
+            ("fixed", None) => Ok(FeeStrategy::Fixed),
+            ("estimate", None) => Ok(FeeStrategy::Estimate),
+            ("manual", Some(fee)) => Ok(FeeStrategy::Manual(fee)),
+            ("manual", None) => Err("Error: --fee-strategy manual requires --fee".to_string()),
+            ("fixed", Some(_)) | ("estimate", Some(_)) => {
+                Err("Error: --fee can only be used with --fee-strategy manual".to_string())
+            }
+            (strategy, _) => Err(format!(
+                "Error: unknown fee strategy {}, expected fixed, estimate or manual",
+                strategy
+            )),
+        }
+    }
+}
+
+/// Fee of a transaction of `tx_len` bytes publishing `code`. `fee_rate` is
+/// only called by the strategies relying on the node.
+pub fn compute_fee<F>(strategy: &FeeStrategy, code: &str, tx_len: usize, fee_rate: F) -> Result<u64, String>
+where
+    F: FnOnce() -> Result<u64, String>,
+{
+    match strategy {
+        FeeStrategy::Fixed => Ok(200 + code.len() as u64),
+        FeeStrategy::Estimate => Ok(fee_rate()? * tx_len as u64),
+        FeeStrategy::Manual(fee) => Ok(*fee),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unreachable_node() -> Result<u64, String> {
+        panic!("fee rate should not be requested")
+    }
+
+    #[test]
+    fn fixed_fee_depends_on_code_length() {
+        let fee = compute_fee(&FeeStrategy::Fixed, "(ok u1)", 1000, unreachable_node).unwrap();
+        assert_eq!(fee, 207);
+    }
+
+    #[test]
+    fn estimated_fee_uses_node_fee_rate() {
+        let fee = compute_fee(&FeeStrategy::Estimate, "(ok u1)", 150, || Ok(2)).unwrap();
+        assert_eq!(fee, 300);
+    }
+
+    #[test]
+    fn manual_fee_is_used_as_is() {
+        let fee = compute_fee(&FeeStrategy::Manual(5000), "(ok u1)", 150, unreachable_node).unwrap();
+        assert_eq!(fee, 5000);
+    }
+
+    #[test]
+    fn manual_strategy_requires_fee() {
+        assert!(FeeStrategy::from_options("manual", None).is_err());
+        assert_eq!(FeeStrategy::from_options("manual", Some(10)), Ok(FeeStrategy::Manual(10)));
+        assert!(FeeStrategy::from_options("fixed", Some(10)).is_err());
+    }
+}
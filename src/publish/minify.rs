@@ -0,0 +1,107 @@
+use clarity_repl::clarity::ast::parser;
+use clarity_repl::clarity::representations::{PreSymbolicExpression, PreSymbolicExpressionType, Span};
+
+/// Source of a contract without its comments, nor the whitespace the parser
+/// doesn't need: runs of whitespace are collapsed into a single space, left
+/// out after an opening delimiter or a comma, and before a closing delimiter,
+/// a comma or a colon. String literals are copied as is.
+///
+/// The minified source is parsed, and rejected unless it yields the same
+/// expressions as `code`.
+pub fn minify(code: &str) -> Result<String, String> {
+    let mut minified = String::with_capacity(code.len());
+    let mut separated = false;
+    let mut chars = code.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' | '\t' | '\n' | '\r' => {
+                separated = true;
+                continue;
+            }
+            ';' if chars.peek() == Some(&';') => {
+                while chars.peek().map_or(false, |c| *c != '\n') {
+                    chars.next();
+                }
+                separated = true;
+                continue;
+            }
+            _ => {}
+        }
+        if separated && needs_separator(minified.chars().last(), c) {
+            minified.push(' ');
+        }
+        separated = false;
+        minified.push(c);
+        if c == '"' {
+            while let Some(c) = chars.next() {
+                minified.push(c);
+                match c {
+                    '\\' => {
+                        if let Some(escaped) = chars.next() {
+                            minified.push(escaped);
+                        }
+                    }
+                    '"' => break,
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    let expressions = parse_without_spans(code)?;
+    if parse_without_spans(&minified)? != expressions {
+        return Err("Error: the minified source differs from the original one".to_string());
+    }
+    Ok(minified)
+}
+
+/// Whether whitespace must be kept between `previous` and `next`.
+fn needs_separator(previous: Option<char>, next: char) -> bool {
+    match previous {
+        None | Some('(') | Some('{') | Some(',') => false,
+        Some(_) => !matches!(next, ')' | '}' | ',' | ':'),
+    }
+}
+
+fn parse_without_spans(code: &str) -> Result<Vec<PreSymbolicExpression>, String> {
+    let mut expressions = parser::parse(code).map_err(|e| format!("Error: unable to parse the source: {}", e))?;
+    clear_spans(&mut expressions);
+    Ok(expressions)
+}
+
+fn clear_spans(expressions: &mut [PreSymbolicExpression]) {
+    for expression in expressions.iter_mut() {
+        expression.span = Span::zero();
+        match expression.pre_expr {
+            PreSymbolicExpressionType::List(ref mut items) | PreSymbolicExpressionType::Tuple(ref mut items) => {
+                clear_spans(items)
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn comments_and_whitespace_are_stripped() {
+        let code = ";; counter\n(define-data-var count uint u0) ;; starts at 0\n\n(define-public (increment)\n  (begin\n    (var-set count (+ (var-get count) u1))\n    (ok { count: (var-get count), by: tx-sender })))\n";
+        assert_eq!(
+            minify(code).unwrap(),
+            "(define-data-var count uint u0) (define-public (increment) (begin (var-set count (+ (var-get count) u1)) (ok {count: (var-get count),by: tx-sender})))"
+        );
+    }
+
+    #[test]
+    fn string_literals_are_preserved() {
+        let code = "(print \"a  ;; b\")\n(print u\"say \\\"hi\\\"  \")";
+        assert_eq!(minify(code).unwrap(), "(print \"a  ;; b\") (print u\"say \\\"hi\\\"  \")");
+    }
+
+    #[test]
+    fn invalid_sources_are_rejected() {
+        assert!(minify("(print \"unterminated)").is_err());
+    }
+}
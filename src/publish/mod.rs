@@ -1 +1,1099 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::outputln;
+use crate::types::{Balance, ContractCall, Network};
+use crate::utils::hash::source_hash;
+use crate::utils::mnemonic;
+use clarity_repl::clarity::analysis::contract_interface_builder::ContractInterface;
+use clarity_repl::clarity::codec::transaction::{
+    FungibleConditionCode, PostConditionPrincipal, RecoverableSignature, SinglesigHashMode,
+    SinglesigSpendingCondition, StacksTransaction, StacksTransactionSigner, TokenTransferMemo,
+    TransactionAnchorMode, TransactionAuth, TransactionContractCall, TransactionPayload, TransactionPostCondition,
+    TransactionPostConditionMode, TransactionPublicKeyEncoding, TransactionSmartContract,
+    TransactionSpendingCondition, TransactionVersion,
+};
+use clarity_repl::clarity::codec::{StacksMessageCodec, StacksString};
+use clarity_repl::clarity::util::address::AddressHashMode;
+use clarity_repl::clarity::util::hash::{hex_bytes, to_hex};
+use clarity_repl::clarity::util::secp256k1::{Secp256k1PrivateKey, Secp256k1PublicKey};
+use clarity_repl::clarity::types::{PrincipalData, QualifiedContractIdentifier};
+use clarity_repl::clarity::util::StacksAddress;
+use clarity_repl::repl::settings::Account;
+use clarity_repl::repl::SessionSettings;
+use secp256k1::{PublicKey, SecretKey};
+use tiny_hderive::bip32::ExtendedPrivKey;
 
+mod fees;
+mod minify;
+
+use fees::compute_fee;
+use minify::minify;
+pub use fees::FeeStrategy;
+
+/// RPC endpoint of the node transactions are sent to, unless another one is
+/// configured.
+pub const DEFAULT_NODE_URL: &str = "http://localhost:20443";
+
+/// Timeout applied to the connection to the node, unless the request timeout is shorter.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a contract can take to be confirmed, before its `post_deploy`
+/// calls are sent or when waiting for the deployment.
+pub const CONFIRMATION_TIMEOUT: Duration = Duration::from_secs(600);
+
+pub struct PublishOptions {
+    /// RPC endpoint of the node transactions are sent to
+    pub node_url: String,
+    /// Timeout of each request sent to the node
+    pub request_timeout: Duration,
+    pub fee_strategy: FeeStrategy,
+    /// Name of the account paying the fees, the transactions being sponsored
+    pub sponsor: Option<String>,
+    /// Name of the account deploying every contract, instead of `deployer`
+    pub deployer: Option<String>,
+    /// Nonce of the first transaction of the deployer, skipping its lookup
+    pub start_nonce: Option<u64>,
+    /// Chain the transactions are built for
+    pub network: Network,
+    /// Version of the transactions, instead of the one of `network`
+    pub tx_version: Option<TransactionVersion>,
+    /// `post_deploy` calls of each contract, sent by its deployer once it is
+    /// confirmed. Only used by `publish_contracts`.
+    pub post_deploy: BTreeMap<String, Vec<String>>,
+    /// µSTX transferred by its deployer to each contract once it is
+    /// confirmed, ahead of its `post_deploy` calls.
+    pub initial_funding: BTreeMap<String, u64>,
+    /// Delay between two checks of the status of the transactions broadcasted
+    pub poll_interval: Duration,
+    /// Blocks, including the one including it, a transaction must be buried
+    /// under to be considered confirmed
+    pub confirmations: u64,
+    /// Deploy the contracts without their comments and superfluous whitespace
+    pub minify: bool,
+    /// `deploy_stages` of the contracts: the contracts of a stage are only
+    /// broadcasted once those of the previous stages are confirmed. Only used
+    /// by `publish_contracts`.
+    pub deploy_stages: BTreeMap<String, usize>,
+}
+
+impl Default for PublishOptions {
+    fn default() -> Self {
+        PublishOptions {
+            node_url: DEFAULT_NODE_URL.to_string(),
+            request_timeout: Duration::from_secs(30),
+            fee_strategy: FeeStrategy::default(),
+            sponsor: None,
+            deployer: None,
+            start_nonce: None,
+            network: Network::Testnet,
+            tx_version: None,
+            post_deploy: BTreeMap::new(),
+            initial_funding: BTreeMap::new(),
+            poll_interval: Duration::from_secs(5),
+            confirmations: 1,
+            minify: false,
+            deploy_stages: BTreeMap::new(),
+        }
+    }
+}
+
+fn account_keys(account: &Account) -> Result<(Secp256k1PublicKey, Secp256k1PrivateKey), String> {
+    let bip39_seed = mnemonic::get_bip39_seed_from_mnemonic(&account.mnemonic, "")
+        .map_err(|e| format!("Error: invalid mnemonic for account {}: {}", account.name, e))?;
+    let ext = ExtendedPrivKey::derive(&bip39_seed[..], account.derivation.as_str())
+        .map_err(|_| format!("Error: invalid derivation path for account {}", account.name))?;
+    let secret_key = SecretKey::parse_slice(&ext.secret()).unwrap();
+    let public_key = PublicKey::from_secret_key(&secret_key);
+
+    let wrapped_public_key = Secp256k1PublicKey::from_slice(&public_key.serialize_compressed()).unwrap();
+    let wrapped_secret_key = Secp256k1PrivateKey::from_slice(&ext.secret()).unwrap();
+    Ok((wrapped_public_key, wrapped_secret_key))
+}
+
+fn singlesig_condition(public_key: &Secp256k1PublicKey, nonce: u64) -> TransactionSpendingCondition {
+    let signer_addr = StacksAddress::from_public_keys(0, &AddressHashMode::SerializeP2PKH, 1, &vec![public_key.clone()]).unwrap();
+    TransactionSpendingCondition::Singlesig(
+        SinglesigSpendingCondition {
+            signer: signer_addr.bytes.clone(),
+            nonce: nonce,
+            tx_fee: 0,
+            hash_mode: SinglesigHashMode::P2PKH,
+            key_encoding: TransactionPublicKeyEncoding::Compressed,
+            signature: RecoverableSignature::empty(),
+        },
+    )
+}
+
+fn describe_request_error(err: reqwest::Error, phase: &str, contract_name: &str) -> String {
+    if err.is_timeout() {
+        format!("Error: timed out while {} for contract {}", phase, contract_name)
+    } else {
+        format!("Error: failed while {} for contract {}: {}", phase, contract_name, err)
+    }
+}
+
+/// A contract-publish transaction, or one of the `post_deploy` calls of the
+/// contract, built but not signed yet.
+struct PendingTransaction {
+    contract_name: String,
+    /// `post_deploy` expression, when the transaction is a contract call,
+    /// or the `stx-transfer?` funding the contract
+    call: Option<String>,
+    /// µSTX transferred by the deployer
+    amount: u64,
+    /// Hash of the source of the contract as written, recorded in
+    /// `Clarinet.lock` instead of the hash of the source deployed when minified
+    source_hash: Option<String>,
+    nonce: u64,
+    fee: u64,
+    tx: StacksTransaction,
+    deployer: Account,
+    sponsor: Option<Account>,
+}
+
+fn build_client(options: &PublishOptions) -> Result<reqwest::blocking::Client, String> {
+    reqwest::blocking::Client::builder()
+        .timeout(options.request_timeout)
+        .connect_timeout(CONNECT_TIMEOUT.min(options.request_timeout))
+        .build()
+        .map_err(|e| format!("Error: unable to build http client: {}", e))
+}
+
+#[derive(Deserialize, Debug)]
+struct AccountState {
+    /// Hex encoded µSTX balance
+    balance: String,
+    nonce: u64,
+}
+
+/// Balance of `address`, as known by the node.
+fn fetch_balance(client: &reqwest::blocking::Client, node: &str, address: &str) -> Result<Balance, String> {
+    let request_url = format!("{}/v2/accounts/{}?proof=0", node, address);
+    debug!("Fetching balance of {} from {}", address, request_url);
+    let response: AccountState = client
+        .get(&request_url)
+        .send()
+        .and_then(|res| res.error_for_status())
+        .and_then(|res| res.json())
+        .map_err(|e| format!("Error: unable to fetch the balance of {}: {}", address, e))?;
+    response
+        .balance
+        .parse()
+        .map_err(|e| format!("Error: unable to read the balance of {}: {}", address, e))
+}
+
+/// Name, address and balance, when the node reports it, of each of the
+/// accounts of the settings.
+pub fn fetch_account_balances(
+    settings: &SessionSettings,
+    options: &PublishOptions,
+) -> Result<Vec<(String, String, Option<Balance>)>, String> {
+    let client = build_client(options)?;
+    Ok(settings
+        .initial_accounts
+        .iter()
+        .map(|account| {
+            let balance = fetch_balance(&client, &options.node_url, &account.address).map_err(|e| debug!("{}", e)).ok();
+            (account.name.clone(), account.address.clone(), balance)
+        })
+        .collect())
+}
+
+/// Account deploying the contracts: the `deployer` of the options when set,
+/// the `deployer` account of the settings otherwise.
+pub fn deployer_account<'a>(settings: &'a SessionSettings, options: &PublishOptions) -> Result<&'a Account, String> {
+    let name = options.deployer.as_deref().unwrap_or("deployer");
+    match settings.initial_accounts.iter().find(|account| account.name == name) {
+        Some(account) => Ok(account),
+        None if options.deployer.is_some() => Err(format!("Error: unknown deployer account {}", name)),
+        None => Err("Error: no deployer account in the settings of the network".to_string()),
+    }
+}
+
+/// Build a contract-publish transaction for each of the `initial_contracts`
+/// of the settings, in order, using the `deployer` account, each one followed
+/// by the `post_deploy` calls of the contract. Nonces are fetched from the
+/// node, and fees computed with the fee strategy.
+fn build_transactions(
+    settings: &SessionSettings,
+    options: &PublishOptions,
+    client: &reqwest::blocking::Client,
+) -> Result<Vec<PendingTransaction>, String> {
+    let mut fee_rate = None;
+    let mut deployers_nonces = BTreeMap::new();
+    let deployer = deployer_account(settings, options)?;
+
+    if let Some(nonce) = options.start_nonce {
+        deployers_nonces.insert(deployer.name.clone(), nonce);
+    }
+
+    // Nonces are fetched once per account, and then incremented locally.
+    let mut next_nonce = |account: &Account, contract_name: &str| -> Result<u64, String> {
+        let nonce = match deployers_nonces.get(&account.name) {
+            Some(nonce) => {
+                *nonce
+            },
+            None => {
+                let request_url = format!(
+                    "{host}/v2/accounts/{addr}",
+                    host = options.node_url,
+                    addr = account.address,
+                );
+
+                debug!("Fetching nonce of {} from {}", account.address, request_url);
+                let response: AccountState = client
+                    .get(&request_url)
+                    .send()
+                    .and_then(|res| res.json())
+                    .map_err(|e| describe_request_error(e, &format!("fetching the nonce of {}", account.name), contract_name))?;
+                response.nonce
+            }
+        };
+        deployers_nonces.insert(account.name.clone(), nonce + 1);
+        Ok(nonce)
+    };
+
+    let sponsor = match options.sponsor {
+        Some(ref name) => match settings.initial_accounts.iter().find(|account| &account.name == name) {
+            Some(account) => Some(account.clone()),
+            None => return Err(format!("Error: unknown sponsor account {}", name)),
+        },
+        None => None,
+    };
+
+    let mut build_transaction = |deployer: &Account,
+                                 payload: TransactionPayload,
+                                 post_conditions: Vec<TransactionPostCondition>,
+                                 code: &str,
+                                 contract_name: &str|
+     -> Result<(u64, u64, StacksTransaction), String> {
+        let (public_key, _) = account_keys(deployer)?;
+
+        let anchor_mode = TransactionAnchorMode::Any;
+
+        let nonce = next_nonce(deployer, contract_name)?;
+        let spending_condition = singlesig_condition(&public_key, nonce);
+
+        // When sponsored, the fee is paid by the sponsor, from its own nonce.
+        let auth = match sponsor {
+            Some(ref sponsor) => {
+                let (sponsor_public_key, _) = account_keys(sponsor)?;
+                let sponsor_nonce = next_nonce(sponsor, contract_name)?;
+                TransactionAuth::Sponsored(spending_condition, singlesig_condition(&sponsor_public_key, sponsor_nonce))
+            }
+            None => TransactionAuth::Standard(spending_condition),
+        };
+        let mut unsigned_tx = StacksTransaction {
+            version: options.tx_version.unwrap_or(if options.network.is_mainnet() { TransactionVersion::Mainnet } else { TransactionVersion::Testnet }),
+            chain_id: options.network.chain_id(),
+            auth: auth,
+            anchor_mode: anchor_mode,
+            post_condition_mode: TransactionPostConditionMode::Deny,
+            post_conditions,
+            payload,
+        };
+    
+        let mut unsigned_tx_bytes = vec![];
+        unsigned_tx
+            .consensus_serialize(&mut unsigned_tx_bytes)
+            .expect("FATAL: invalid transaction");
+
+        let tx_fee = compute_fee(&options.fee_strategy, code, unsigned_tx_bytes.len(), || {
+            if let Some(rate) = fee_rate {
+                return Ok(rate);
+            }
+            let request_url = format!("{}/v2/fees/transfer", options.node_url);
+            debug!("Fetching fee rate from {}", request_url);
+            let rate: u64 = client
+                .get(&request_url)
+                .send()
+                .and_then(|res| res.json())
+                .map_err(|e| describe_request_error(e, "fetching the fee rate", contract_name))?;
+            fee_rate = Some(rate);
+            Ok(rate)
+        })?;
+        unsigned_tx.set_tx_fee(tx_fee);
+        Ok((nonce, tx_fee, unsigned_tx))
+    };
+
+    let mut transactions = vec![];
+    for initial_contract in settings.initial_contracts.iter() {
+        let contract_name = initial_contract.name.clone().unwrap();
+
+        let code = if options.minify {
+            match minify(&initial_contract.code) {
+                Ok(code) => {
+                    info!(
+                        "Minified {}: {} bytes saved ({} bytes deployed)",
+                        contract_name,
+                        initial_contract.code.len() - code.len(),
+                        code.len()
+                    );
+                    code
+                }
+                Err(e) => {
+                    warn!("{}, contract {} deployed as written", e, contract_name);
+                    initial_contract.code.clone()
+                }
+            }
+        } else {
+            initial_contract.code.clone()
+        };
+        let payload = TransactionSmartContract {
+            name: contract_name.as_str().into(),
+            code_body: StacksString::from_string(&code).unwrap()
+        };
+
+        let (nonce, fee, tx) = build_transaction(
+            deployer,
+            TransactionPayload::SmartContract(payload),
+            vec![],
+            &code,
+            &contract_name,
+        )?;
+        transactions.push(PendingTransaction {
+            contract_name: contract_name.clone(),
+            call: None,
+            amount: 0,
+            source_hash: if code != initial_contract.code { Some(source_hash(initial_contract.code.as_bytes())) } else { None },
+            nonce,
+            fee,
+            tx,
+            deployer: deployer.clone(),
+            sponsor: sponsor.clone(),
+        });
+
+        let deployer_principal = PrincipalData::parse_standard_principal(&deployer.address)
+            .map_err(|_| format!("Error: invalid address for account {}", deployer.name))?;
+
+        // The funding of the contract follows it in the nonce sequence of the
+        // deployer, and is bound to the amount by a post-condition.
+        if let Some(amount) = options.initial_funding.get(&contract_name).cloned() {
+            let contract_id = QualifiedContractIdentifier::new(deployer_principal.clone(), contract_name.as_str().into());
+            let expression = format!("(stx-transfer? u{} tx-sender '{})", amount, contract_id);
+            let payload = TransactionPayload::TokenTransfer(
+                PrincipalData::Contract(contract_id),
+                amount,
+                TokenTransferMemo([0u8; 34]),
+            );
+            let post_condition = TransactionPostCondition::STX(PostConditionPrincipal::Origin, FungibleConditionCode::SentEq, amount);
+            let (nonce, fee, tx) = build_transaction(deployer, payload, vec![post_condition], &expression, &contract_name)?;
+            transactions.push(PendingTransaction {
+                contract_name: contract_name.clone(),
+                call: Some(expression),
+                amount,
+                source_hash: None,
+                nonce,
+                fee,
+                tx,
+                deployer: deployer.clone(),
+                sponsor: sponsor.clone(),
+            });
+        }
+
+        // The calls follow the contract in the nonce sequence of the deployer.
+        for expression in options.post_deploy.get(&contract_name).into_iter().flatten() {
+            let call = ContractCall::parse(expression)
+                .map_err(|e| format!("Error: invalid post_deploy for contract {}: {}", contract_name, e))?;
+            let payload = TransactionContractCall {
+                address: StacksAddress::from(call.contract_address(&deployer_principal)),
+                contract_name: call.contract_name.clone(),
+                function_name: call.function_name.clone(),
+                function_args: call.resolve_args(&deployer_principal),
+            };
+            let (nonce, fee, tx) = build_transaction(
+                deployer,
+                TransactionPayload::ContractCall(payload),
+                vec![],
+                expression,
+                &contract_name,
+            )?;
+            transactions.push(PendingTransaction {
+                contract_name: contract_name.clone(),
+                call: Some(expression.clone()),
+                amount: 0,
+                source_hash: None,
+                nonce,
+                fee,
+                tx,
+                deployer: deployer.clone(),
+                sponsor: sponsor.clone(),
+            });
+        }
+    }
+    Ok(transactions)
+}
+
+/// Sign the transaction with the keys of its deployer, and of its sponsor.
+fn sign_transaction(pending: &PendingTransaction) -> Result<StacksTransaction, String> {
+    let (_, secret_key) = account_keys(&pending.deployer)?;
+    let mut tx_signer = StacksTransactionSigner::new(&pending.tx);
+    tx_signer
+        .sign_origin(&secret_key)
+        .map_err(|e| format!("Error: unable to sign contract {}: {:?}", pending.contract_name, e))?;
+    if let Some(ref sponsor) = pending.sponsor {
+        let (_, sponsor_secret_key) = account_keys(sponsor)?;
+        tx_signer
+            .sign_sponsor(&sponsor_secret_key)
+            .map_err(|e| format!("Error: unable to sign contract {}: {:?}", pending.contract_name, e))?;
+    }
+    Ok(tx_signer.get_tx().unwrap())
+}
+
+/// Send a signed transaction to the node, returning its txid.
+fn broadcast_transaction(
+    client: &reqwest::blocking::Client,
+    node: &str,
+    contract_name: &str,
+    signed_tx: &StacksTransaction,
+) -> Result<String, String> {
+    let tx_bytes = signed_tx.serialize_to_vec();
+    let path = format!("{}/v2/transactions", node);
+    debug!("Broadcasting contract {} to {}", contract_name, path);
+    let res = client
+        .post(&path)
+        .header("Content-Type", "application/octet-stream")
+        .body(tx_bytes)
+        .send()
+        .map_err(|e| describe_request_error(e, "broadcasting the transaction", contract_name))?;
+
+    if !res.status().is_success() {
+        let reason = res.text().unwrap_or_default();
+        return Err(format!("Error: unable to broadcast contract {}: {}", contract_name, reason));
+    }
+    res.json()
+        .map_err(|e| describe_request_error(e, "broadcasting the transaction", contract_name))
+}
+
+/// Contract successfully broadcasted, to be recorded in `Clarinet.lock`.
+#[derive(Serialize, Debug, Clone)]
+pub struct DeployedContract {
+    pub contract_name: String,
+    pub txid: String,
+    pub source_hash: String,
+    /// Address of the account which deployed the contract
+    pub deployer: String,
+    pub nonce: u64,
+    pub fee: u64,
+    /// Address of the account paying the fee (the sponsor, if any)
+    pub fee_payer: String,
+}
+
+fn deployed_contract(contract_name: &str, txid: String, tx: &StacksTransaction) -> DeployedContract {
+    DeployedContract {
+        contract_name: contract_name.to_string(),
+        txid,
+        source_hash: source_hash(&smart_contract_payload(tx).code_body[..]),
+        deployer: tx.origin_address().to_string(),
+        nonce: tx.get_origin_nonce(),
+        fee: tx.get_tx_fee(),
+        fee_payer: fee_payer_address(tx),
+    }
+}
+
+/// `post_deploy` call successfully broadcasted.
+#[derive(Serialize, Debug, Clone)]
+pub struct PostDeployCall {
+    pub contract_name: String,
+    pub expression: String,
+    pub txid: String,
+    pub nonce: u64,
+    pub fee: u64,
+    /// Address of the account paying the fee (the sponsor, if any)
+    pub fee_payer: String,
+}
+
+/// Outcome of a deployment: the contracts and `post_deploy` calls
+/// broadcasted, and the balances of the accounts paying their fees, fetched
+/// before broadcasting anything.
+#[derive(Debug, Default)]
+pub struct DeploymentReport {
+    pub contracts: Vec<DeployedContract>,
+    pub calls: Vec<PostDeployCall>,
+    pub starting_balances: BTreeMap<String, Option<Balance>>,
+}
+
+/// Fees paid by an account during a deployment.
+#[derive(Serialize, Debug)]
+pub struct AccountSpending {
+    pub address: String,
+    pub starting_balance: Option<Balance>,
+    pub fees: u64,
+    /// Starting balance minus the fees, once the transactions are mined
+    pub remaining_balance: Option<Balance>,
+}
+
+impl DeploymentReport {
+    fn record_starting_balances<I>(&mut self, client: &reqwest::blocking::Client, node: &str, addresses: I)
+    where
+        I: Iterator<Item = String>,
+    {
+        for address in addresses {
+            if self.starting_balances.contains_key(&address) {
+                continue;
+            }
+            let balance = fetch_balance(client, node, &address).map_err(|e| debug!("{}", e)).ok();
+            self.starting_balances.insert(address, balance);
+        }
+    }
+
+    /// Check, before broadcasting anything, that each account paying fees can
+    /// afford all of them. `fees` lists, for each transaction, the address of
+    /// the account paying its fee, the name of that account, and the fee,
+    /// followed by the amounts transferred. Accounts whose balance couldn't be
+    /// fetched are not checked.
+    fn check_balances<I>(&self, fees: I) -> Result<(), String>
+    where
+        I: Iterator<Item = (String, String, u64)>,
+    {
+        let mut needed: Vec<(String, String, u128)> = vec![];
+        for (address, name, fee) in fees {
+            match needed.iter_mut().find(|(a, _, _)| a == &address) {
+                Some(entry) => entry.2 += fee as u128,
+                None => needed.push((address, name, fee as u128)),
+            }
+        }
+        for (address, name, total) in needed.iter() {
+            match self.starting_balances.get(address).cloned().flatten() {
+                Some(balance) if balance.micro_stx() < *total => {
+                    return Err(format!(
+                        "Error: {} has {} uSTX but needs {} to pay the fees and transfers, nothing was broadcasted",
+                        name, balance.micro_stx(), total
+                    ));
+                }
+                Some(_) => {}
+                None => warn!("Unable to check that {} can pay the fees of the deployment", name),
+            }
+        }
+        Ok(())
+    }
+
+    /// Fees paid by each account, in the order they first paid one.
+    pub fn spending(&self) -> Vec<AccountSpending> {
+        let mut spending: Vec<AccountSpending> = vec![];
+        let fees = self
+            .contracts
+            .iter()
+            .map(|contract| (&contract.fee_payer, contract.fee))
+            .chain(self.calls.iter().map(|call| (&call.fee_payer, call.fee)));
+        for (fee_payer, fee) in fees {
+            match spending.iter_mut().find(|s| &s.address == fee_payer) {
+                Some(account) => account.fees += fee,
+                None => spending.push(AccountSpending {
+                    address: fee_payer.clone(),
+                    starting_balance: self.starting_balances.get(fee_payer).cloned().flatten(),
+                    fees: fee,
+                    remaining_balance: None,
+                }),
+            }
+        }
+        for account in spending.iter_mut() {
+            account.remaining_balance = account
+                .starting_balance
+                .map(|balance| balance.saturating_sub(account.fees as u128));
+        }
+        spending
+    }
+}
+
+fn smart_contract_payload(tx: &StacksTransaction) -> &TransactionSmartContract {
+    match tx.payload {
+        TransactionPayload::SmartContract(ref payload) => payload,
+        _ => unreachable!(),
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct TransactionStatus {
+    tx_status: String,
+    block_height: Option<u64>,
+}
+
+#[derive(Deserialize, Debug)]
+struct NodeInfo {
+    stacks_tip_height: u64,
+}
+
+fn fetch_tip_height(client: &reqwest::blocking::Client, node: &str) -> Result<u64, String> {
+    let url = format!("{}/v2/info", node);
+    client
+        .get(&url)
+        .send()
+        .and_then(|response| response.error_for_status())
+        .and_then(|response| response.json::<NodeInfo>())
+        .map(|info| info.stacks_tip_height)
+        .map_err(|e| format!("Error: unable to retrieve the chain tip: {}", e))
+}
+
+fn fetch_transaction_status(client: &reqwest::blocking::Client, node: &str, txid: &str) -> Result<TransactionStatus, String> {
+    let url = format!("{}/extended/v1/tx/{}", node, txid);
+    client
+        .get(&url)
+        .send()
+        .and_then(|response| response.error_for_status())
+        .and_then(|response| response.json())
+        .map_err(|e| format!("Error: unable to retrieve transaction {}: {}", txid, e))
+}
+
+/// Height of the block which included the transaction `txid`, if the node
+/// knows about it. Any failure is treated as "not confirmed yet".
+pub fn confirmation_height(txid: &str, options: &PublishOptions) -> Option<u64> {
+    let client = build_client(options).ok()?;
+    let tx = fetch_transaction_status(&client, &options.node_url, txid)
+        .map_err(|e| debug!("{}", e))
+        .ok()?;
+    if tx.tx_status == "success" {
+        tx.block_height
+    } else {
+        None
+    }
+}
+
+/// Poll the node, every `poll_interval`, until the transactions of `deployed`
+/// all have the number of `confirmations` of the options, failing as soon as
+/// one of them is rejected, or after `timeout`. The status of a transaction
+/// is checked again on each round, a reorg sending it back to the mempool.
+pub fn wait_for_confirmations(
+    deployed: &[DeployedContract],
+    options: &PublishOptions,
+    timeout: Duration,
+) -> Result<(), String> {
+    let client = build_client(options)?;
+    let start = Instant::now();
+    let mut pending: Vec<&DeployedContract> = deployed.iter().collect();
+    while !pending.is_empty() {
+        let mut still_pending = vec![];
+        // The chain tip is only needed, once per round, when more than one
+        // confirmation is required.
+        let mut tip_height = None;
+        for contract in pending.into_iter() {
+            match fetch_transaction_status(&client, &options.node_url, &contract.txid) {
+                Ok(tx) if tx.tx_status == "success" => {
+                    let block_height = tx.block_height.unwrap_or_default();
+                    let confirmations = if options.confirmations <= 1 {
+                        1
+                    } else {
+                        if tip_height.is_none() {
+                            tip_height = fetch_tip_height(&client, &options.node_url).map_err(|e| debug!("{}", e)).ok();
+                        }
+                        tip_height.map_or(0, |tip: u64| (tip + 1).saturating_sub(block_height))
+                    };
+                    if confirmations >= options.confirmations {
+                        info!("Contract {} confirmed at block {}", contract.contract_name, block_height);
+                    } else {
+                        debug!(
+                            "Contract {} included at block {} ({}/{} confirmations)",
+                            contract.contract_name, block_height, confirmations, options.confirmations
+                        );
+                        still_pending.push(contract);
+                    }
+                }
+                Ok(tx) if tx.tx_status.starts_with("abort") => {
+                    return Err(format!(
+                        "Error: deployment of contract {} failed ({})",
+                        contract.contract_name, tx.tx_status
+                    ));
+                }
+                Ok(_) => still_pending.push(contract),
+                Err(e) => {
+                    // Transactions are not indexed right after being broadcasted.
+                    debug!("{}", e);
+                    still_pending.push(contract);
+                }
+            }
+        }
+        pending = still_pending;
+        if pending.is_empty() {
+            break;
+        }
+        if start.elapsed() > timeout {
+            let names: Vec<&str> = pending.iter().map(|contract| contract.contract_name.as_str()).collect();
+            return Err(format!("Error: timed out waiting for the confirmation of {}", names.join(", ")));
+        }
+        thread::sleep(options.poll_interval);
+    }
+    Ok(())
+}
+
+/// Sign and broadcast a contract-publish transaction for each of the
+/// `initial_contracts` of the settings, in order, using the `deployer` account.
+/// The `initial_funding` transfer and the `post_deploy` calls of a contract
+/// are broadcasted once it is confirmed.
+/// The contracts and calls broadcasted are added to `report`, including when
+/// a later one fails.
+pub fn publish_contracts(
+    settings: &SessionSettings,
+    options: &PublishOptions,
+    report: &mut DeploymentReport,
+) -> Result<(), String> {
+    let client = build_client(options)?;
+    let transactions = build_transactions(settings, options, &client)?;
+    report.record_starting_balances(
+        &client,
+        &options.node_url,
+        transactions.iter().map(|pending| fee_payer_address(&pending.tx)).chain(
+            transactions
+                .iter()
+                .filter(|pending| pending.amount > 0)
+                .map(|pending| pending.deployer.address.clone()),
+        ),
+    );
+    // The amounts funding the contracts are paid by their deployer.
+    let transfers = transactions
+        .iter()
+        .filter(|pending| pending.amount > 0)
+        .map(|pending| (pending.deployer.address.clone(), pending.deployer.name.clone(), pending.amount));
+    report.check_balances(
+        transactions
+            .iter()
+            .map(|pending| {
+                let payer = pending.sponsor.as_ref().unwrap_or(&pending.deployer);
+                (fee_payer_address(&pending.tx), payer.name.clone(), pending.fee)
+            })
+            .chain(transfers),
+    )?;
+    let mut confirmed: Vec<String> = vec![];
+    let mut current_stage = 0;
+    for pending in transactions.iter() {
+        if let Some(ref expression) = pending.call {
+            if !confirmed.contains(&pending.contract_name) {
+                let contract: Vec<DeployedContract> = report
+                    .contracts
+                    .iter()
+                    .filter(|contract| contract.contract_name == pending.contract_name)
+                    .cloned()
+                    .collect();
+                info!("Waiting for the confirmation of {} before funding it and sending its post_deploy calls", pending.contract_name);
+                wait_for_confirmations(&contract, options, CONFIRMATION_TIMEOUT)?;
+                confirmed.push(pending.contract_name.clone());
+            }
+            debug!("Signing {} (fee: {}, nonce: {})", expression, pending.fee, pending.nonce);
+            let signed_tx = sign_transaction(pending)?;
+            let txid = broadcast_transaction(&client, &options.node_url, &pending.contract_name, &signed_tx)?;
+            info!("Broadcasted {} (txid: {})", expression, txid);
+            report.calls.push(PostDeployCall {
+                contract_name: pending.contract_name.clone(),
+                expression: expression.clone(),
+                txid,
+                nonce: pending.nonce,
+                fee: pending.fee,
+                fee_payer: fee_payer_address(&signed_tx),
+            });
+            continue;
+        }
+        let stage = options.deploy_stages.get(&pending.contract_name).cloned().unwrap_or(usize::MAX);
+        if stage > current_stage {
+            let unconfirmed: Vec<DeployedContract> = report
+                .contracts
+                .iter()
+                .filter(|contract| !confirmed.contains(&contract.contract_name))
+                .cloned()
+                .collect();
+            if !unconfirmed.is_empty() {
+                info!("Waiting for the confirmation of the previous deploy stages before deploying {}", pending.contract_name);
+                wait_for_confirmations(&unconfirmed, options, CONFIRMATION_TIMEOUT)?;
+                confirmed.extend(unconfirmed.into_iter().map(|contract| contract.contract_name));
+            }
+            current_stage = stage;
+        }
+        debug!("Signing contract {} (fee: {}, nonce: {})", pending.contract_name, pending.fee, pending.nonce);
+        let signed_tx = sign_transaction(pending)?;
+        let txid = broadcast_transaction(&client, &options.node_url, &pending.contract_name, &signed_tx)?;
+        info!("Broadcasted {} (txid: {})", pending.contract_name, txid);
+        let mut contract = deployed_contract(&pending.contract_name, txid, &signed_tx);
+        if let Some(ref source_hash) = pending.source_hash {
+            contract.source_hash = source_hash.clone();
+        }
+        report.contracts.push(contract);
+    }
+    Ok(())
+}
+
+fn fee_payer_address(tx: &StacksTransaction) -> String {
+    tx.sponsor_address().unwrap_or_else(|| tx.origin_address()).to_string()
+}
+
+/// Write the unsigned transactions publishing the `initial_contracts` of the
+/// settings to `dir`, one hex encoded `<index>-<contract>.tx` file per
+/// contract, to be signed offline.
+pub fn export_unsigned_transactions(settings: &SessionSettings, options: &PublishOptions, dir: &Path) -> Result<(), String> {
+    let client = build_client(options)?;
+    let transactions = build_transactions(settings, options, &client)?;
+    fs::create_dir_all(dir).map_err(|e| format!("Error: unable to create {}: {}", dir.display(), e))?;
+    for (index, pending) in transactions.iter().enumerate() {
+        let path = dir.join(format!("{:03}-{}.tx", index, pending.contract_name));
+        fs::write(&path, to_hex(&pending.tx.serialize_to_vec()))
+            .map_err(|e| format!("Error: unable to write {}: {}", path.display(), e))?;
+        outputln!("Wrote {} (fee: {}, nonce: {})", path.display(), pending.fee, pending.nonce);
+    }
+    Ok(())
+}
+
+/// Decode a hex encoded contract-publish transaction, `origin` describing
+/// where it comes from in error messages.
+fn decode_transaction(hex: &str, origin: &str) -> Result<(StacksTransaction, TransactionSmartContract), String> {
+    let bytes = hex_bytes(hex.trim().trim_start_matches("0x"))
+        .map_err(|e| format!("Error: {} is not hex encoded: {:?}", origin, e))?;
+    let tx = StacksTransaction::consensus_deserialize(&mut &bytes[..])
+        .map_err(|e| format!("Error: {} is not a valid transaction: {:?}", origin, e))?;
+    let payload = match tx.payload {
+        TransactionPayload::SmartContract(ref payload) => payload.clone(),
+        _ => return Err(format!("Error: {} does not publish a contract", origin)),
+    };
+    Ok((tx, payload))
+}
+
+/// Broadcast the signed transactions found in `dir` (hex encoded `.tx`
+/// files), in the order of their file names, adding the contracts
+/// broadcasted to `report`.
+pub fn broadcast_signed_transactions(
+    dir: &Path,
+    options: &PublishOptions,
+    report: &mut DeploymentReport,
+) -> Result<(), String> {
+    let mut paths = fs::read_dir(dir)
+        .map_err(|e| format!("Error: unable to read {}: {}", dir.display(), e))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().map_or(false, |ext| ext == "tx"))
+        .collect::<Vec<_>>();
+    paths.sort();
+    if paths.is_empty() {
+        return Err(format!("Error: no .tx file found in {}", dir.display()));
+    }
+
+    // All the files are decoded before broadcasting anything.
+    let mut transactions = vec![];
+    for path in paths.iter() {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Error: unable to read {}: {}", path.display(), e))?;
+        let (tx, payload) = decode_transaction(&content, &path.display().to_string())?;
+        transactions.push((payload.name.as_str().to_string(), tx));
+    }
+
+    let client = build_client(options)?;
+    report.record_starting_balances(&client, &options.node_url, transactions.iter().map(|(_, tx)| fee_payer_address(tx)));
+    report.check_balances(transactions.iter().map(|(_, tx)| (fee_payer_address(tx), fee_payer_address(tx), tx.get_tx_fee())))?;
+    for (contract_name, tx) in transactions.iter() {
+        let txid = broadcast_transaction(&client, &options.node_url, contract_name, tx)?;
+        info!("Broadcasted {} (txid: {})", contract_name, txid);
+        report.contracts.push(deployed_contract(contract_name, txid, tx));
+    }
+    Ok(())
+}
+
+/// Signed deployment plan, written by `deploy --export-bundle` for review,
+/// and replayed by `deploy --broadcast-bundle`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DeploymentBundle {
+    pub project: Option<String>,
+    pub environment: String,
+    pub node: String,
+    pub transactions: Vec<BundledTransaction>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BundledTransaction {
+    pub contract_name: String,
+    /// SHA-256 of the contract source
+    pub source_hash: String,
+    pub nonce: u64,
+    pub fee: u64,
+    /// Signed transaction, hex encoded
+    pub tx: String,
+}
+
+/// Build and sign the transactions publishing the `initial_contracts` of the
+/// settings, and write them, with their metadata, to a JSON bundle at `path`.
+/// Nothing is broadcasted.
+pub fn export_bundle(
+    settings: &SessionSettings,
+    options: &PublishOptions,
+    project: Option<String>,
+    environment: &str,
+    path: &Path,
+) -> Result<(), String> {
+    let client = build_client(options)?;
+    let mut transactions = vec![];
+    for pending in build_transactions(settings, options, &client)?.iter() {
+        let signed_tx = sign_transaction(pending)?;
+        transactions.push(BundledTransaction {
+            contract_name: pending.contract_name.clone(),
+            source_hash: source_hash(&smart_contract_payload(&pending.tx).code_body[..]),
+            nonce: pending.nonce,
+            fee: pending.fee,
+            tx: to_hex(&signed_tx.serialize_to_vec()),
+        });
+    }
+    let bundle = DeploymentBundle {
+        project,
+        environment: environment.to_string(),
+        node: options.node_url.clone(),
+        transactions,
+    };
+    let content = serde_json::to_string_pretty(&bundle)
+        .map_err(|e| format!("Error: unable to serialize the bundle: {}", e))?;
+    fs::write(path, content).map_err(|e| format!("Error: unable to write {}: {}", path.display(), e))?;
+    info!("Bundle with {} transaction(s) written to {}", bundle.transactions.len(), path.display());
+    Ok(())
+}
+
+/// Deployment plan written by `deploy --emit-plan`, for deployment pipelines
+/// building and signing the transactions themselves.
+#[derive(Serialize, Debug)]
+pub struct DeploymentPlan {
+    pub project: Option<String>,
+    pub environment: String,
+    pub node: String,
+    pub transactions: Vec<PlannedTransaction>,
+}
+
+/// Contract publish, or `post_deploy` call, in the order it has to be sent.
+#[derive(Serialize, Debug)]
+pub struct PlannedTransaction {
+    pub contract_name: String,
+    /// Path of the contract source, relative to the project root
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// `post_deploy` expression, for a contract call
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub call: Option<String>,
+    /// SHA-256 of the contract source, for a contract publish
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_hash: Option<String>,
+    pub deployer: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sponsor: Option<String>,
+    pub nonce: u64,
+    pub fee: u64,
+}
+
+/// Compute the nonces and fees of the transactions publishing the
+/// `initial_contracts` of the settings, and of their `post_deploy` calls, and
+/// write them in order to a plan at `path`, as TOML for a `.toml` file, as
+/// JSON otherwise. `contract_paths` gives the path of the source of each
+/// contract. Nothing is signed nor broadcasted.
+pub fn export_plan(
+    settings: &SessionSettings,
+    options: &PublishOptions,
+    project: Option<String>,
+    environment: &str,
+    contract_paths: &BTreeMap<String, String>,
+    path: &Path,
+) -> Result<(), String> {
+    let client = build_client(options)?;
+    let transactions = build_transactions(settings, options, &client)?
+        .into_iter()
+        .map(|pending| PlannedTransaction {
+            path: match pending.call {
+                Some(_) => None,
+                None => contract_paths.get(&pending.contract_name).cloned(),
+            },
+            source_hash: match pending.tx.payload {
+                TransactionPayload::SmartContract(ref payload) => Some(source_hash(&payload.code_body[..])),
+                _ => None,
+            },
+            deployer: pending.deployer.address.clone(),
+            sponsor: pending.sponsor.as_ref().map(|sponsor| sponsor.address.clone()),
+            nonce: pending.nonce,
+            fee: pending.fee,
+            contract_name: pending.contract_name,
+            call: pending.call,
+        })
+        .collect();
+    let plan = DeploymentPlan {
+        project,
+        environment: environment.to_string(),
+        node: options.node_url.clone(),
+        transactions,
+    };
+    let content = match path.extension() {
+        Some(extension) if extension == "toml" => toml::to_string(&plan).map_err(|e| e.to_string()),
+        _ => serde_json::to_string_pretty(&plan).map_err(|e| e.to_string()),
+    }
+    .map_err(|e| format!("Error: unable to serialize the plan: {}", e))?;
+    fs::write(path, content).map_err(|e| format!("Error: unable to write {}: {}", path.display(), e))?;
+    info!("Plan with {} transaction(s) written to {}", plan.transactions.len(), path.display());
+    Ok(())
+}
+
+/// Contract of the manifest written by `deploy --export-manifest`, to register
+/// a deployment with a block explorer or an indexer.
+#[derive(Serialize, Debug)]
+pub struct ExplorerContract {
+    pub txid: String,
+    pub source: String,
+    pub interface: Option<ContractInterface>,
+}
+
+/// Write the txid, source and interface of each of the `deployed` contracts,
+/// keyed by principal (`<deployer>.<name>`), to the JSON file at `path`. The
+/// sources are the ones of the `initial_contracts` of the settings.
+pub fn export_explorer_manifest(
+    settings: &SessionSettings,
+    deployed: &[DeployedContract],
+    interfaces: &BTreeMap<String, ContractInterface>,
+    path: &Path,
+) -> Result<(), String> {
+    let mut manifest = BTreeMap::new();
+    for contract in deployed.iter() {
+        let source = settings
+            .initial_contracts
+            .iter()
+            .find(|initial| initial.name.as_deref() == Some(contract.contract_name.as_str()))
+            .map(|initial| initial.code.clone())
+            .unwrap_or_default();
+        manifest.insert(
+            format!("{}.{}", contract.deployer, contract.contract_name),
+            ExplorerContract {
+                txid: contract.txid.clone(),
+                source,
+                interface: interfaces.get(&contract.contract_name).cloned(),
+            },
+        );
+    }
+    let content = serde_json::to_string_pretty(&manifest)
+        .map_err(|e| format!("Error: unable to serialize the manifest: {}", e))?;
+    fs::write(path, content).map_err(|e| format!("Error: unable to write {}: {}", path.display(), e))?;
+    info!("Manifest of {} contract(s) written to {}", manifest.len(), path.display());
+    Ok(())
+}
+
+/// Broadcast the transactions of a bundle, in order, after checking that each
+/// of them matches the contract name, source hash and nonce it is listed with.
+/// The contracts broadcasted are added to `report`.
+pub fn broadcast_bundle(
+    path: &Path,
+    options: &PublishOptions,
+    report: &mut DeploymentReport,
+) -> Result<(), String> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("Error: unable to read {}: {}", path.display(), e))?;
+    let bundle: DeploymentBundle = serde_json::from_str(&content)
+        .map_err(|e| format!("Error: unable to parse {}: {}", path.display(), e))?;
+
+    let mut transactions = vec![];
+    for entry in bundle.transactions.iter() {
+        let origin = format!("transaction of {} in {}", entry.contract_name, path.display());
+        let (tx, payload) = decode_transaction(&entry.tx, &origin)?;
+        if payload.name.as_str() != entry.contract_name
+            || source_hash(&payload.code_body[..]) != entry.source_hash
+            || tx.get_origin_nonce() != entry.nonce
+        {
+            return Err(format!("Error: {} does not match its metadata", origin));
+        }
+        transactions.push((entry, tx));
+    }
+
+    let client = build_client(options)?;
+    report.record_starting_balances(&client, &options.node_url, transactions.iter().map(|(_, tx)| fee_payer_address(tx)));
+    report.check_balances(transactions.iter().map(|(_, tx)| (fee_payer_address(tx), fee_payer_address(tx), tx.get_tx_fee())))?;
+    for (entry, tx) in transactions.iter() {
+        let txid = broadcast_transaction(&client, &options.node_url, &entry.contract_name, tx)?;
+        info!("Broadcasted {} (txid: {})", entry.contract_name, txid);
+        report.contracts.push(deployed_contract(&entry.contract_name, txid, tx));
+    }
+    Ok(())
+}
@@ -4,6 +4,7 @@ use deno_core::error::AnyError;
 use deno_runtime::permissions::Permissions;
 use deno_runtime::worker::MainWorker;
 use deno_runtime::worker::WorkerOptions;
+use std::cell::RefCell;
 use std::rc::Rc;
 use std::sync::Arc;
 use serde::Serialize;
@@ -22,57 +23,26 @@ use deno_core::ModuleSpecifier;
 use deno_runtime::web_worker::WebWorkerOptions;
 use deno_runtime::ops::worker_host::CreateWebWorkerCb;
 use deno_runtime::web_worker::WebWorker;
+use clarity_repl::repl::SessionSettings;
 
 mod sessions {
     use std::sync::Mutex;
-    use std::fs;
-    use std::env;
     use std::collections::HashMap;
     use deno_core::error::AnyError;
-    use clarity_repl::repl::{self, Session};
+    use clarity_repl::repl::{self, Session, SessionSettings};
     use clarity_repl::repl::settings::Account;
-    use crate::types::{ChainConfig, MainConfig};
     use super::TransactionArgs;
 
     lazy_static! {
         static ref SESSIONS: Mutex<HashMap<u32, Session>> = Mutex::new(HashMap::new());
     }
 
-    pub fn handle_setup_chain(transactions: Vec<TransactionArgs>) -> Result<(u32, Vec<Account>), AnyError> {
+    pub fn handle_setup_chain(transactions: Vec<TransactionArgs>, base_settings: &SessionSettings) -> Result<(u32, Vec<Account>), AnyError> {
         let mut sessions = SESSIONS.lock().unwrap();
         let session_id = sessions.len() as u32;
 
         let mut settings = repl::SessionSettings::default();
-        let root_path = env::current_dir().unwrap();
-        let mut project_config_path = root_path.clone();
-        project_config_path.push("Clarinet.toml");
-    
-        let mut chain_config_path = root_path.clone();
-        chain_config_path.push("settings");
-        chain_config_path.push("Development.toml");
-    
-        let project_config = MainConfig::from_path(&project_config_path);
-        let chain_config = ChainConfig::from_path(&chain_config_path);
-    
-        let mut deployer_address = None;
-        let mut initial_deployer = None;
-
-        for (name, account) in chain_config.accounts.iter() {
-            let account = repl::settings::Account {
-                name: name.clone(),
-                balance: account.balance,
-                address: account.address.clone(),
-                mnemonic: account.mnemonic.clone(),
-                derivation: account.derivation.clone(),
-            };
-            if name == "deployer" {
-                initial_deployer = Some(account.clone());
-                deployer_address = Some(account.address.clone());
-            }
-            settings
-                .initial_accounts
-                .push(account);
-        }
+        settings.initial_accounts = base_settings.initial_accounts.clone();
 
         for tx in transactions.iter() {
           let deployer = Some(tx.sender.clone());
@@ -99,22 +69,11 @@ mod sessions {
           // }
         }
 
-        for (name, config) in project_config.ordered_contracts().iter() {
-            let mut contract_path = root_path.clone();
-            contract_path.push(&config.path);
-    
-            let code = fs::read_to_string(&contract_path).unwrap();
-    
-            settings
-                .initial_contracts
-                .push(repl::settings::InitialContract {
-                    code: code,
-                    name: Some(name.clone()),
-                    deployer: deployer_address.clone(),
-                });
-        }
-        settings.initial_deployer = initial_deployer;
-        settings.include_boot_contracts = true;
+        settings
+            .initial_contracts
+            .extend(base_settings.initial_contracts.iter().cloned());
+        settings.initial_deployer = base_settings.initial_deployer.clone();
+        settings.include_boot_contracts = base_settings.include_boot_contracts;
   
         let mut session = Session::new(settings.clone());
         session.start();
@@ -135,14 +94,52 @@ mod sessions {
     }
 }
 
-pub async fn run_tests(files: Vec<String>) -> Result<(), AnyError> {
+/// Outcome of a test suite run, as reported by the runtime once all the
+/// test cases have been executed.
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct TestResults {
+    pub passed: u32,
+    pub failed: u32,
+    pub ignored: u32,
+    /// Wall-clock duration of the suite, in milliseconds
+    #[serde(default)]
+    pub duration: u64,
+    /// Test cases run, in order
+    #[serde(default)]
+    pub tests: Vec<TestCaseResult>,
+    /// Whether some test cases were marked `only`, skipping all the others
+    #[serde(default, rename = "usedOnly")]
+    pub used_only: bool,
+}
+
+impl TestResults {
+    /// A suite succeeds when no test case failed and none was marked `only`,
+    /// as the test cases it skips would otherwise go unnoticed.
+    pub fn success(&self) -> bool {
+        self.failed == 0 && !self.used_only
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TestCaseResult {
+    pub name: String,
+    /// passed, failed or ignored
+    pub status: String,
+    /// Wall-clock duration of the test case, in milliseconds
+    pub duration: u64,
+    /// Message of the error failing the test case
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+pub async fn run_tests(files: Vec<String>, settings: SessionSettings, args: Vec<String>, quiet: bool) -> Result<TestResults, AnyError> {
 
     let fail_fast = true;
-    let quiet = false;
     let filter = None;
 
     let mut flags = Flags::default();
     flags.unstable = true;
+    flags.argv = args;
     let program_state = ProgramState::build(flags.clone()).await?;
     let permissions = Permissions::from_options(&flags.clone().into());
     let cwd = std::env::current_dir().expect("No current directory");
@@ -156,8 +153,8 @@ pub async fn run_tests(files: Vec<String>) -> Result<(), AnyError> {
       tools::test_runner::prepare_test_modules_urls(include, &cwd)?;
   
     if test_modules.is_empty() {
-      println!("No matching test modules found");
-      return Ok(());
+      warn!("No matching test modules found");
+      return Ok(TestResults::default());
     }
     let main_module = deno_core::resolve_path("$deno$test.ts")?;
     // Create a dummy source file.
@@ -184,7 +181,13 @@ pub async fn run_tests(files: Vec<String>) -> Result<(), AnyError> {
     let mut worker =
       create_main_worker(&program_state, main_module.clone(), permissions);
 
-    worker.js_runtime.register_op("setup_chain", op(setup_chain));
+    let results = Rc::new(RefCell::new(TestResults::default()));
+    let results_handle = results.clone();
+    worker.js_runtime.register_op("setup_chain", op(move |args: SetupChainArgs| setup_chain(args, &settings)));
+    worker.js_runtime.register_op("report_test_results", op(move |args: TestResults| {
+      results_handle.replace(args);
+      Ok(())
+    }));
     worker.js_runtime.register_op("mine_block", op(mine_block));
     worker.js_runtime.register_op("mine_empty_blocks", op(mine_empty_blocks));
     worker.js_runtime.register_op("call_read_only_fn", op(call_read_only_fn));
@@ -210,7 +213,8 @@ pub async fn run_tests(files: Vec<String>) -> Result<(), AnyError> {
       return Err(e);
     }
 
-    Ok(())
+    let results = results.borrow().clone();
+    Ok(results)
 }
 
 fn create_web_worker_callback(
@@ -371,8 +375,8 @@ struct SetupChainArgs {
   transactions: Vec<TransactionArgs>
 }
 
-fn setup_chain(args: SetupChainArgs) -> Result<Value, AnyError> {
-    let (session_id, accounts) = sessions::handle_setup_chain(args.transactions)?;
+fn setup_chain(args: SetupChainArgs, settings: &SessionSettings) -> Result<Value, AnyError> {
+    let (session_id, accounts) = sessions::handle_setup_chain(args.transactions, settings)?;
 
     Ok(json!({
         "session_id": session_id,
@@ -44,8 +44,23 @@ mod version;
 
 mod deno;
 
-pub fn run_tests(files: Vec<String>) {
-    block_on(deno::run_tests(files));
+use std::path::Path;
+use clarity_repl::repl::SessionSettings;
+use deno_core::error::AnyError;
+
+pub use deno::{TestCaseResult, TestResults};
+
+/// Run the test `files` against `settings`. `args` are exposed to the tests
+/// as `Deno.args`. With `quiet`, neither the progress of the tests nor their
+/// logs are printed.
+pub fn run_tests(files: Vec<String>, settings: SessionSettings, args: Vec<String>, quiet: bool) -> Result<TestResults, AnyError> {
+    block_on(deno::run_tests(files, settings, args, quiet))
+}
+
+/// Test files (`*_test.ts`, `*.test.ts`, ...) found under `dir`, sorted.
+pub fn discover_test_files(dir: &Path) -> Result<Vec<String>, AnyError> {
+    let files = tools::test_runner::discover_test_files(dir)?;
+    Ok(files.into_iter().map(|file| file.to_string_lossy().to_string()).collect())
 }
 
 pub fn create_basic_runtime() -> tokio::runtime::Runtime {
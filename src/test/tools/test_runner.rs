@@ -32,6 +32,13 @@ fn is_supported(p: &Path) -> bool {
   }
 }
 
+/// Test files found under `dir`, recursively, sorted by path.
+pub fn discover_test_files(dir: &Path) -> Result<Vec<PathBuf>, AnyError> {
+  let mut test_files = fs_util::collect_files(&[dir.to_path_buf()], &[], is_supported)?;
+  test_files.sort();
+  Ok(test_files)
+}
+
 pub fn prepare_test_modules_urls(
   include: Vec<String>,
   root_path: &PathBuf,
@@ -77,17 +84,29 @@ pub fn render_test_file(
   }
 
   let options = if let Some(filter) = filter {
-    json!({ "failFast": fail_fast, "reportToConsole": !quiet, "disableLog": quiet, "filter": filter })
+    json!({ "failFast": fail_fast, "reportToConsole": !quiet, "disableLog": quiet, "exitOnFail": false, "filter": filter })
   } else {
-    json!({ "failFast": fail_fast, "reportToConsole": !quiet, "disableLog": quiet })
+    json!({ "failFast": fail_fast, "reportToConsole": !quiet, "disableLog": quiet, "exitOnFail": false })
   };
 
   test_file.push_str("// @ts-ignore\n");
 
   test_file.push_str(&format!(
-    "await Deno[Deno.internal].runTests({});\n",
+    "const results = await Deno[Deno.internal].runTests({});\n",
     options
   ));
 
+  // Results are handed back to clarinet, which decides how the process exits.
+  test_file.push_str("// @ts-ignore\n");
+  test_file.push_str("Deno.core.ops();\n");
+  test_file.push_str("// @ts-ignore\n");
+  test_file.push_str(
+    concat!(
+      "Deno.core.jsonOpSync(\"report_test_results\", { passed: results.passed, failed: results.failed, ignored: results.ignored, ",
+      "duration: results.duration, tests: results.results.map((r) => ({ name: r.name, status: r.status, duration: r.duration, ",
+      "error: r.error ? String(r.error.message ?? r.error) : undefined })), usedOnly: results.usedOnly });\n",
+    ),
+  );
+
   test_file
 }
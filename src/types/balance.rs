@@ -0,0 +1,95 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+use toml::value::Value;
+
+/// Number of µSTX in one STX.
+pub const MICRO_STX_PER_STX: u128 = 1_000_000;
+
+/// Amount of µSTX, as written in the settings (an integer) or reported by a
+/// node (a hex encoded string). Displayed in STX.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(transparent)]
+pub struct Balance(pub u128);
+
+impl Balance {
+    pub fn micro_stx(self) -> u128 {
+        self.0
+    }
+
+    /// Balance of the settings: a non negative integer, or a string parsed
+    /// as by `from_str`.
+    pub fn from_toml(value: &Value) -> Result<Balance, String> {
+        match value {
+            Value::Integer(amount) if *amount >= 0 => Ok(Balance(*amount as u128)),
+            Value::Integer(amount) => Err(format!("negative balance {}", amount)),
+            Value::String(amount) => amount.parse(),
+            value => Err(format!("expected an amount of µSTX, found {}", value)),
+        }
+    }
+
+    /// Balance as expected by the sessions, which hold balances as `u64`.
+    pub fn to_u64(self) -> Result<u64, String> {
+        u64::try_from(self.0).map_err(|_| format!("balance {} is too large", self.0))
+    }
+
+    pub fn saturating_sub(self, amount: u128) -> Balance {
+        Balance(self.0.saturating_sub(amount))
+    }
+}
+
+impl FromStr for Balance {
+    type Err = String;
+
+    /// Parse an amount of µSTX, hex encoded when prefixed with `0x`, as
+    /// returned by the nodes, and decimal otherwise.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let amount = match s.strip_prefix("0x") {
+            Some(hex) => u128::from_str_radix(hex, 16),
+            None => s.parse::<u128>(),
+        };
+        amount
+            .map(Balance)
+            .map_err(|e| format!("invalid balance {}: {}", s, e))
+    }
+}
+
+impl fmt::Display for Balance {
+    /// `1.5 STX`, without trailing zeros.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let stx = self.0 / MICRO_STX_PER_STX;
+        let micro_stx = self.0 % MICRO_STX_PER_STX;
+        if micro_stx == 0 {
+            return write!(f, "{} STX", stx);
+        }
+        let decimals = format!("{:06}", micro_stx);
+        write!(f, "{}.{} STX", stx, decimals.trim_end_matches('0'))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn node_and_settings_balances_are_parsed() {
+        assert_eq!("0x00000000000000000000000000989680".parse::<Balance>(), Ok(Balance(10_000_000)));
+        assert_eq!("10000000".parse::<Balance>(), Ok(Balance(10_000_000)));
+        assert!("0xzz".parse::<Balance>().is_err());
+        assert!("-1".parse::<Balance>().is_err());
+
+        assert_eq!(Balance::from_toml(&Value::Integer(10_000_000)), Ok(Balance(10_000_000)));
+        assert_eq!(Balance::from_toml(&Value::String("10000000".to_string())), Ok(Balance(10_000_000)));
+        assert!(Balance::from_toml(&Value::Integer(-1)).is_err());
+        assert!(Balance::from_toml(&Value::Boolean(true)).is_err());
+    }
+
+    #[test]
+    fn balances_are_displayed_in_stx() {
+        assert_eq!(Balance(10_000_000).to_string(), "10 STX");
+        assert_eq!(Balance(1_500_000).to_string(), "1.5 STX");
+        assert_eq!(Balance(1).to_string(), "0.000001 STX");
+        assert!(Balance(u128::from(u64::MAX) + 1).to_u64().is_err());
+    }
+}
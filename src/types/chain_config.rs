@@ -1,17 +1,22 @@
 use std::{collections::BTreeMap, fs::File};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::{
     io::{BufReader, Read},
 };
 use toml::value::Value;
 use bip39::{Mnemonic};
 use crate::utils::mnemonic;
-use clarity_repl::clarity::util::StacksAddress;
-use clarity_repl::clarity::util::secp256k1::Secp256k1PublicKey;
-use tiny_hderive::bip32::ExtendedPrivKey;
-use secp256k1::{SecretKey, PublicKey};
+use super::{Balance, Network};
 
-const DEFAULT_DERIVATION_PATH: &str = "m/44'/5757'/0'/0/0";
+/// SLIP-44 coin type of Stacks.
+pub const DEFAULT_COIN_TYPE: u32 = 5757;
+/// Derivation path of the accounts omitting `derivation`, `{coin_type}` being
+/// replaced by the coin type of the network.
+pub const DEFAULT_DERIVATION_TEMPLATE: &str = "m/44'/{coin_type}'/0'/0/0";
+/// Environment variable overriding the `node_url` of the settings.
+pub const NODE_URL_VAR: &str = "CLARINET_NODE_URL";
+/// Environment variable overriding the mnemonic of the `deployer` account.
+pub const DEPLOYER_MNEMONIC_VAR: &str = "CLARINET_DEPLOYER_MNEMONIC";
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ChainConfigFile {
@@ -22,7 +27,11 @@ pub struct ChainConfigFile {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct NetworkConfigFile {
     name: String,
+    node_url: Option<String>,
+    /// Former name of `node_url`
     node_rpc_address: Option<String>,
+    coin_type: Option<u32>,
+    derivation: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -41,8 +50,60 @@ pub struct ChainConfig {
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct NetworkConfig {
-    name: String,
-    node_rpc_address: Option<String>,
+    pub name: String,
+    /// RPC endpoint of the node of the environment, defaulting to a public
+    /// node for testnet and mainnet, and to a local one otherwise
+    pub node_url: String,
+    pub coin_type: u32,
+    pub derivation: String,
+}
+
+impl NetworkConfig {
+    fn from_config_file(config_file: &NetworkConfigFile) -> Result<NetworkConfig, String> {
+        let coin_type = config_file.coin_type.unwrap_or(DEFAULT_COIN_TYPE);
+        // Coin types are hardened BIP32 indexes.
+        if coin_type >= 0x8000_0000 {
+            return Err(format!("Error: invalid coin_type {}: must be lower than 2147483648", coin_type));
+        }
+        let derivation = config_file
+            .derivation
+            .clone()
+            .unwrap_or(DEFAULT_DERIVATION_TEMPLATE.to_string());
+        if !derivation.starts_with("m/") {
+            return Err(format!("Error: invalid derivation template {}: must start with m/", derivation));
+        }
+        let node_url = config_file
+            .node_url
+            .clone()
+            .or_else(|| config_file.node_rpc_address.clone())
+            .unwrap_or_else(|| config_file.name.parse::<Network>().unwrap_or_default().default_node_url().to_string());
+        Ok(NetworkConfig {
+            name: config_file.name.clone(),
+            node_url: node_url.trim_end_matches('/').to_string(),
+            coin_type,
+            derivation,
+        })
+    }
+
+    /// Derivation path of the `index`-th account derived from a mnemonic: the
+    /// default derivation path, its last index replaced by `index`.
+    pub fn derivation_path(&self, index: u32) -> String {
+        let path = self.default_derivation_path();
+        match path.rfind('/') {
+            Some(separator) => format!("{}/{}", &path[..separator], index),
+            None => path,
+        }
+    }
+
+    /// Chain of the network, defaulting to a development one.
+    pub fn chain(&self) -> Network {
+        self.name.parse::<Network>().unwrap_or_default()
+    }
+
+    /// Derivation path used for the accounts without an explicit `derivation`.
+    pub fn default_derivation_path(&self) -> String {
+        self.derivation.replace("{coin_type}", &self.coin_type.to_string())
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -54,93 +115,369 @@ pub struct AccountConfig {
     pub is_mainnet: bool
 }
 
-impl ChainConfig {
-    pub fn from_path(path: &PathBuf) -> ChainConfig {
-        let path = match File::open(path) {
-            Ok(path) => path,
-            Err(_) => {
-                let error = format!("Unable to open file {:?}", path.to_str());
-                panic!(error)
+impl AccountConfig {
+    /// Account of the `[accounts.<account_name>]` table of the settings of
+    /// `network`, its address derived from its mnemonic.
+    fn from_settings(account_name: &str, account_settings: &toml::map::Map<String, Value>, network: &NetworkConfig) -> Result<AccountConfig, String> {
+        let balance = match account_settings.get("balance") {
+            Some(balance) => Balance::from_toml(balance)
+                .and_then(Balance::to_u64)
+                .map_err(|e| format!("Error: invalid balance for account {}: {}", account_name, e))?,
+            None => 0,
+        };
+
+        let is_mainnet = match account_settings.get("is_mainnet") {
+            Some(Value::Boolean(is_mainnet)) => *is_mainnet,
+            _ => false,
+        };
+
+        let mnemonic = match account_settings.get("mnemonic") {
+            Some(Value::String(words)) => Mnemonic::parse(words)
+                .map_err(|e| format!("Error: invalid mnemonic for account {}: {}", account_name, e))?
+                .to_string(),
+            _ => {
+                let entropy = &[0x33, 0xE4, 0x6B, 0xB1, 0x3A, 0x74, 0x6E, 0xA4, 0x1C, 0xDD, 0xE4, 0x5C, 0x90, 0x84, 0x6A, 0x79]; // todo(ludo): rand
+                Mnemonic::from_entropy(entropy).unwrap().to_string()
             }
         };
-        let mut config_file_reader = BufReader::new(path);
-        let mut config_file_buffer = vec![];
-        config_file_reader
-            .read_to_end(&mut config_file_buffer)
-            .unwrap();
-        let config_file: ChainConfigFile = toml::from_slice(&config_file_buffer[..]).unwrap();
+
+        let derivation = match account_settings.get("derivation") {
+            Some(Value::String(derivation)) => derivation.to_string(),
+            _ => network.default_derivation_path(),
+        };
+
+        let address = mnemonic::get_stx_address_from_mnemonic(&mnemonic, &derivation, network.chain().address_version())
+            .map_err(|e| format!("Error: unable to derive address of account {}: {}", account_name, e))?;
+
+        Ok(AccountConfig {
+            mnemonic,
+            derivation,
+            balance,
+            address,
+            is_mainnet,
+        })
+    }
+}
+
+/// `[accounts.<name>]` tables of a settings file, by name.
+fn account_tables(config_file: &ChainConfigFile) -> impl Iterator<Item = (&str, &toml::map::Map<String, Value>)> {
+    let accounts = match config_file.accounts {
+        Some(Value::Table(ref accounts)) => Some(accounts),
+        _ => None,
+    };
+    accounts.into_iter().flatten().filter_map(|(account_name, account_settings)| match account_settings {
+        Value::Table(account_settings) => Some((account_name.as_str(), account_settings)),
+        _ => None,
+    })
+}
+
+impl ChainConfig {
+    /// Load a settings file, with the overrides set in the environment.
+    pub fn from_path(path: &Path) -> Result<ChainConfig, String> {
+        let mut value = ChainConfig::read_with_base(path, &mut vec![])?;
+        overlay(&mut value, env_overrides(|name| std::env::var(name).ok()));
+        let config_file: ChainConfigFile = value
+            .try_into()
+            .map_err(|e| format!("Error: unable to parse {}: {}", path.display(), e))?;
         ChainConfig::from_config_file(config_file)
     }
 
-    pub fn from_config_file(config_file: ChainConfigFile) -> ChainConfig {
+    /// Address of each account of a settings file, or the reason it can't be
+    /// derived, by account name. Unlike `from_path`, an account failing to
+    /// derive doesn't prevent the others from being derived.
+    pub fn derive_account_addresses(path: &Path) -> Result<BTreeMap<String, Result<String, String>>, String> {
+        let mut value = ChainConfig::read_with_base(path, &mut vec![])?;
+        overlay(&mut value, env_overrides(|name| std::env::var(name).ok()));
+        let config_file: ChainConfigFile = value
+            .try_into()
+            .map_err(|e| format!("Error: unable to parse {}: {}", path.display(), e))?;
+        let network = NetworkConfig::from_config_file(&config_file.network)?;
+        Ok(account_tables(&config_file)
+            .map(|(account_name, account_settings)| {
+                let address = AccountConfig::from_settings(account_name, account_settings, &network)
+                    .map(|account| account.address);
+                (account_name.to_string(), address)
+            })
+            .collect())
+    }
+
+    /// Read a settings file, overlaid on top of the file it `extends` (a path
+    /// relative to its own directory), if any. `visited` holds the files being
+    /// read, to detect inheritance cycles.
+    fn read_with_base(path: &Path, visited: &mut Vec<PathBuf>) -> Result<Value, String> {
+        let path_str = path.display().to_string();
+        let canonical_path = path
+            .canonicalize()
+            .map_err(|e| format!("Error: unable to open {}: {}", path_str, e))?;
+        if visited.contains(&canonical_path) {
+            let mut chain: Vec<String> = visited.iter().map(|p| p.display().to_string()).collect();
+            chain.push(path_str);
+            return Err(format!("Error: settings inheritance cycle: {}", chain.join(" -> ")));
+        }
+
+        let file = File::open(path).map_err(|e| format!("Error: unable to open {}: {}", path_str, e))?;
+        let mut config_file_reader = BufReader::new(file);
+        let mut config_file_buffer = vec![];
+        config_file_reader
+            .read_to_end(&mut config_file_buffer)
+            .map_err(|e| format!("Error: unable to read {}: {}", path_str, e))?;
+        let mut value: Value = toml::from_slice(&config_file_buffer[..])
+            .map_err(|e| format!("Error: unable to parse {}: {}", path_str, e))?;
 
-        let network = NetworkConfig {
-            name: config_file.network.name.clone(),
-            node_rpc_address: config_file.network.node_rpc_address.clone(),
+        let extends = match value.as_table_mut().and_then(|table| table.remove("extends")) {
+            Some(Value::String(extends)) => extends,
+            Some(_) => return Err(format!("Error: unable to parse {}: extends must be a path", path_str)),
+            None => return Ok(value),
         };
+        visited.push(canonical_path);
+        let base_path = path.parent().unwrap_or(Path::new(".")).join(extends);
+        let mut base = ChainConfig::read_with_base(&base_path, visited)?;
+        visited.pop();
+        overlay(&mut base, value);
+        Ok(base)
+    }
 
+    pub fn from_config_file(config_file: ChainConfigFile) -> Result<ChainConfig, String> {
+        let network = NetworkConfig::from_config_file(&config_file.network)?;
         let mut config = ChainConfig {
             network,
             accounts: BTreeMap::new(),
         };
+        for (account_name, account_settings) in account_tables(&config_file) {
+            let account = AccountConfig::from_settings(account_name, account_settings, &config.network)?;
+            config.accounts.insert(account_name.to_string(), account);
+        }
+        for (first, second) in config.accounts_sharing_an_address().iter() {
+            warn!(
+                "accounts {} and {} derive to the same address {}, check their derivation paths",
+                first, second, config.accounts[first].address
+            );
+        }
+        Ok(config)
+    }
+
+    /// Pairs of accounts deriving to the same address, usually an account
+    /// copied without changing its derivation index.
+    pub fn accounts_sharing_an_address(&self) -> Vec<(String, String)> {
+        let mut owners: BTreeMap<&str, &str> = BTreeMap::new();
+        let mut pairs = vec![];
+        for (name, account) in self.accounts.iter() {
+            match owners.get(account.address.as_str()) {
+                Some(owner) => pairs.push((owner.to_string(), name.clone())),
+                None => {
+                    owners.insert(&account.address, name);
+                }
+            }
+        }
+        pairs
+    }
+}
 
-        match config_file.accounts {
-            Some(Value::Table(accounts)) => {
-                for (account_name, account_settings) in accounts.iter() {
-                    match account_settings {
-                        Value::Table(account_settings) => {
-                            let balance = match account_settings.get("balance") {
-                                Some(Value::Integer(balance)) => *balance as u64,
-                                _ => 0,
-                            };
-
-                            let is_mainnet = match account_settings.get("is_mainnet") {
-                                Some(Value::Boolean(is_mainnet)) => *is_mainnet,
-                                _ => false,
-                            };
-
-                            let mnemonic = match account_settings.get("mnemonic") {
-                                Some(Value::String(words)) => Mnemonic::parse(words).unwrap().to_string(),
-                                _ => {
-                                    let entropy = &[0x33, 0xE4, 0x6B, 0xB1, 0x3A, 0x74, 0x6E, 0xA4, 0x1C, 0xDD, 0xE4, 0x5C, 0x90, 0x84, 0x6A, 0x79]; // todo(ludo): rand
-                                    Mnemonic::from_entropy(entropy).unwrap().to_string()
-                                }
-                            };
-
-                            let derivation = match account_settings.get("derivation") {
-                                Some(Value::String(derivation)) => derivation.to_string(),
-                                _ => DEFAULT_DERIVATION_PATH.to_string(),
-                            }; // todo(ludo): use derivation path
-
-                            let bip39_seed = match mnemonic::get_bip39_seed_from_mnemonic(&mnemonic, "") {
-                                Ok(bip39_seed) => bip39_seed,
-                                Err(_) => panic!(),
-                            };
-
-                            let ext = ExtendedPrivKey::derive(&bip39_seed[..], DEFAULT_DERIVATION_PATH).unwrap();
-                            let secret_key = SecretKey::parse_slice(&ext.secret()).unwrap();
-                            let public_key = PublicKey::from_secret_key(&secret_key);
-                            let pub_key = Secp256k1PublicKey::from_slice(&public_key.serialize_compressed()).unwrap();
-                            let version = 26; // todo(ludo): un-hardcode this
-                            let address = StacksAddress::from_public_key(version, pub_key).unwrap().to_string();
-
-                            config.accounts.insert(
-                                account_name.to_string(),
-                                AccountConfig {
-                                    mnemonic,
-                                    derivation,
-                                    balance,
-                                    address,
-                                    is_mainnet,
-                                }
-                            );
-                        }
-                        _ => {}
+/// Settings set by the environment variables, as read by `lookup`, to be
+/// overlaid on the settings files. Empty variables are ignored.
+fn env_overrides<F>(lookup: F) -> Value
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let lookup = |name: &str| lookup(name).filter(|value| !value.trim().is_empty());
+    let mut overrides = toml::map::Map::new();
+    if let Some(node_url) = lookup(NODE_URL_VAR) {
+        let mut network = toml::map::Map::new();
+        network.insert("node_url".to_string(), Value::String(node_url));
+        overrides.insert("network".to_string(), Value::Table(network));
+    }
+    if let Some(mnemonic) = lookup(DEPLOYER_MNEMONIC_VAR) {
+        let mut deployer = toml::map::Map::new();
+        deployer.insert("mnemonic".to_string(), Value::String(mnemonic.trim().to_string()));
+        let mut accounts = toml::map::Map::new();
+        accounts.insert("deployer".to_string(), Value::Table(deployer));
+        overrides.insert("accounts".to_string(), Value::Table(accounts));
+    }
+    Value::Table(overrides)
+}
+
+/// Tables are merged key by key (accounts by name, then field by field),
+/// any other value of `overrides` replaces the one of `base`.
+fn overlay(base: &mut Value, overrides: Value) {
+    match (base, overrides) {
+        (Value::Table(base), Value::Table(overrides)) => {
+            for (key, value) in overrides.into_iter() {
+                match base.get_mut(&key) {
+                    Some(existing) => overlay(existing, value),
+                    None => {
+                        base.insert(key, value);
                     }
                 }
             }
-            _ => {}
+        }
+        (base, overrides) => *base = overrides,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn environment_overrides_the_settings() {
+        let mut value: Value = toml::from_str(r#"
+[network]
+name = "testnet"
+node_url = "http://file:20443"
+
+[accounts.deployer]
+balance = 1000
+"#).unwrap();
+        let original = ChainConfig::from_config_file(value.clone().try_into().unwrap()).unwrap();
+
+        let mnemonic = "point approve language letter cargo rough similar wrap focus edge polar task olympic tobacco cinnamon drop lawn boring sort trade senior screen tiger climb";
+        overlay(&mut value, env_overrides(|name| match name {
+            NODE_URL_VAR => Some("http://env:20443".to_string()),
+            DEPLOYER_MNEMONIC_VAR => Some(mnemonic.to_string()),
+            _ => None,
+        }));
+        let config = ChainConfig::from_config_file(value.try_into().unwrap()).unwrap();
+        assert_eq!(config.network.node_url, "http://env:20443");
+        assert_eq!(config.accounts["deployer"].mnemonic, mnemonic);
+        assert_eq!(config.accounts["deployer"].balance, 1000);
+        assert_ne!(config.accounts["deployer"].address, original.accounts["deployer"].address);
+    }
+
+    #[test]
+    fn extended_settings_are_overlaid() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let dir = tempdir.path();
+        fs::write(dir.join("Development.toml"), r#"
+[network]
+name = "development"
+
+[accounts.deployer]
+balance = 1000
+
+[accounts.wallet_1]
+balance = 2000
+"#).unwrap();
+        fs::write(dir.join("Testnet.toml"), r#"
+extends = "Development.toml"
+
+[network]
+name = "testnet"
+
+[accounts.wallet_1]
+balance = 5000
+"#).unwrap();
+
+        let config = ChainConfig::from_path(&dir.join("Testnet.toml"));
+        let config = config.unwrap();
+        assert_eq!(config.network.name, "testnet");
+        assert_eq!(config.accounts["deployer"].balance, 1000);
+        assert_eq!(config.accounts["wallet_1"].balance, 5000);
+    }
+
+    #[test]
+    fn accounts_default_to_the_network_coin_type() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let dir = tempdir.path();
+        fs::write(dir.join("Custom.toml"), r#"
+[network]
+name = "custom"
+coin_type = 1
+
+[accounts.deployer]
+balance = 1000
+
+[accounts.wallet_1]
+derivation = "m/44'/5757'/0'/0/1"
+"#).unwrap();
+        fs::write(dir.join("Invalid.toml"), "[network]\nname = \"invalid\"\ncoin_type = 2147483648\n").unwrap();
+
+        let config = ChainConfig::from_path(&dir.join("Custom.toml"));
+        let invalid = ChainConfig::from_path(&dir.join("Invalid.toml"));
+        let config = config.unwrap();
+        assert_eq!(config.accounts["deployer"].derivation, "m/44'/1'/0'/0/0");
+        assert_eq!(config.accounts["wallet_1"].derivation, "m/44'/5757'/0'/0/1");
+        assert!(invalid.unwrap_err().contains("coin_type"));
+        assert_eq!(config.network.derivation_path(7), "m/44'/1'/0'/0/7");
+    }
+
+    #[test]
+    fn account_derivations_are_reported_one_by_one() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let dir = tempdir.path();
+        fs::write(dir.join("Development.toml"), r#"
+[network]
+name = "Development"
+
+[accounts.deployer]
+balance = 1000
+
+[accounts.wallet_1]
+derivation = "m/44'/invalid"
+"#).unwrap();
+
+        let addresses = ChainConfig::derive_account_addresses(&dir.join("Development.toml"));
+        let config = ChainConfig::from_path(&dir.join("Development.toml"));
+        let addresses = addresses.unwrap();
+        assert!(addresses["deployer"].as_ref().unwrap().starts_with("ST"));
+        assert!(addresses["wallet_1"].as_ref().unwrap_err().contains("wallet_1"));
+        assert!(config.is_err());
+    }
+
+    #[test]
+    fn node_url_defaults_to_the_network_node() {
+        let node_url = |content: &str| {
+            let config_file: ChainConfigFile = toml::from_str(content).unwrap();
+            ChainConfig::from_config_file(config_file).unwrap().network.node_url
         };
-        config
+        assert_eq!(node_url("[network]\nname = \"Development\"\n"), "http://localhost:20443");
+        assert_eq!(node_url("[network]\nname = \"mocknet\"\n"), "http://localhost:20443");
+        assert_eq!(node_url("[network]\nname = \"testnet\"\n"), "https://stacks-node-api.testnet.stacks.co");
+        assert_eq!(node_url("[network]\nname = \"mainnet\"\n"), "https://stacks-node-api.mainnet.stacks.co");
+        assert_eq!(
+            node_url("[network]\nname = \"testnet\"\nnode_url = \"http://testnet-node:20443/\"\n"),
+            "http://testnet-node:20443"
+        );
+        assert_eq!(
+            node_url("[network]\nname = \"testnet\"\nnode_rpc_address = \"http://legacy:20443\"\n"),
+            "http://legacy:20443"
+        );
+    }
+
+    #[test]
+    fn accounts_sharing_a_derivation_path_are_reported() {
+        let config_file: ChainConfigFile = toml::from_str(r#"
+[network]
+name = "testnet"
+
+[accounts.deployer]
+mnemonic = "point approve language letter cargo rough similar wrap focus edge polar task olympic tobacco cinnamon drop lawn boring sort trade senior screen tiger climb"
+derivation = "m/44'/5757'/0'/0/0"
+
+[accounts.wallet_1]
+mnemonic = "point approve language letter cargo rough similar wrap focus edge polar task olympic tobacco cinnamon drop lawn boring sort trade senior screen tiger climb"
+derivation = "m/44'/5757'/0'/0/1"
+
+[accounts.wallet_2]
+mnemonic = "point approve language letter cargo rough similar wrap focus edge polar task olympic tobacco cinnamon drop lawn boring sort trade senior screen tiger climb"
+derivation = "m/44'/5757'/0'/0/1"
+"#).unwrap();
+
+        let config = ChainConfig::from_config_file(config_file).unwrap();
+        assert_eq!(
+            config.accounts_sharing_an_address(),
+            vec![("wallet_1".to_string(), "wallet_2".to_string())]
+        );
+    }
+
+    #[test]
+    fn inheritance_cycles_are_rejected() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let dir = tempdir.path();
+        fs::write(dir.join("A.toml"), "extends = \"B.toml\"\n[network]\nname = \"a\"\n").unwrap();
+        fs::write(dir.join("B.toml"), "extends = \"A.toml\"\n[network]\nname = \"b\"\n").unwrap();
+
+        let res = ChainConfig::from_path(&dir.join("A.toml"));
+        assert!(res.unwrap_err().contains("cycle"));
     }
 }
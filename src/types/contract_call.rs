@@ -0,0 +1,144 @@
+use clarity_repl::clarity::ast::parser;
+use clarity_repl::clarity::representations::{
+    ClarityName, ContractName, PreSymbolicExpression, PreSymbolicExpressionType,
+};
+use clarity_repl::clarity::types::{
+    PrincipalData, QualifiedContractIdentifier, StandardPrincipalData, Value,
+};
+
+/// Argument of a contract call listed in `post_deploy`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CallArgument {
+    Value(Value),
+    /// `tx-sender`, standing for the deployer
+    TxSender,
+    /// `.name`, a contract of the deployer
+    DeployerContract(ContractName),
+}
+
+/// `(contract-call? <contract> <function> <args>...)` expression, listed in
+/// the `post_deploy` of a contract and sent after its deployment. Only
+/// literal arguments are supported, as there is no session to evaluate them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContractCall {
+    /// Address of the contract called, `None` for a contract of the deployer (`.name`)
+    pub contract_address: Option<StandardPrincipalData>,
+    pub contract_name: ContractName,
+    pub function_name: ClarityName,
+    pub args: Vec<CallArgument>,
+}
+
+impl ContractCall {
+    pub fn parse(expression: &str) -> Result<ContractCall, String> {
+        let expressions = parser::parse(expression)
+            .map_err(|e| format!("unable to parse '{}': {}", expression, e))?;
+        let list = match expressions.as_slice() {
+            [call] => call.match_list(),
+            _ => None,
+        };
+        let (contract, function_name, args) = match list {
+            Some([keyword, contract, function_name, args @ ..])
+                if keyword.match_atom().map_or(false, |atom| atom.as_str() == "contract-call?") =>
+            {
+                (contract, function_name, args)
+            }
+            _ => {
+                return Err(format!(
+                    "'{}' is not a (contract-call? <contract> <function> <args>...) expression",
+                    expression
+                ))
+            }
+        };
+
+        let (contract_address, contract_name) = match contract.pre_expr {
+            PreSymbolicExpressionType::SugaredContractIdentifier(ref name) => (None, name.clone()),
+            PreSymbolicExpressionType::AtomValue(Value::Principal(PrincipalData::Contract(ref id))) => {
+                (Some(id.issuer.clone()), id.name.clone())
+            }
+            _ => return Err(format!("'{}' does not call a contract", expression)),
+        };
+        let function_name = match function_name.match_atom() {
+            Some(name) => name.clone(),
+            None => return Err(format!("'{}' does not name the function called", expression)),
+        };
+        let args = args
+            .iter()
+            .map(|arg| call_argument(arg).ok_or_else(|| {
+                format!("unsupported argument in '{}': only literal values are supported", expression)
+            }))
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Ok(ContractCall {
+            contract_address,
+            contract_name,
+            function_name,
+            args,
+        })
+    }
+
+    /// Address of the contract called, when deployed by `deployer`.
+    pub fn contract_address(&self, deployer: &StandardPrincipalData) -> StandardPrincipalData {
+        self.contract_address.clone().unwrap_or_else(|| deployer.clone())
+    }
+
+    /// Arguments of the call, when sent by `deployer`.
+    pub fn resolve_args(&self, deployer: &StandardPrincipalData) -> Vec<Value> {
+        self.args
+            .iter()
+            .map(|arg| match arg {
+                CallArgument::Value(value) => value.clone(),
+                CallArgument::TxSender => Value::Principal(PrincipalData::Standard(deployer.clone())),
+                CallArgument::DeployerContract(name) => Value::Principal(PrincipalData::Contract(
+                    QualifiedContractIdentifier::new(deployer.clone(), name.clone()),
+                )),
+            })
+            .collect()
+    }
+}
+
+fn call_argument(arg: &PreSymbolicExpression) -> Option<CallArgument> {
+    match arg.pre_expr {
+        PreSymbolicExpressionType::AtomValue(ref value) => Some(CallArgument::Value(value.clone())),
+        PreSymbolicExpressionType::SugaredContractIdentifier(ref name) => {
+            Some(CallArgument::DeployerContract(name.clone()))
+        }
+        PreSymbolicExpressionType::Atom(ref atom) => match atom.as_str() {
+            "true" => Some(CallArgument::Value(Value::Bool(true))),
+            "false" => Some(CallArgument::Value(Value::Bool(false))),
+            "none" => Some(CallArgument::Value(Value::none())),
+            "tx-sender" => Some(CallArgument::TxSender),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn contract_call_arguments_are_resolved_for_the_deployer() {
+        let call = ContractCall::parse("(contract-call? .token mint u1000 tx-sender .market true)").unwrap();
+        assert_eq!(call.contract_address, None);
+        assert_eq!(call.contract_name.as_str(), "token");
+        assert_eq!(call.function_name.as_str(), "mint");
+
+        let deployer = PrincipalData::parse_standard_principal("ST1HTBVD3JG9C05J7HBJTHGR0GGW7KXW28M5JS8QE").unwrap();
+        let args = call.resolve_args(&deployer);
+        assert_eq!(args[0], Value::UInt(1000));
+        assert_eq!(args[1], Value::Principal(PrincipalData::Standard(deployer.clone())));
+        assert_eq!(
+            args[2],
+            Value::Principal(PrincipalData::Contract(QualifiedContractIdentifier::new(deployer, "market".into())))
+        );
+        assert_eq!(args[3], Value::Bool(true));
+    }
+
+    #[test]
+    fn contract_call_rejects_expressions() {
+        assert!(ContractCall::parse("(contract-call? .token mint (+ u1 u2))").is_err());
+        assert!(ContractCall::parse("(print u1)").is_err());
+        assert!(ContractCall::parse("(contract-call? .token mint u1) (print u1)").is_err());
+    }
+}
@@ -0,0 +1,58 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+use clarity_repl::clarity::representations::ContractName;
+use clarity_repl::clarity::types::PrincipalData;
+
+/// Fully qualified contract identifier (`<address>.<contract-name>`), as
+/// accepted by `contract link`, `contract fork` and the `[[links]]` entries.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ContractId {
+    pub address: String,
+    pub name: String,
+}
+
+impl ContractId {
+    pub fn parse(contract_id: &str) -> Result<ContractId, String> {
+        let components: Vec<&str> = contract_id.split('.').collect();
+        let (address, name) = match components.as_slice() {
+            [address, name] => (*address, *name),
+            _ => {
+                return Err(format!(
+                    "invalid contract id '{}': expected <address>.<contract-name>",
+                    contract_id
+                ))
+            }
+        };
+        if PrincipalData::parse_standard_principal(address).is_err() {
+            return Err(format!(
+                "invalid contract id '{}': '{}' is not a valid Stacks address",
+                contract_id, address
+            ));
+        }
+        if ContractName::try_from(name.to_string()).is_err() {
+            return Err(format!(
+                "invalid contract id '{}': '{}' is not a valid contract name",
+                contract_id, name
+            ));
+        }
+        Ok(ContractId {
+            address: address.to_string(),
+            name: name.to_string(),
+        })
+    }
+}
+
+impl FromStr for ContractId {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ContractId::parse(s)
+    }
+}
+
+impl fmt::Display for ContractId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}", self.address, self.name)
+    }
+}
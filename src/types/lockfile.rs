@@ -0,0 +1,91 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use crate::utils::hash::source_hash;
+
+const LOCKFILE_HEADER: &str = "# This file is maintained by `clarinet deploy`, do not edit it manually.\n";
+
+/// Contracts deployed by `clarinet deploy`, per environment, as recorded in
+/// `Clarinet.lock`.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Lockfile {
+    #[serde(flatten)]
+    pub environments: BTreeMap<String, LockedEnvironment>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct LockedEnvironment {
+    pub contracts: BTreeMap<String, LockedContract>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LockedContract {
+    pub txid: String,
+    /// SHA-256 of the deployed source
+    pub source_hash: String,
+    /// Height of the block including the transaction, once known
+    pub confirmation_height: Option<u64>,
+}
+
+impl Lockfile {
+    /// Load `Clarinet.lock`, or an empty lockfile if the file doesn't exist yet.
+    pub fn from_path(path: &Path) -> Result<Lockfile, String> {
+        if !path.exists() {
+            return Ok(Lockfile::default());
+        }
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Error: unable to read {}: {}", path.display(), e))?;
+        toml::from_str(&content).map_err(|e| format!("Error: unable to parse {}: {}", path.display(), e))
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        let content = toml::to_string(self)
+            .map_err(|e| format!("Error: unable to serialize {}: {}", path.display(), e))?;
+        fs::write(path, format!("{}{}", LOCKFILE_HEADER, content))
+            .map_err(|e| format!("Error: unable to write {}: {}", path.display(), e))
+    }
+
+    pub fn record(&mut self, environment: &str, contract_name: &str, txid: &str, source_hash: &str) {
+        self.environments.entry(environment.to_string()).or_default().contracts.insert(
+            contract_name.to_string(),
+            LockedContract {
+                txid: txid.to_string(),
+                source_hash: source_hash.to_string(),
+                confirmation_height: None,
+            },
+        );
+    }
+
+    /// Environments in which `contract_name` was deployed with a source
+    /// different from `code`.
+    pub fn drifted_environments(&self, contract_name: &str, code: &str) -> Vec<String> {
+        let hash = source_hash(code.as_bytes());
+        self.environments
+            .iter()
+            .filter(|(_, environment)| {
+                environment
+                    .contracts
+                    .get(contract_name)
+                    .map_or(false, |contract| contract.source_hash != hash)
+            })
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redeployed_contract_is_not_drifting() {
+        let mut lockfile = Lockfile::default();
+        lockfile.record("testnet", "token", "0x01", &source_hash(b"(define-data-var a int 1)"));
+        lockfile.record("mocknet", "token", "0x02", &source_hash(b"(define-data-var a int 2)"));
+
+        let serialized = toml::to_string(&lockfile).unwrap();
+        let lockfile: Lockfile = toml::from_str(&serialized).unwrap();
+        assert_eq!(lockfile.drifted_environments("token", "(define-data-var a int 1)"), vec!["mocknet"]);
+        assert!(lockfile.drifted_environments("market", "").is_empty());
+    }
+}
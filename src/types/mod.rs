@@ -1,5 +1,15 @@
 mod project_config;
+mod balance;
 mod chain_config;
+mod contract_id;
+mod contract_call;
+mod lockfile;
+mod network;
 
-pub use project_config::{MainConfig, MainConfigFile, ContractConfig, LinkConfig};
-pub use chain_config::{ChainConfig, ChainConfigFile};
+pub use project_config::{MainConfig, MainConfigFile, ContractConfig, LinkConfig, ProfileConfig};
+pub use balance::Balance;
+pub use chain_config::{ChainConfig, ChainConfigFile, NetworkConfig, NODE_URL_VAR};
+pub use contract_id::ContractId;
+pub use contract_call::{CallArgument, ContractCall};
+pub use network::Network;
+pub use lockfile::{LockedContract, LockedEnvironment, Lockfile};
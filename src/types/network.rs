@@ -0,0 +1,95 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Environment targeted by a command (`--network`), selecting the settings
+/// file loaded, and the chain the transactions are built for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Network {
+    Development,
+    Mocknet,
+    Testnet,
+    Mainnet,
+}
+
+impl Network {
+    /// Name of the environment, as used by `environments` in `Clarinet.toml`
+    /// and in `Clarinet.lock`.
+    pub fn env_name(&self) -> &'static str {
+        match self {
+            Network::Development => "development",
+            Network::Mocknet => "mocknet",
+            Network::Testnet => "testnet",
+            Network::Mainnet => "mainnet",
+        }
+    }
+
+    /// Settings file of the environment, in the `settings` directory.
+    pub fn settings_file(&self) -> &'static str {
+        match self {
+            Network::Development => "Development.toml",
+            Network::Mocknet => "Mocknet.toml",
+            Network::Testnet => "Testnet.toml",
+            Network::Mainnet => "Mainnet.toml",
+        }
+    }
+
+    pub fn is_mainnet(&self) -> bool {
+        *self == Network::Mainnet
+    }
+
+    pub fn chain_id(&self) -> u32 {
+        if self.is_mainnet() {
+            0x00000001
+        } else {
+            0x80000000
+        }
+    }
+
+    /// RPC endpoint of the node used when the settings of the environment
+    /// don't set a `node_url`.
+    pub fn default_node_url(&self) -> &'static str {
+        match self {
+            Network::Development | Network::Mocknet => "http://localhost:20443",
+            Network::Testnet => "https://stacks-node-api.testnet.stacks.co",
+            Network::Mainnet => "https://stacks-node-api.mainnet.stacks.co",
+        }
+    }
+
+    /// Version byte of the single-signature addresses of the network.
+    pub fn address_version(&self) -> u8 {
+        if self.is_mainnet() {
+            22
+        } else {
+            26
+        }
+    }
+}
+
+impl Default for Network {
+    fn default() -> Self {
+        Network::Development
+    }
+}
+
+impl FromStr for Network {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "dev" | "development" => Ok(Network::Development),
+            "mocknet" => Ok(Network::Mocknet),
+            "testnet" => Ok(Network::Testnet),
+            "mainnet" => Ok(Network::Mainnet),
+            _ => Err(format!(
+                "unknown network '{}': expected mocknet, testnet, mainnet or dev",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.env_name())
+    }
+}
@@ -1,23 +1,32 @@
 use std::fs::File;
-use std::collections::{HashSet, BTreeMap};
-use std::path::PathBuf;
+use std::collections::{HashSet, BTreeMap, VecDeque};
+use std::path::{Path, PathBuf};
 use std::iter::FromIterator;
 use std::{
     io::{BufReader, Read},
 };
-use std::process;
 use toml::value::Value;
+use super::{ContractCall, ContractId};
+use clarity_repl::clarity::representations::ContractName;
+use std::convert::TryFrom;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct MainConfigFile {
     project: ProjectConfigFile,
     links: Option<Value>,
     contracts: Option<Value>,
+    profiles: Option<BTreeMap<String, ProfileConfig>>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ProjectConfigFile {
     name: String,
+    deploy_order: Option<Vec<String>>,
+    deploy_stages: Option<Vec<Vec<String>>>,
+    test_dir: Option<String>,
+    contracts_glob: Option<String>,
+    contracts_dir: Option<String>,
+    contracts_dirs: Option<Vec<String>>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -27,22 +36,187 @@ pub struct MainConfig {
     pub links: Option<Vec<LinkConfig>>,
     // #[serde(serialize_with = "toml::ser::tables_last")]
     pub contracts: Option<BTreeMap<String, ContractConfig>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profiles: Option<BTreeMap<String, ProfileConfig>>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ProjectConfig {
     pub name: String,
+    /// Contracts to deploy first, in this order, overriding the order
+    /// computed from `depends_on`
+    pub deploy_order: Option<Vec<String>>,
+    /// Groups of contracts deployed one after the other, `clarinet deploy`
+    /// waiting for the confirmation of a group before deploying the next one
+    pub deploy_stages: Option<Vec<Vec<String>>>,
+    /// Directory searched for test files by `clarinet test`
+    pub test_dir: Option<String>,
+    /// Pattern (`contracts/**/*.clar`) of the files registered as contracts
+    /// without a `[contracts]` entry
+    pub contracts_glob: Option<String>,
+    /// Directory of the contracts declared without a `path`
+    pub contracts_dir: Option<String>,
+    /// Directories searched, in this order, for the contracts declared
+    /// without a `path` and for the files matching `contracts_glob`
+    pub contracts_dirs: Option<Vec<String>>,
+}
+
+impl ProjectConfig {
+    pub fn test_dir(&self) -> &str {
+        self.test_dir.as_deref().unwrap_or("tests")
+    }
+
+    pub fn contracts_dir(&self) -> &str {
+        self.contracts_dir.as_deref().unwrap_or("contracts")
+    }
+
+    /// Directories searched for contracts, by order of precedence.
+    pub fn contracts_dirs(&self) -> Vec<&str> {
+        match self.contracts_dirs {
+            Some(ref dirs) => dirs.iter().map(|dir| dir.as_str()).collect(),
+            None => vec![self.contracts_dir()],
+        }
+    }
+
+    /// Path of a contract declared without a `path`: `<contracts_dir>/<name>.clar`,
+    /// using the first of the `contracts_dirs` when several are listed.
+    pub fn default_contract_path(&self, name: &str) -> String {
+        self.contract_path_in(self.contracts_dirs()[0], name)
+    }
+
+    fn contract_path_in(&self, dir: &str, name: &str) -> String {
+        format!("{}/{}.clar", dir.trim_end_matches('/'), name)
+    }
+}
+
+/// Settings of a `[profiles.<name>]` section, overlaid on the defaults of
+/// every command by `--profile <name>`. Command line flags still take
+/// precedence over them.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ProfileConfig {
+    /// RPC endpoint of the node transactions are sent to
+    pub node_url: Option<String>,
+    /// How transaction fees are computed (fixed, estimate or manual)
+    pub fee_strategy: Option<String>,
+    /// Fee, in µSTX, of each transaction, with the manual fee strategy
+    pub fee: Option<u64>,
+    /// Account deploying every contract, instead of `deployer`
+    pub deployer: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct LinkConfig {
     pub contract_id: String,
+    /// When set, the contract and its dependencies are fetched as they were
+    /// at this block height instead of at the chain tip
+    pub block_height: Option<u64>,
+    /// Local alias of the contract, checked for collisions with the contracts
+    /// of the project instead of its on-chain name
+    pub name: Option<String>,
+    /// Reuse the sources cached in `.clarinet/links/tip/` instead of fetching
+    /// the contract and its dependencies on every load
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub cache: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ContractConfig {
     pub path: String,
+    /// Set when `path` was derived from the contract name, and is therefore
+    /// not written back to `Clarinet.toml`
+    #[serde(skip)]
+    pub derived_path: bool,
     pub depends_on: Vec<String>,
+    /// When set, the contract is only loaded in these environments
+    pub environments: Option<Vec<String>>,
+    /// Environments in which the contract is never loaded
+    pub excluded_environments: Option<Vec<String>>,
+    /// Labels used to select a subset of contracts (`clarinet deploy --tag`)
+    pub tags: Option<Vec<String>>,
+    /// Free form description, displayed by `clarinet contract list`
+    pub description: Option<String>,
+    /// Contract calls sent by `clarinet deploy` once the contract is deployed
+    pub post_deploy: Option<Vec<String>>,
+    /// µSTX sent by the deployer to the contract once it is deployed
+    pub initial_funding: Option<u64>,
+    /// Keys of the contract's table unknown to this version, written back
+    /// as is to `Clarinet.toml`
+    #[serde(skip)]
+    pub extra_keys: BTreeMap<String, Value>,
+}
+
+/// Keys of a `[contracts.<name>]` table.
+const CONTRACT_KEYS: &[&str] = &[
+    "path",
+    "depends_on",
+    "environments",
+    "excluded_environments",
+    "tags",
+    "description",
+    "post_deploy",
+    "initial_funding",
+];
+
+impl ContractConfig {
+    pub fn is_enabled_for(&self, env: &str) -> bool {
+        let listed = |envs: &Vec<String>| envs.iter().any(|e| e.eq_ignore_ascii_case(env));
+        if let Some(ref excluded) = self.excluded_environments {
+            if listed(excluded) {
+                return false;
+            }
+        }
+        match self.environments {
+            Some(ref environments) => listed(environments),
+            None => true,
+        }
+    }
+
+    /// True if one of the contract's tags matches one of the `patterns`,
+    /// where `*` stands for any sequence of characters (`v*`, `*-token`).
+    pub fn has_tag_matching(&self, patterns: &[String]) -> bool {
+        match self.tags {
+            Some(ref tags) => tags
+                .iter()
+                .any(|tag| patterns.iter().any(|pattern| wildcard_match(pattern, tag))),
+            None => false,
+        }
+    }
+}
+
+fn wildcard_match(pattern: &str, value: &str) -> bool {
+    match pattern.find('*') {
+        None => pattern == value,
+        Some(index) => {
+            let (prefix, rest) = (&pattern[..index], &pattern[index + 1..]);
+            if !value.starts_with(prefix) {
+                return false;
+            }
+            let value = &value[prefix.len()..];
+            (0..=value.len())
+                .filter(|i| value.is_char_boundary(*i))
+                .any(|i| wildcard_match(rest, &value[i..]))
+        }
+    }
+}
+
+/// Match a `/` separated `path` against `pattern`, where `**` stands for any
+/// number of directories, and `*` for any sequence of characters within a
+/// file or directory name.
+fn glob_match(pattern: &str, path: &str) -> bool {
+    fn match_segments(pattern: &[&str], path: &[&str]) -> bool {
+        match pattern.split_first() {
+            None => path.is_empty(),
+            Some((&"**", rest)) => (0..=path.len()).any(|i| match_segments(rest, &path[i..])),
+            Some((segment, rest)) => match path.split_first() {
+                Some((name, path)) => wildcard_match(segment, name) && match_segments(rest, path),
+                None => false,
+            },
+        }
+    }
+    let pattern: Vec<&str> = pattern.split('/').collect();
+    let path: Vec<&str> = path.split('/').collect();
+    match_segments(&pattern, &path)
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -52,44 +226,244 @@ pub struct NotebookConfig {
 }
 
 impl MainConfig {
-    pub fn from_path(path: &PathBuf) -> MainConfig {
-        let path = File::open(path).unwrap();
-        let mut config_file_reader = BufReader::new(path);
+    pub fn from_path(path: &Path) -> Result<MainConfig, String> {
+        let path_str = path.display().to_string();
+        let file = File::open(path).map_err(|e| format!("Error: unable to open {}: {}", path_str, e))?;
+        let mut config_file_reader = BufReader::new(file);
         let mut config_file_buffer = vec![];
         config_file_reader
             .read_to_end(&mut config_file_buffer)
-            .unwrap();
-        let config_file: MainConfigFile = toml::from_slice(&config_file_buffer[..]).unwrap();
-        MainConfig::from_config_file(config_file)
+            .map_err(|e| format!("Error: unable to read {}: {}", path_str, e))?;
+        let config_file: MainConfigFile = toml::from_slice(&config_file_buffer[..])
+            .map_err(|e| format!("Error: unable to parse {}: {}", path_str, e))?;
+        let root = path.parent().unwrap_or(Path::new("."));
+        let mut config = MainConfig::from_config_file(config_file)?;
+        config.resolve_contract_paths(root)?;
+        config.discover_contracts(root)?;
+        Ok(config)
     }
 
-    pub fn ordered_contracts(&self) -> Vec<(String, ContractConfig)> {
-        let mut dst = vec![];
-        let mut lookup = BTreeMap::new();
-        let mut reverse_lookup = BTreeMap::new();
+    /// Check that each of the `contracts_dirs` exists under `root`, and point
+    /// every contract declared without a `path` to the first of them holding
+    /// a `<name>.clar` file.
+    pub fn resolve_contract_paths(&mut self, root: &Path) -> Result<(), String> {
+        let dirs = match self.project.contracts_dirs {
+            Some(ref dirs) => dirs.clone(),
+            None => return Ok(()),
+        };
+        for dir in dirs.iter() {
+            if !root.join(dir).is_dir() {
+                return Err(format!("Error: contracts directory {} not found", dir));
+            }
+        }
+        let project = &self.project;
+        if let Some(ref mut contracts) = self.contracts {
+            for (name, contract) in contracts.iter_mut() {
+                if !contract.derived_path {
+                    continue;
+                }
+                let found = dirs
+                    .iter()
+                    .map(|dir| project.contract_path_in(dir, name))
+                    .find(|path| root.join(path).is_file());
+                if let Some(path) = found {
+                    contract.path = path;
+                }
+            }
+        }
+        Ok(())
+    }
 
-        let mut index: usize = 0;
-        let contracts = match self.contracts {
-            Some(ref contracts) => contracts.clone(),
-            None => return vec![]
+    /// Register the files matching `contracts_glob` as contracts named after
+    /// their file, without dependencies. The pattern is relative to `root`,
+    /// or to each of the `contracts_dirs` when they are listed, in which case
+    /// a file found in a directory shadows the files of the same name found
+    /// in the following ones. Contracts listed in `[contracts]` take
+    /// precedence over the discovered ones.
+    pub fn discover_contracts(&mut self, root: &Path) -> Result<(), String> {
+        let pattern = match self.project.contracts_glob {
+            Some(ref pattern) => pattern.trim_start_matches("./").to_string(),
+            None => return Ok(()),
+        };
+        let search_roots: Vec<PathBuf> = match self.project.contracts_dirs {
+            Some(ref dirs) => dirs.iter().map(|dir| root.join(dir)).collect(),
+            None => vec![root.to_path_buf()],
         };
+        // Only the directory preceding the first wildcard is walked.
+        let base: Vec<&str> = pattern.split('/').take_while(|segment| !segment.contains('*')).collect();
 
-        for (contract, _) in contracts.iter() {
-            lookup.insert(contract, index);
-            reverse_lookup.insert(index, contract.clone());
-            index += 1;
+        let mut discovered: BTreeMap<String, String> = BTreeMap::new();
+        for search_root in search_roots.iter() {
+            let mut found: BTreeMap<String, String> = BTreeMap::new();
+            let walk_root = search_root.join(base.join("/"));
+            for entry in walkdir::WalkDir::new(&walk_root).into_iter().filter_map(|entry| entry.ok()) {
+                if !entry.file_type().is_file() {
+                    continue;
+                }
+                let relative_path = |base: &Path| {
+                    entry
+                        .path()
+                        .strip_prefix(base)
+                        .ok()
+                        .map(|path| path.to_string_lossy().replace('\\', "/"))
+                };
+                let (matched_path, path) = match (relative_path(search_root), relative_path(root)) {
+                    (Some(matched_path), Some(path)) => (matched_path, path),
+                    _ => continue,
+                };
+                if !glob_match(&pattern, &matched_path) {
+                    continue;
+                }
+                let name = match entry.path().file_stem() {
+                    Some(stem) => stem.to_string_lossy().to_string(),
+                    None => continue,
+                };
+                if let Some(other) = found.insert(name.clone(), path.clone()) {
+                    return Err(format!(
+                        "Error: contracts_glob matches {} and {}, which would both be named {}",
+                        other, path, name
+                    ));
+                }
+            }
+            for (name, path) in found.into_iter() {
+                discovered.entry(name).or_insert(path);
+            }
         }
 
-        let mut graph = Graph::new();
-        for (contract, contract_config) in contracts.iter() {
-            let contract_id = lookup.get(contract).unwrap();
-            graph.add_node(*contract_id);
-            for deps in contract_config.depends_on.iter() {
-                let dep_id = lookup.get(deps).unwrap();
-                graph.add_directed_edge(*contract_id, *dep_id);
+        let contracts = self.contracts.get_or_insert_with(BTreeMap::new);
+        for (name, path) in discovered.into_iter() {
+            if contracts.contains_key(&name) {
+                continue;
+            }
+            contracts.insert(
+                name,
+                ContractConfig {
+                    path,
+                    derived_path: false,
+                    depends_on: vec![],
+                    environments: None,
+                    excluded_environments: None,
+                    tags: None,
+                    description: None,
+                    post_deploy: None,
+                    initial_funding: None,
+                    extra_keys: BTreeMap::new(),
+                },
+            );
+        }
+        Ok(())
+    }
+
+    /// Serialize the configuration back to the `Clarinet.toml` format,
+    /// leaving out the paths derived from the contract names, and restoring
+    /// the unknown keys of the contracts.
+    pub fn to_toml(&self) -> Result<String, String> {
+        let mut value = Value::try_from(self).map_err(|e| e.to_string())?;
+        if let (Some(contracts), Some(Value::Table(tables))) = (self.contracts.as_ref(), value.get_mut("contracts")) {
+            for (name, contract) in contracts.iter() {
+                if let Some(Value::Table(table)) = tables.get_mut(name) {
+                    if contract.derived_path {
+                        table.remove("path");
+                    }
+                    for (key, extra) in contract.extra_keys.iter() {
+                        table.insert(key.clone(), extra.clone());
+                    }
+                }
+            }
+        }
+        toml::to_string(&value).map_err(|e| e.to_string())
+    }
+
+    /// Whether `Clarinet.toml` defines at least one contract.
+    pub fn has_contracts(&self) -> bool {
+        self.contracts.as_ref().map_or(false, |contracts| !contracts.is_empty())
+    }
+
+    /// Contracts in deployment order: the contracts listed in `deploy_order`
+    /// come first, followed by the others, sorted by dependencies. With
+    /// `deploy_stages`, the contracts are then grouped by stage, the contracts
+    /// of no stage being deployed last.
+    pub fn ordered_contracts(&self) -> Result<Vec<(String, ContractConfig)>, String> {
+        let mut contracts = self.contracts_in_deploy_order()?;
+        let stages = self.deploy_stages();
+        if stages.is_empty() {
+            return Ok(contracts);
+        }
+        let stage_of = |name: &str| stages.get(name).cloned().unwrap_or(usize::MAX);
+        for name in stages.keys() {
+            if !contracts.iter().any(|(contract, _)| contract == name) {
+                return Err(format!("Error: unknown contract {} in deploy_stages", name));
+            }
+        }
+        for (name, config) in contracts.iter() {
+            for dep in config.depends_on.iter() {
+                if stage_of(dep) > stage_of(name) {
+                    return Err(format!(
+                        "Error: deploy_stages deploys {} before {}, which it depends on",
+                        name, dep
+                    ));
+                }
+            }
+        }
+        contracts.sort_by_key(|(name, _)| stage_of(name));
+        Ok(contracts)
+    }
+
+    /// Stage of each contract listed in `deploy_stages`, by contract name.
+    pub fn deploy_stages(&self) -> BTreeMap<String, usize> {
+        let mut stages = BTreeMap::new();
+        for (index, stage) in self.project.deploy_stages.iter().flatten().enumerate() {
+            for name in stage.iter() {
+                stages.entry(name.clone()).or_insert(index);
+            }
+        }
+        stages
+    }
+
+    fn contracts_in_deploy_order(&self) -> Result<Vec<(String, ContractConfig)>, String> {
+        let sorted = self.sorted_contracts()?;
+        let deploy_order = match self.project.deploy_order {
+            Some(ref deploy_order) => deploy_order,
+            None => return Ok(sorted),
+        };
+
+        let mut dst = vec![];
+        for name in deploy_order.iter() {
+            if dst.iter().any(|(contract, _): &(String, ContractConfig)| contract == name) {
+                return Err(format!("Error: contract {} is listed twice in deploy_order", name));
+            }
+            match sorted.iter().find(|(contract, _)| contract == name) {
+                Some(entry) => dst.push(entry.clone()),
+                None => return Err(format!("Error: unknown contract {} in deploy_order", name)),
+            }
+        }
+        for entry in sorted.into_iter() {
+            if !deploy_order.contains(&entry.0) {
+                dst.push(entry);
+            }
+        }
+
+        for (index, (name, config)) in dst.iter().enumerate() {
+            for dep in config.depends_on.iter() {
+                if !dst[..index].iter().any(|(contract, _)| contract == dep) {
+                    return Err(format!(
+                        "Error: deploy_order deploys {} before {}, which it depends on",
+                        name, dep
+                    ));
+                }
             }
         }
+        Ok(dst)
+    }
+
+    fn sorted_contracts(&self) -> Result<Vec<(String, ContractConfig)>, String> {
+        let mut dst = vec![];
+        let contracts = match self.contracts {
+            Some(ref contracts) => contracts.clone(),
+            None => return Ok(vec![])
+        };
 
+        let (graph, reverse_lookup) = dependency_graph(&contracts, false)?;
         let mut walker = GraphWalker::new();
         let sorted_indexes = walker.get_sorted_dependencies(&graph);
 
@@ -103,8 +477,7 @@ impl MainConfig {
                 };
                 contracts.push(contract);
             }
-            println!("Error: cycling dependencies: {}", contracts.join(", "));
-            process::exit(0);
+            return Err(format!("Error: cycling dependencies: {}", contracts.join(", ")));
         }
 
         for index in sorted_indexes.iter() {
@@ -115,19 +488,229 @@ impl MainConfig {
             let config = contracts.get(&contract).unwrap();
             dst.push((contract, config.clone()))
         }
-        dst
+        Ok(dst)
+    }
+
+    /// Check that the `path` of every contract, relative to `root`, is an
+    /// existing `.clar` file, reporting all the offending contracts at once.
+    pub fn validate_paths(&self, root: &Path) -> Result<(), String> {
+        let mut problems = vec![];
+        if let Some(ref contracts) = self.contracts {
+            for (name, contract) in contracts.iter() {
+                let path = root.join(&contract.path);
+                if path.extension().map_or(true, |ext| ext != "clar") {
+                    problems.push(format!("Error: contract {}: {} is not a .clar file", name, contract.path));
+                } else if !path.is_file() {
+                    problems.push(format!("Error: contract {}: {} not found", name, contract.path));
+                }
+            }
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems.join("\n"))
+        }
+    }
+
+    /// Contracts `name` transitively depends on or, when `reverse` is set,
+    /// contracts transitively depending on `name`, in deployment order.
+    pub fn dependencies_of(&self, name: &str, reverse: bool) -> Result<Vec<String>, String> {
+        let contracts = match self.contracts {
+            Some(ref contracts) => contracts,
+            None => return Err(format!("Error: unknown contract {}", name)),
+        };
+        let (graph, reverse_lookup) = dependency_graph(contracts, reverse)?;
+        let index = match reverse_lookup.iter().find(|(_, contract)| contract.as_str() == name) {
+            Some((index, _)) => *index,
+            None => return Err(format!("Error: unknown contract {}", name)),
+        };
+
+        let mut walker = GraphWalker::new();
+        let mut closure = vec![];
+        walker.sort_dependencies_recursion(index, &graph, &mut closure);
+        let closure: HashSet<&String> = closure
+            .iter()
+            .filter(|i| **i != index)
+            .map(|i| reverse_lookup.get(i).unwrap())
+            .collect();
+
+        Ok(self
+            .ordered_contracts()?
+            .into_iter()
+            .map(|(contract, _)| contract)
+            .filter(|contract| closure.contains(contract))
+            .collect())
+    }
+
+    /// Contracts `names`, along with the contracts they transitively depend
+    /// on, in deployment order. Unknown names are reported.
+    pub fn contracts_with_dependencies(&self, names: &[String]) -> Result<Vec<String>, String> {
+        let mut selected = HashSet::new();
+        for name in names.iter() {
+            selected.extend(self.dependencies_of(name, false)?);
+            selected.insert(name.clone());
+        }
+        Ok(self
+            .ordered_contracts()?
+            .into_iter()
+            .map(|(contract, _)| contract)
+            .filter(|contract| selected.contains(contract))
+            .collect())
+    }
+
+    /// Shortest chain of `depends_on` leading from contract `from` to contract
+    /// `to`, both included, or `None` when `from` doesn't depend on `to`.
+    pub fn dependency_path(&self, from: &str, to: &str) -> Result<Option<Vec<String>>, String> {
+        let contracts = match self.contracts {
+            Some(ref contracts) => contracts,
+            None => return Err(format!("Error: unknown contract {}", from)),
+        };
+        let (graph, reverse_lookup) = dependency_graph(contracts, false)?;
+        let index_of = |name: &str| {
+            reverse_lookup
+                .iter()
+                .find(|(_, contract)| contract.as_str() == name)
+                .map(|(index, _)| *index)
+                .ok_or_else(|| format!("Error: unknown contract {}", name))
+        };
+        let (start, target) = (index_of(from)?, index_of(to)?);
+
+        // Breadth first, remembering the contract each one was reached from.
+        let mut parents = BTreeMap::new();
+        let mut queue = VecDeque::new();
+        queue.push_back(start);
+        while let Some(index) = queue.pop_front() {
+            if index == target && index != start {
+                let mut path = vec![reverse_lookup[&index].clone()];
+                let mut current = index;
+                while let Some(parent) = parents.get(&current) {
+                    path.push(reverse_lookup[parent].clone());
+                    current = *parent;
+                }
+                path.reverse();
+                return Ok(Some(path));
+            }
+            for dependency in graph.get_node_descendants(index).iter() {
+                if *dependency != start && !parents.contains_key(dependency) {
+                    parents.insert(*dependency, index);
+                    queue.push_back(*dependency);
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Contracts are deployed under their name, so two local contracts, or a
+    /// local contract and a link, sharing a name (ignoring case) would conflict.
+    pub fn check_name_collisions(&self) -> Result<(), String> {
+        let mut names: BTreeMap<String, String> = BTreeMap::new();
+        if let Some(ref contracts) = self.contracts {
+            for name in contracts.keys() {
+                if let Some(other) = names.insert(name.to_lowercase(), format!("contract {}", name)) {
+                    return Err(format!(
+                        "Error: {} and contract {} resolve to the same contract name",
+                        other, name
+                    ));
+                }
+            }
+        }
+        if let Some(ref links) = self.links {
+            for link in links.iter() {
+                let contract_id = ContractId::parse(&link.contract_id)?;
+                let name = link.name.clone().unwrap_or(contract_id.name);
+                if let Some(other) = names.get(&name.to_lowercase()) {
+                    return Err(format!(
+                        "Error: {} and link {} resolve to the same contract name",
+                        other, link.contract_id
+                    ));
+                }
+            }
+        }
+        Ok(())
     }
 
-    pub fn from_config_file(config_file: MainConfigFile) -> MainConfig {
+    /// Settings of the profile `name`.
+    pub fn profile(&self, name: &str) -> Result<ProfileConfig, String> {
+        let profiles = self.profiles.clone().unwrap_or_default();
+        match profiles.get(name) {
+            Some(profile) => Ok(profile.clone()),
+            None if profiles.is_empty() => Err(format!(
+                "Error: unknown profile {} (no profile is defined in Clarinet.toml)",
+                name
+            )),
+            None => Err(format!(
+                "Error: unknown profile {} (available profiles: {})",
+                name,
+                profiles.keys().cloned().collect::<Vec<_>>().join(", ")
+            )),
+        }
+    }
+
+    /// Contracts to load in the environment `env`, in deployment order.
+    pub fn contracts_for_env(&self, env: &str) -> Result<Vec<(String, ContractConfig)>, String> {
+        let contracts: Vec<(String, ContractConfig)> = self.ordered_contracts()?
+            .into_iter()
+            .filter(|(_, config)| config.is_enabled_for(env))
+            .collect();
+        for (name, config) in contracts.iter() {
+            for dep in config.depends_on.iter() {
+                if !contracts.iter().any(|(contract, _)| contract == dep) {
+                    return Err(format!(
+                        "Error: contract {} depends on {}, which is not enabled in the {} environment",
+                        name, dep, env
+                    ));
+                }
+            }
+        }
+        Ok(contracts)
+    }
+
+    pub fn from_config_file(config_file: MainConfigFile) -> Result<MainConfig, String> {
 
         let project = ProjectConfig {
             name: config_file.project.name.clone(),
+            deploy_order: config_file.project.deploy_order.clone(),
+            deploy_stages: config_file.project.deploy_stages.clone(),
+            test_dir: config_file.project.test_dir.clone(),
+            contracts_glob: config_file.project.contracts_glob.clone(),
+            contracts_dir: config_file.project.contracts_dir.clone(),
+            contracts_dirs: config_file.project.contracts_dirs.clone(),
         };
+        let mut staged = HashSet::new();
+        for name in project.deploy_stages.iter().flatten().flatten() {
+            if !staged.insert(name) {
+                return Err(format!("Error: contract {} is listed in several deploy_stages in Clarinet.toml", name));
+            }
+        }
+        match project.contracts_dirs {
+            Some(_) if project.contracts_dir.is_some() => {
+                return Err("Error: contracts_dir and contracts_dirs can't both be set in Clarinet.toml".to_string())
+            }
+            Some(ref dirs) if dirs.is_empty() => {
+                return Err("Error: contracts_dirs can't be empty in Clarinet.toml".to_string())
+            }
+            _ => {}
+        }
+
+        if let Some(ref profiles) = config_file.profiles {
+            for (name, profile) in profiles.iter() {
+                match profile.fee_strategy.as_deref() {
+                    None | Some("fixed") | Some("estimate") | Some("manual") => {}
+                    Some(strategy) => {
+                        return Err(format!(
+                            "Error: invalid fee_strategy {} for profile {} in Clarinet.toml",
+                            strategy, name
+                        ))
+                    }
+                }
+            }
+        }
 
         let mut config = MainConfig {
             project,
             links: None,
             contracts: None,
+            profiles: config_file.profiles.clone(),
         };
         let mut config_contracts = BTreeMap::new();
         let mut config_links: Vec<LinkConfig> = Vec::new();
@@ -138,12 +721,49 @@ impl MainConfig {
                     match link_settings {
                         Value::Table(link_settings) => {
                             let contract_id = match link_settings.get("contract_id") {
-                                Some(Value::String(contract_id)) => contract_id.to_string(),
+                                Some(Value::String(contract_id)) => ContractId::parse(contract_id)
+                                    .map_err(|e| format!("Error: invalid link in Clarinet.toml: {}", e))?
+                                    .to_string(),
                                 _ => continue,
                             };
+                            let block_height = match link_settings.get("block_height") {
+                                Some(Value::Integer(height)) if *height >= 0 => Some(*height as u64),
+                                Some(_) => {
+                                    return Err(format!(
+                                        "Error: invalid block_height for link {} in Clarinet.toml",
+                                        contract_id
+                                    ))
+                                }
+                                None => None,
+                            };
+                            let name = match link_settings.get("name") {
+                                Some(Value::String(name)) if ContractName::try_from(name.to_string()).is_ok() => {
+                                    Some(name.to_string())
+                                }
+                                Some(_) => {
+                                    return Err(format!(
+                                        "Error: invalid name for link {} in Clarinet.toml",
+                                        contract_id
+                                    ))
+                                }
+                                None => None,
+                            };
+                            let cache = match link_settings.get("cache") {
+                                Some(Value::Boolean(cache)) => *cache,
+                                Some(_) => {
+                                    return Err(format!(
+                                        "Error: invalid cache for link {} in Clarinet.toml",
+                                        contract_id
+                                    ))
+                                }
+                                None => false,
+                            };
                             config_links.push(
                                 LinkConfig {
-                                    contract_id
+                                    contract_id,
+                                    block_height,
+                                    name,
+                                    cache,
                                 }
                             );
                         }
@@ -159,21 +779,112 @@ impl MainConfig {
                 for (contract_name, contract_settings) in contracts.iter() {
                     match contract_settings {
                         Value::Table(contract_settings) => {
-                            let path = match contract_settings.get("path") {
-                                Some(Value::String(path)) => path.to_string(),
-                                _ => continue,
+                            let (path, derived_path) = match contract_settings.get("path") {
+                                Some(Value::String(path)) => (path.to_string(), false),
+                                Some(_) => {
+                                    return Err(format!(
+                                        "Error: invalid path for contract {} in Clarinet.toml",
+                                        contract_name
+                                    ))
+                                }
+                                None => (config.project.default_contract_path(contract_name), true),
                             };
                             let depends_on = match contract_settings.get("depends_on") {
                                 Some(Value::Array(depends_on)) => {
-                                    depends_on.iter().map(|v| v.as_str().unwrap().to_string()).collect::<Vec<String>>()
+                                    match depends_on.iter().map(|v| v.as_str().map(|v| v.to_string())).collect::<Option<Vec<String>>>() {
+                                        Some(depends_on) => depends_on,
+                                        None => {
+                                            return Err(format!(
+                                                "Error: invalid depends_on for contract {} in Clarinet.toml",
+                                                contract_name
+                                            ))
+                                        }
+                                    }
                                 },
-                                _ => continue,
+                                Some(_) => {
+                                    return Err(format!(
+                                        "Error: invalid depends_on for contract {} in Clarinet.toml",
+                                        contract_name
+                                    ))
+                                }
+                                None => vec![],
+                            };
+                            let environments = match contract_settings.get("environments") {
+                                Some(Value::Array(environments)) => Some(
+                                    environments.iter().filter_map(|v| v.as_str()).map(|v| v.to_string()).collect::<Vec<String>>()
+                                ),
+                                _ => None,
+                            };
+                            let excluded_environments = match contract_settings.get("excluded_environments") {
+                                Some(Value::Array(environments)) => Some(
+                                    environments.iter().filter_map(|v| v.as_str()).map(|v| v.to_string()).collect::<Vec<String>>()
+                                ),
+                                _ => None,
+                            };
+                            let tags = match contract_settings.get("tags") {
+                                Some(Value::Array(tags)) => Some(
+                                    tags.iter().filter_map(|v| v.as_str()).map(|v| v.to_string()).collect::<Vec<String>>()
+                                ),
+                                _ => None,
+                            };
+                            let description = match contract_settings.get("description") {
+                                Some(Value::String(description)) => Some(description.to_string()),
+                                _ => None,
+                            };
+                            let post_deploy = match contract_settings.get("post_deploy") {
+                                Some(Value::Array(calls)) => {
+                                    let mut post_deploy = vec![];
+                                    for call in calls.iter() {
+                                        let call = call.as_str().ok_or(format!(
+                                            "Error: invalid post_deploy for contract {} in Clarinet.toml",
+                                            contract_name
+                                        ))?;
+                                        ContractCall::parse(call).map_err(|e| format!(
+                                            "Error: invalid post_deploy for contract {} in Clarinet.toml: {}",
+                                            contract_name, e
+                                        ))?;
+                                        post_deploy.push(call.to_string());
+                                    }
+                                    Some(post_deploy)
+                                }
+                                Some(_) => {
+                                    return Err(format!(
+                                        "Error: invalid post_deploy for contract {} in Clarinet.toml",
+                                        contract_name
+                                    ))
+                                }
+                                None => None,
                             };
+                            let initial_funding = match contract_settings.get("initial_funding") {
+                                Some(Value::Integer(amount)) if *amount > 0 => Some(*amount as u64),
+                                Some(_) => {
+                                    return Err(format!(
+                                        "Error: initial_funding of contract {} must be a positive amount of uSTX in Clarinet.toml",
+                                        contract_name
+                                    ))
+                                }
+                                None => None,
+                            };
+                            let mut extra_keys = BTreeMap::new();
+                            for (key, extra) in contract_settings.iter() {
+                                if !CONTRACT_KEYS.contains(&key.as_str()) {
+                                    warn!("unknown key {} for contract {} in Clarinet.toml", key, contract_name);
+                                    extra_keys.insert(key.clone(), extra.clone());
+                                }
+                            }
                             config_contracts.insert(
                                 contract_name.to_string(),
                                 ContractConfig {
                                     path,
+                                    derived_path,
                                     depends_on,
+                                    environments,
+                                    excluded_environments,
+                                    tags,
+                                    description,
+                                    post_deploy,
+                                    initial_funding,
+                                    extra_keys,
                                 }
                             );
                         }
@@ -185,10 +896,40 @@ impl MainConfig {
         };
         config.contracts = Some(config_contracts);
         config.links = Some(config_links);
-        config
+        Ok(config)
     }
 }
 
+/// Graph of the `depends_on` relations (reversed when `reverse` is set), with
+/// the name of the contract behind each node.
+fn dependency_graph(contracts: &BTreeMap<String, ContractConfig>, reverse: bool) -> Result<(Graph, BTreeMap<usize, String>), String> {
+    let mut lookup = BTreeMap::new();
+    let mut reverse_lookup = BTreeMap::new();
+
+    let mut graph = Graph::new();
+    for (index, (contract, _)) in contracts.iter().enumerate() {
+        lookup.insert(contract, index);
+        reverse_lookup.insert(index, contract.clone());
+        graph.add_node(index);
+    }
+
+    for (contract, contract_config) in contracts.iter() {
+        let contract_id = lookup.get(contract).unwrap();
+        for deps in contract_config.depends_on.iter() {
+            let dep_id = match lookup.get(deps) {
+                Some(dep_id) => dep_id,
+                None => return Err(format!("Error: contract {} depends on unknown contract {}", contract, deps)),
+            };
+            if reverse {
+                graph.add_directed_edge(*dep_id, *contract_id);
+            } else {
+                graph.add_directed_edge(*contract_id, *dep_id);
+            }
+        }
+    }
+    Ok((graph, reverse_lookup))
+}
+
 struct Graph {
     pub adjacency_list: Vec<Vec<usize>>,
 }
@@ -291,4 +1032,680 @@ impl GraphWalker {
         let deps = nodes.difference(&tainted).map(|i| *i).collect();
         Some(deps)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_from_str(content: &str) -> MainConfig {
+        let config_file: MainConfigFile = toml::from_str(content).unwrap();
+        MainConfig::from_config_file(config_file).unwrap()
+    }
+
+    #[test]
+    fn mocknet_only_contract_is_not_loaded_on_testnet() {
+        let config = config_from_str(r#"
+[project]
+name = "test"
+
+[contracts.token]
+path = "contracts/token.clar"
+depends_on = []
+
+[contracts.admin]
+path = "contracts/admin.clar"
+depends_on = ["token"]
+environments = ["mocknet"]
+"#);
+
+        let names = |env: &str| -> Vec<String> {
+            config.contracts_for_env(env).unwrap().into_iter().map(|(name, _)| name).collect()
+        };
+        assert_eq!(names("mocknet"), vec!["token".to_string(), "admin".to_string()]);
+        assert_eq!(names("testnet"), vec!["token".to_string()]);
+    }
+
+    #[test]
+    fn excluded_dependency_is_reported() {
+        let config = config_from_str(r#"
+[project]
+name = "test"
+
+[contracts.token]
+path = "contracts/token.clar"
+depends_on = []
+excluded_environments = ["testnet"]
+
+[contracts.market]
+path = "contracts/market.clar"
+depends_on = ["token"]
+"#);
+
+        assert!(config.contracts_for_env("development").is_ok());
+        assert!(config.contracts_for_env("testnet").is_err());
+    }
+
+    #[test]
+    fn post_deploy_calls_are_validated() {
+        let config = config_from_str(r#"
+[project]
+name = "test"
+
+[contracts.token]
+path = "contracts/token.clar"
+depends_on = []
+post_deploy = ["(contract-call? .token set-admin tx-sender)"]
+"#);
+        let token = &config.contracts.as_ref().unwrap()["token"];
+        assert_eq!(token.post_deploy.as_ref().unwrap().len(), 1);
+
+        let config_file: MainConfigFile = toml::from_str(r#"
+[project]
+name = "test"
+
+[contracts.token]
+path = "contracts/token.clar"
+depends_on = []
+post_deploy = ["(contract-call? .token mint (+ u1 u1))"]
+"#).unwrap();
+        assert!(MainConfig::from_config_file(config_file).is_err());
+    }
+
+    #[test]
+    fn project_without_contracts_loads_nothing() {
+        let config = config_from_str(r#"
+[project]
+name = "test"
+"#);
+
+        assert!(!config.has_contracts());
+        assert!(config.ordered_contracts().unwrap().is_empty());
+        assert!(config.contracts_for_env("development").unwrap().is_empty());
+        assert!(config.validate_paths(Path::new(".")).is_ok());
+        assert!(config.check_name_collisions().is_ok());
+    }
+
+    #[test]
+    fn contracts_glob_is_merged_with_explicit_contracts() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let root = tempdir.path();
+        std::fs::create_dir_all(root.join("contracts/tokens")).unwrap();
+        std::fs::write(root.join("contracts/market.clar"), "").unwrap();
+        std::fs::write(root.join("contracts/tokens/token.clar"), "").unwrap();
+        std::fs::write(root.join("contracts/notes.md"), "").unwrap();
+
+        let mut config = config_from_str(r#"
+[project]
+name = "test"
+contracts_glob = "contracts/**/*.clar"
+
+[contracts.market]
+path = "contracts/market.clar"
+depends_on = ["token"]
+"#);
+        config.discover_contracts(root).unwrap();
+
+        let contracts = config.contracts.unwrap();
+        assert_eq!(contracts.keys().collect::<Vec<_>>(), vec!["market", "token"]);
+        assert_eq!(contracts["market"].depends_on, vec!["token".to_string()]);
+        assert_eq!(contracts["token"].path, "contracts/tokens/token.clar");
+        assert!(contracts["token"].depends_on.is_empty());
+    }
+
+    #[test]
+    fn glob_double_star_matches_nested_directories() {
+        assert!(glob_match("contracts/**/*.clar", "contracts/a.clar"));
+        assert!(glob_match("contracts/**/*.clar", "contracts/a/b/c.clar"));
+        assert!(!glob_match("contracts/*.clar", "contracts/a/b.clar"));
+        assert!(!glob_match("contracts/**/*.clar", "tests/a.clar"));
+    }
+
+    #[test]
+    fn depends_on_defaults_to_no_dependency() {
+        let config = config_from_str(r#"
+[project]
+name = "test"
+
+[contracts.token]
+path = "contracts/token.clar"
+
+[contracts.market]
+path = "contracts/market.clar"
+depends_on = ["token"]
+"#);
+
+        let contracts = config.contracts.as_ref().unwrap();
+        assert!(contracts["token"].depends_on.is_empty());
+        let names: Vec<String> = config.ordered_contracts().unwrap().into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names, vec!["token".to_string(), "market".to_string()]);
+
+        let config_file: MainConfigFile = toml::from_str(r#"
+[project]
+name = "test"
+
+[contracts.token]
+path = "contracts/token.clar"
+depends_on = "market"
+"#).unwrap();
+        assert!(MainConfig::from_config_file(config_file).is_err());
+    }
+
+    #[test]
+    fn contract_path_defaults_to_contracts_dir() {
+        let config = config_from_str(r#"
+[project]
+name = "test"
+contracts_dir = "src"
+
+[contracts.token]
+depends_on = []
+
+[contracts.market]
+path = "contracts/market.clar"
+depends_on = ["token"]
+"#);
+
+        let contracts = config.contracts.as_ref().unwrap();
+        assert_eq!(contracts["token"].path, "src/token.clar");
+        assert_eq!(contracts["market"].path, "contracts/market.clar");
+
+        let serialized = config.to_toml().unwrap();
+        assert!(!serialized.contains("src/token.clar"));
+        assert!(serialized.contains("contracts/market.clar"));
+    }
+
+    #[test]
+    fn contracts_are_selected_with_their_dependencies() {
+        let config = config_from_str(r#"
+[project]
+name = "test"
+
+[contracts.token]
+path = "contracts/token.clar"
+depends_on = []
+
+[contracts.oracle]
+path = "contracts/oracle.clar"
+depends_on = []
+
+[contracts.market]
+path = "contracts/market.clar"
+depends_on = ["token"]
+"#);
+
+        assert_eq!(
+            config.contracts_with_dependencies(&["market".to_string()]).unwrap(),
+            vec!["token".to_string(), "market".to_string()]
+        );
+        assert!(config.contracts_with_dependencies(&["vault".to_string()]).is_err());
+    }
+
+    #[test]
+    fn initial_funding_must_be_positive() {
+        let parse = |funding: &str| {
+            let content = format!(
+                "[project]\nname = \"test\"\n\n[contracts.vault]\npath = \"contracts/vault.clar\"\ndepends_on = []\ninitial_funding = {}\n",
+                funding
+            );
+            let config_file: MainConfigFile = toml::from_str(&content).unwrap();
+            MainConfig::from_config_file(config_file).map(|config| config.contracts.unwrap()["vault"].initial_funding)
+        };
+        assert_eq!(parse("5000000"), Ok(Some(5000000)));
+        assert!(parse("0").is_err());
+        assert!(parse("\"5 STX\"").is_err());
+    }
+
+    #[test]
+    fn unknown_contract_keys_are_written_back() {
+        let config = config_from_str(r#"
+[project]
+name = "test"
+
+[contracts.token]
+path = "contracts/token.clar"
+depends_on = []
+audited = true
+"#);
+
+        let token = &config.contracts.as_ref().unwrap()["token"];
+        assert_eq!(token.extra_keys.get("audited"), Some(&Value::Boolean(true)));
+
+        let reloaded = config_from_str(&config.to_toml().unwrap());
+        assert_eq!(reloaded.contracts.unwrap()["token"].extra_keys.get("audited"), Some(&Value::Boolean(true)));
+    }
+
+    #[test]
+    fn contracts_dirs_are_searched_in_order() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let root = tempdir.path();
+        std::fs::create_dir_all(root.join("contracts")).unwrap();
+        std::fs::create_dir_all(root.join("vendor")).unwrap();
+        std::fs::write(root.join("contracts/token.clar"), "").unwrap();
+        std::fs::write(root.join("vendor/token.clar"), "").unwrap();
+        std::fs::write(root.join("vendor/trait.clar"), "").unwrap();
+        std::fs::write(root.join("vendor/oracle.clar"), "").unwrap();
+
+        let mut config = config_from_str(r#"
+[project]
+name = "test"
+contracts_dirs = ["contracts", "vendor"]
+contracts_glob = "*.clar"
+
+[contracts.token]
+depends_on = []
+
+[contracts.trait]
+depends_on = []
+"#);
+        let res = config
+            .resolve_contract_paths(root)
+            .and_then(|_| config.discover_contracts(root));
+        let missing = config_from_str(r#"
+[project]
+name = "test"
+contracts_dirs = ["contracts", "lib"]
+"#)
+        .resolve_contract_paths(root);
+        res.unwrap();
+        assert!(missing.is_err());
+
+        let contracts = config.contracts.unwrap();
+        assert_eq!(contracts["token"].path, "contracts/token.clar");
+        assert_eq!(contracts["trait"].path, "vendor/trait.clar");
+        assert_eq!(contracts["oracle"].path, "vendor/oracle.clar");
+    }
+
+    #[test]
+    fn deploy_order_overrides_sorted_order() {
+        let config = config_from_str(r#"
+[project]
+name = "test"
+deploy_order = ["registry"]
+
+[contracts.token]
+path = "contracts/token.clar"
+depends_on = []
+
+[contracts.registry]
+path = "contracts/registry.clar"
+depends_on = []
+"#);
+
+        let names: Vec<String> = config.ordered_contracts().unwrap().into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names, vec!["registry".to_string(), "token".to_string()]);
+    }
+
+    #[test]
+    fn deploy_order_violating_dependencies_is_rejected() {
+        let config = config_from_str(r#"
+[project]
+name = "test"
+deploy_order = ["market", "token"]
+
+[contracts.token]
+path = "contracts/token.clar"
+depends_on = []
+
+[contracts.market]
+path = "contracts/market.clar"
+depends_on = ["token"]
+"#);
+
+        assert!(config.ordered_contracts().is_err());
+    }
+
+    #[test]
+    fn contracts_are_grouped_by_deploy_stage() {
+        let content = r#"
+[project]
+name = "test"
+deploy_stages = [["token", "oracle"], ["market"]]
+
+[contracts.oracle]
+path = "contracts/oracle.clar"
+depends_on = []
+
+[contracts.market]
+path = "contracts/market.clar"
+depends_on = ["token", "oracle"]
+
+[contracts.token]
+path = "contracts/token.clar"
+depends_on = []
+
+[contracts.archive]
+path = "contracts/archive.clar"
+depends_on = []
+"#;
+        let config = config_from_str(content);
+        let names: Vec<String> = config.ordered_contracts().unwrap().into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names[2..], ["market", "archive"]);
+        assert_eq!(config.deploy_stages()["market"], 1);
+
+        let config = config_from_str(&content.replace(r#"[["token", "oracle"], ["market"]]"#, r#"[["market"], ["token", "oracle"]]"#));
+        assert!(config.ordered_contracts().is_err());
+
+        let config_file: MainConfigFile = toml::from_str(&content.replace(r#"["market"]]"#, r#"["market", "token"]]"#)).unwrap();
+        assert!(MainConfig::from_config_file(config_file).is_err());
+    }
+
+    #[test]
+    fn cyclic_dependencies_are_returned_as_errors() {
+        let config = config_from_str(r#"
+[project]
+name = "test"
+
+[contracts.token]
+path = "contracts/token.clar"
+depends_on = ["market"]
+
+[contracts.market]
+path = "contracts/market.clar"
+depends_on = ["token"]
+
+[contracts.oracle]
+path = "contracts/oracle.clar"
+depends_on = []
+"#);
+
+        let err = config.ordered_contracts().unwrap_err();
+        assert!(err.starts_with("Error: cycling dependencies"));
+        assert!(err.contains("token") && err.contains("market"));
+        assert!(!err.contains("oracle"));
+        assert!(config.contracts_for_env("development").is_err());
+        assert!(config.dependencies_of("oracle", false).is_err());
+    }
+
+    #[test]
+    fn invalid_dependencies_are_returned_as_errors() {
+        let config = config_from_str(r#"
+[project]
+name = "test"
+
+[contracts.market]
+path = "contracts/market.clar"
+depends_on = ["tokn"]
+"#);
+        assert_eq!(
+            config.ordered_contracts().unwrap_err(),
+            "Error: contract market depends on unknown contract tokn"
+        );
+
+        let config_file: MainConfigFile = toml::from_str(r#"
+[project]
+name = "test"
+
+[contracts.market]
+path = "contracts/market.clar"
+depends_on = [1]
+"#).unwrap();
+        assert!(MainConfig::from_config_file(config_file).is_err());
+    }
+
+    #[test]
+    fn profiles_are_looked_up_by_name() {
+        let config = config_from_str(r#"
+[project]
+name = "test"
+
+[profiles.ci]
+node_url = "http://stacks-node:20443"
+fee_strategy = "manual"
+fee = 1000
+
+[profiles.staging]
+deployer = "wallet_1"
+"#);
+
+        let ci = config.profile("ci").unwrap();
+        assert_eq!(ci.node_url.as_deref(), Some("http://stacks-node:20443"));
+        assert_eq!(ci.fee, Some(1000));
+        assert_eq!(ci.deployer, None);
+        let err = config.profile("local").unwrap_err();
+        assert!(err.contains("available profiles: ci, staging"));
+        assert!(config.to_toml().unwrap().contains("[profiles.ci]"));
+
+        let config_file: MainConfigFile = toml::from_str(r#"
+[project]
+name = "test"
+
+[profiles.ci]
+fee_strategy = "cheap"
+"#).unwrap();
+        assert!(MainConfig::from_config_file(config_file).is_err());
+    }
+
+    #[test]
+    fn description_is_optional() {
+        let config = config_from_str(r#"
+[project]
+name = "test"
+
+[contracts.token]
+path = "contracts/token.clar"
+depends_on = []
+description = "Fungible token backing the market"
+
+[contracts.market]
+path = "contracts/market.clar"
+depends_on = ["token"]
+"#);
+
+        let contracts = config.contracts.unwrap();
+        assert_eq!(
+            contracts.get("token").unwrap().description,
+            Some("Fungible token backing the market".to_string())
+        );
+        assert_eq!(contracts.get("market").unwrap().description, None);
+    }
+
+    #[test]
+    fn local_contract_colliding_with_link_is_reported() {
+        let config = config_from_str(r#"
+[project]
+name = "test"
+
+[[links]]
+contract_id = "SP3FBR2AGK5H9QBDH3EEN6DF8EK8JY7RKQQ8PTN5V.token"
+
+[contracts.token]
+path = "contracts/token.clar"
+depends_on = []
+"#);
+
+        let err = config.check_name_collisions().unwrap_err();
+        assert!(err.contains("contract token"));
+        assert!(err.contains("SP3FBR2AGK5H9QBDH3EEN6DF8EK8JY7RKQQ8PTN5V.token"));
+    }
+
+    #[test]
+    fn contracts_differing_by_case_are_reported() {
+        let config = config_from_str(r#"
+[project]
+name = "test"
+
+[contracts.token]
+path = "contracts/token.clar"
+depends_on = []
+
+[contracts.Token]
+path = "contracts/Token.clar"
+depends_on = []
+"#);
+
+        assert!(config.check_name_collisions().is_err());
+    }
+
+    #[test]
+    fn tags_are_matched_with_wildcards() {
+        let config = config_from_str(r#"
+[project]
+name = "test"
+
+[contracts.token]
+path = "contracts/token.clar"
+depends_on = []
+tags = ["token", "v2"]
+
+[contracts.market]
+path = "contracts/market.clar"
+depends_on = []
+"#);
+
+        let contracts = config.contracts.unwrap();
+        let token = contracts.get("token").unwrap();
+        let market = contracts.get("market").unwrap();
+        assert!(token.has_tag_matching(&["token".to_string()]));
+        assert!(token.has_tag_matching(&["v*".to_string()]));
+        assert!(!token.has_tag_matching(&["v1".to_string()]));
+        assert!(!market.has_tag_matching(&["*".to_string()]));
+    }
+
+    #[test]
+    fn links_can_be_pinned_to_a_block_height() {
+        let config = config_from_str(r#"
+[project]
+name = "test"
+
+[[links]]
+contract_id = "SP3FBR2AGK5H9QBDH3EEN6DF8EK8JY7RKQQ8PTN5V.token"
+block_height = 12000
+
+[[links]]
+contract_id = "SP3FBR2AGK5H9QBDH3EEN6DF8EK8JY7RKQQ8PTN5V.market"
+"#);
+
+        let links = config.links.unwrap();
+        assert_eq!(links[0].block_height, Some(12000));
+        assert_eq!(links[1].block_height, None);
+    }
+
+    #[test]
+    fn links_can_be_aliased_and_cached() {
+        let config = config_from_str(r#"
+[project]
+name = "test"
+
+[[links]]
+contract_id = "SP3FBR2AGK5H9QBDH3EEN6DF8EK8JY7RKQQ8PTN5V.token"
+name = "remote-token"
+cache = true
+
+[[links]]
+contract_id = "SP3FBR2AGK5H9QBDH3EEN6DF8EK8JY7RKQQ8PTN5V.market"
+
+[contracts.token]
+path = "contracts/token.clar"
+depends_on = []
+"#);
+
+        let links = config.links.as_ref().unwrap();
+        assert_eq!(links[0].name.as_deref(), Some("remote-token"));
+        assert!(links[0].cache);
+        assert_eq!(links[1].name, None);
+        assert!(!links[1].cache);
+        // The alias, not the on-chain name, is checked for collisions.
+        assert!(config.check_name_collisions().is_ok());
+
+        let config_file: MainConfigFile = toml::from_str(r#"
+[project]
+name = "test"
+
+[[links]]
+contract_id = "SP3FBR2AGK5H9QBDH3EEN6DF8EK8JY7RKQQ8PTN5V.token"
+cache = "yes"
+"#).unwrap();
+        assert!(MainConfig::from_config_file(config_file).is_err());
+    }
+
+    #[test]
+    fn transitive_dependencies_are_listed_in_deployment_order() {
+        let config = config_from_str(r#"
+[project]
+name = "test"
+
+[contracts.token]
+path = "contracts/token.clar"
+depends_on = []
+
+[contracts.oracle]
+path = "contracts/oracle.clar"
+depends_on = []
+
+[contracts.vault]
+path = "contracts/vault.clar"
+depends_on = ["token"]
+
+[contracts.market]
+path = "contracts/market.clar"
+depends_on = ["vault", "oracle"]
+"#);
+
+        assert_eq!(config.dependencies_of("market", false).unwrap(), vec!["token", "vault", "oracle"]);
+        assert_eq!(config.dependencies_of("token", true).unwrap(), vec!["vault", "market"]);
+        assert!(config.dependencies_of("token", false).unwrap().is_empty());
+        assert!(config.dependencies_of("unknown", false).is_err());
+    }
+
+    #[test]
+    fn dependency_path_follows_depends_on() {
+        let config = config_from_str(r#"
+[project]
+name = "test"
+
+[contracts.token]
+path = "contracts/token.clar"
+depends_on = []
+
+[contracts.oracle]
+path = "contracts/oracle.clar"
+depends_on = []
+
+[contracts.vault]
+path = "contracts/vault.clar"
+depends_on = ["token"]
+
+[contracts.market]
+path = "contracts/market.clar"
+depends_on = ["vault", "oracle"]
+"#);
+
+        assert_eq!(config.dependency_path("market", "token").unwrap(), Some(vec!["market".into(), "vault".into(), "token".into()]));
+        assert_eq!(config.dependency_path("market", "oracle").unwrap(), Some(vec!["market".into(), "oracle".into()]));
+        assert_eq!(config.dependency_path("token", "market").unwrap(), None);
+        assert_eq!(config.dependency_path("oracle", "token").unwrap(), None);
+        assert!(config.dependency_path("market", "unknown").is_err());
+    }
+
+    #[test]
+    fn invalid_contract_paths_are_all_reported() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let root = tempdir.path();
+        std::fs::create_dir_all(root.join("contracts")).unwrap();
+        std::fs::write(root.join("contracts/token.clar"), "").unwrap();
+        std::fs::write(root.join("contracts/notes.txt"), "").unwrap();
+
+        let config = config_from_str(r#"
+[project]
+name = "test"
+
+[contracts.token]
+path = "contracts/token.clar"
+depends_on = []
+
+[contracts.notes]
+path = "contracts/notes.txt"
+depends_on = []
+
+[contracts.market]
+path = "contracts/market.clar"
+depends_on = []
+"#);
+
+        let err = config.validate_paths(root).unwrap_err();
+        assert!(err.contains("contract notes"));
+        assert!(err.contains("contract market"));
+        assert!(!err.contains("contract token"));
+    }
+}
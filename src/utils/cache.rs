@@ -0,0 +1,105 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+use super::DEFAULT_CACHE_DIR;
+
+/// Remove the cache directory, returning the files and directories removed
+/// (or that would be, with `dry_run`). Only the `.clarinet` directory of the
+/// project, one of its subdirectories, or a directory outside of the project
+/// can be removed, and never when it holds contracts (`.clar` files, other
+/// than the linked contracts cached in `.clarinet/links/`), settings files,
+/// a Clarinet.toml, or any of the `protected` paths (the contracts of the
+/// project).
+pub fn clean_cache_dir(
+    project_path: &Path,
+    cache_dir: &Path,
+    protected: &[PathBuf],
+    dry_run: bool,
+) -> Result<Vec<PathBuf>, String> {
+    if !cache_dir.exists() {
+        return Ok(vec![]);
+    }
+    if !cache_dir.is_dir() {
+        return Err(format!("Error: {} is not a directory", cache_dir.display()));
+    }
+    let cache_dir = cache_dir
+        .canonicalize()
+        .map_err(|e| format!("Error: unable to resolve {}: {}", cache_dir.display(), e))?;
+    let project_path = project_path
+        .canonicalize()
+        .map_err(|e| format!("Error: unable to resolve {}: {}", project_path.display(), e))?;
+    let default_cache_dir = project_path.join(DEFAULT_CACHE_DIR);
+    let refuse = |reason: &str| Err(format!("Error: refusing to remove {}, {}", cache_dir.display(), reason));
+
+    if project_path.starts_with(&cache_dir) {
+        return refuse("it contains the project");
+    }
+    if cache_dir.starts_with(&project_path) && !cache_dir.starts_with(&default_cache_dir) {
+        return refuse(&format!("only {} or a directory outside of the project can be removed", DEFAULT_CACHE_DIR));
+    }
+    for path in protected.iter() {
+        if path.canonicalize().map_or(false, |path| path.starts_with(&cache_dir)) {
+            return refuse(&format!("it contains {}", path.display()));
+        }
+    }
+
+    let linked_contracts_dir = default_cache_dir.join("links");
+    let mut entries = vec![];
+    for entry in WalkDir::new(&cache_dir).contents_first(true) {
+        let entry = entry.map_err(|e| format!("Error: unable to list {}: {}", cache_dir.display(), e))?;
+        let path = entry.into_path();
+        let extension = path.extension().map(|extension| extension.to_string_lossy().to_string());
+        let in_settings = path
+            .parent()
+            .and_then(|parent| parent.file_name())
+            .map_or(false, |name| name == "settings");
+        let is_project_file = match extension.as_deref() {
+            Some("clar") => !path.starts_with(&linked_contracts_dir),
+            Some("toml") => in_settings || path.file_name().map_or(false, |name| name == "Clarinet.toml"),
+            _ => false,
+        };
+        if is_project_file && path.is_file() {
+            return refuse(&format!("it contains {}", path.display()));
+        }
+        entries.push(path);
+    }
+    if !dry_run {
+        fs::remove_dir_all(&cache_dir)
+            .map_err(|e| format!("Error: unable to remove {}: {}", cache_dir.display(), e))?;
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_cache_directory_is_removed() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let root = tempdir.path();
+        fs::create_dir_all(root.join("contracts")).unwrap();
+        fs::create_dir_all(root.join("settings")).unwrap();
+        fs::create_dir_all(root.join(".clarinet/links/tip")).unwrap();
+        fs::write(root.join("Clarinet.toml"), "").unwrap();
+        fs::write(root.join("contracts/token.clar"), "").unwrap();
+        fs::write(root.join("settings/Development.toml"), "").unwrap();
+        fs::write(root.join(".clarinet/links/tip/SP000.pox.clar"), "").unwrap();
+        fs::write(root.join(".clarinet/history.txt"), "").unwrap();
+        let protected = vec![root.join("contracts/token.clar")];
+
+        let contracts = clean_cache_dir(root, &root.join("contracts"), &protected, false);
+        let settings = clean_cache_dir(root, &root.join("settings"), &protected, false);
+        let project = clean_cache_dir(root, root, &protected, false);
+        let contracts_kept = root.join("contracts/token.clar").exists();
+        let cache = clean_cache_dir(root, &root.join(DEFAULT_CACHE_DIR), &protected, false);
+        let cache_removed = !root.join(DEFAULT_CACHE_DIR).exists();
+
+        assert!(contracts.is_err());
+        assert!(settings.is_err());
+        assert!(project.is_err());
+        assert!(contracts_kept);
+        assert_eq!(cache.unwrap().len(), 5);
+        assert!(cache_removed);
+    }
+}
@@ -0,0 +1,109 @@
+use clarity_repl::clarity::ast;
+use clarity_repl::clarity::representations::{SymbolicExpression, SymbolicExpressionType};
+use clarity_repl::clarity::types::{QualifiedContractIdentifier, TupleData, Value};
+use clarity_repl::clarity::util::hash::to_hex;
+
+/// Consensus-serialize a Clarity value literal (`u1`, `(some "foo")`,
+/// `(tuple (a u1))`, `{a: u1}`, ...) into its hex form.
+pub fn encode_value(literal: &str) -> Result<String, String> {
+    let contract_id = QualifiedContractIdentifier::transient();
+    let contract_ast = ast::build_ast(&contract_id, literal, &mut ())
+        .map_err(|e| format!("Error: unable to parse {}: {}", literal, e.diagnostic.message))?;
+    let value = match contract_ast.expressions.as_slice() {
+        [expr] => value_from_literal(expr)?,
+        _ => return Err(format!("Error: expected a single value, got {}", literal)),
+    };
+    let mut bytes = vec![];
+    value
+        .serialize_write(&mut bytes)
+        .map_err(|e| format!("Error: unable to serialize {}: {}", value, e))?;
+    Ok(format!("0x{}", to_hex(&bytes)))
+}
+
+/// Print the value represented by a consensus-serialized hex string.
+pub fn decode_value(hex: &str) -> Result<String, String> {
+    Value::try_deserialize_hex_untyped(hex.trim())
+        .map(|value| value.to_string())
+        .map_err(|e| format!("Error: unable to decode {}: {}", hex, e))
+}
+
+fn value_from_literal(expr: &SymbolicExpression) -> Result<Value, String> {
+    let invalid = || format!("Error: {} is not a value literal", expr);
+    match expr.expr {
+        SymbolicExpressionType::AtomValue(ref value) | SymbolicExpressionType::LiteralValue(ref value) => {
+            Ok(value.clone())
+        }
+        SymbolicExpressionType::Atom(ref name) => match name.as_str() {
+            "true" => Ok(Value::Bool(true)),
+            "false" => Ok(Value::Bool(false)),
+            "none" => Ok(Value::none()),
+            _ => Err(invalid()),
+        },
+        SymbolicExpressionType::List(ref list) => {
+            let (function, args) = match list.split_first() {
+                Some((function, args)) => (function.match_atom().ok_or_else(invalid)?, args),
+                None => return Err(invalid()),
+            };
+            let single_arg = || match args {
+                [arg] => value_from_literal(arg),
+                _ => Err(format!("Error: {} expects a single argument", function)),
+            };
+            let value = match function.as_str() {
+                "some" => Value::some(single_arg()?),
+                "ok" => Value::okay(single_arg()?),
+                "err" => Value::error(single_arg()?),
+                "list" => Value::list_from(args.iter().map(value_from_literal).collect::<Result<_, _>>()?),
+                "tuple" => {
+                    let mut fields = vec![];
+                    for pair in args.iter() {
+                        let (name, value) = match pair.match_list() {
+                            Some([name, value]) => (name.match_atom().ok_or_else(invalid)?, value),
+                            _ => return Err(invalid()),
+                        };
+                        fields.push((name.clone(), value_from_literal(value)?));
+                    }
+                    TupleData::from_data(fields).map(Value::Tuple)
+                }
+                _ => return Err(invalid()),
+            };
+            value.map_err(|e| format!("Error: invalid value {}: {}", expr, e))
+        }
+        _ => Err(invalid()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_survive_an_encode_decode_round_trip() {
+        let literals = [
+            ("u1", "u1"),
+            ("-5", "-5"),
+            ("true", "true"),
+            ("none", "none"),
+            ("(some \"foo\")", "(some \"foo\")"),
+            ("(ok u1)", "(ok u1)"),
+            ("(err 3)", "(err 3)"),
+            ("(list u1 u2)", "[u1, u2]"),
+            ("(tuple (a u1) (b 0x0102))", "{a: u1, b: 0x0102}"),
+        ];
+        for (literal, printed) in literals.iter() {
+            let hex = encode_value(literal).unwrap();
+            assert_eq!(decode_value(&hex).unwrap(), *printed);
+        }
+        assert_eq!(encode_value("u1").unwrap(), "0x0100000000000000000000000000000001");
+    }
+
+    #[test]
+    fn malformed_input_is_rejected() {
+        assert!(encode_value("u1 u2").is_err());
+        assert!(encode_value("(some)").is_err());
+        assert!(encode_value("(foo u1)").is_err());
+        assert!(encode_value("(tuple (a))").is_err());
+        assert!(decode_value("").is_err());
+        assert!(decode_value("0xzz").is_err());
+        assert!(decode_value("0x01").is_err());
+    }
+}
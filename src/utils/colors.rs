@@ -0,0 +1,47 @@
+use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
+use ansi_term::{Colour, Style};
+use atty::Stream;
+
+static COLOR_DISABLED: AtomicBool = AtomicBool::new(false);
+
+/// Turn off ANSI styling for the rest of the process (`--no-color`).
+pub fn disable_color() {
+    COLOR_DISABLED.store(true, Ordering::Relaxed);
+}
+
+/// Colors are used when stdout is a terminal, unless `--no-color` was passed
+/// or the `NO_COLOR` environment variable is set.
+pub fn use_color() -> bool {
+    !COLOR_DISABLED.load(Ordering::Relaxed)
+        && env::var_os("NO_COLOR").is_none()
+        && atty::is(Stream::Stdout)
+}
+
+fn paint<S: AsRef<str>>(style: Style, s: S) -> String {
+    if use_color() {
+        style.paint(s.as_ref()).to_string()
+    } else {
+        s.as_ref().to_string()
+    }
+}
+
+pub fn green<S: AsRef<str>>(s: S) -> String {
+    paint(Colour::Green.bold(), s)
+}
+
+pub fn red<S: AsRef<str>>(s: S) -> String {
+    paint(Colour::Red.bold(), s)
+}
+
+pub fn yellow<S: AsRef<str>>(s: S) -> String {
+    paint(Colour::Yellow.bold(), s)
+}
+
+pub fn gray<S: AsRef<str>>(s: S) -> String {
+    paint(Colour::Fixed(8).normal(), s)
+}
+
+pub fn bold<S: AsRef<str>>(s: S) -> String {
+    paint(Style::new().bold(), s)
+}
@@ -0,0 +1,24 @@
+use clarity_repl::clarity::util::hash::to_hex;
+use sha2::{Digest, Sha256};
+
+/// Hash identifying a contract source in `Clarinet.lock` and deployment bundles.
+pub fn source_hash(code: &[u8]) -> String {
+    to_hex(&Sha256::digest(code))
+}
+
+/// Source with `\n` line endings, without trailing whitespace on its lines,
+/// nor blank lines at its end.
+pub fn normalize_source(code: &str) -> String {
+    let lines: Vec<&str> = code.lines().map(|line| line.trim_end()).collect();
+    let mut normalized = lines.join("\n");
+    let trimmed_len = normalized.trim_end().len();
+    normalized.truncate(trimmed_len);
+    normalized.push('\n');
+    normalized
+}
+
+/// Hash of the normalized source of a contract, stable across editors and
+/// platforms, as printed by `clarinet contract hash`.
+pub fn normalized_source_hash(code: &str) -> String {
+    source_hash(normalize_source(code).as_bytes())
+}
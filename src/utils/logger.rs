@@ -0,0 +1,43 @@
+use std::io::{self, Write};
+use std::str::FromStr;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use super::colors;
+
+/// Writes clarinet's own records to stderr, keeping stdout for the results
+/// of the commands. Records emitted by dependencies are dropped.
+struct Logger;
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level() && metadata.target().starts_with("clarinet")
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let _ = match record.level() {
+            Level::Info => writeln!(io::stderr(), "{}", record.args()),
+            Level::Error => writeln!(io::stderr(), "{} {}", colors::red("error:"), record.args()),
+            Level::Warn => writeln!(io::stderr(), "{} {}", colors::yellow("warn:"), record.args()),
+            Level::Debug | Level::Trace => {
+                writeln!(io::stderr(), "{}", colors::gray(format!("debug: {}", record.args())))
+            }
+        };
+    }
+
+    fn flush(&self) {
+        let _ = io::stderr().flush();
+    }
+}
+
+static LOGGER: Logger = Logger;
+
+/// Install the logger, filtering out the records below `level`
+/// (one of error, warn, info, debug).
+pub fn init(level: &str) -> Result<(), String> {
+    let filter = LevelFilter::from_str(level).map_err(|_| format!("Error: unknown log level {}", level))?;
+    log::set_logger(&LOGGER).map_err(|e| format!("Error: unable to initialize logger: {}", e))?;
+    log::set_max_level(filter);
+    Ok(())
+}
@@ -4,6 +4,10 @@ use hmac::Hmac;
 use pbkdf2::pbkdf2;
 use ripemd160::Ripemd160;
 use sha2::{Digest, Sha256};
+use clarity_repl::clarity::util::StacksAddress;
+use clarity_repl::clarity::util::secp256k1::Secp256k1PublicKey;
+use secp256k1::{PublicKey, SecretKey};
+use tiny_hderive::bip32::ExtendedPrivKey;
 
 pub fn get_bip39_seed_from_mnemonic(mnemonic: &str, password: &str) -> Result<Vec<u8>, String> {
     const PBKDF2_ROUNDS: u32 = 2048;
@@ -19,6 +23,22 @@ pub fn get_bip39_seed_from_mnemonic(mnemonic: &str, password: &str) -> Result<Ve
     Ok(seed)
 }
 
+/// Derive the key found at `derivation` (BIP32 path) from `mnemonic` (BIP39,
+/// no passphrase) and return its c32 encoded Stacks address.
+pub fn get_stx_address_from_mnemonic(mnemonic: &str, derivation: &str, version: u8) -> Result<String, String> {
+    let bip39_seed = get_bip39_seed_from_mnemonic(mnemonic, "")?;
+    let ext = ExtendedPrivKey::derive(&bip39_seed[..], derivation)
+        .map_err(|e| format!("invalid derivation path {}: {:?}", derivation, e))?;
+    let secret_key = SecretKey::parse_slice(&ext.secret())
+        .map_err(|e| format!("unable to derive secret key: {:?}", e))?;
+    let public_key = PublicKey::from_secret_key(&secret_key);
+    let pub_key = Secp256k1PublicKey::from_slice(&public_key.serialize_compressed())
+        .map_err(|e| format!("unable to derive public key: {}", e))?;
+    let address = StacksAddress::from_public_key(version, pub_key)
+        .ok_or(format!("unable to derive address for {}", derivation))?;
+    Ok(address.to_string())
+}
+
 #[allow(dead_code)]
 pub fn get_address_from_public_key(public_key: &str) -> Result<String, String> {
     let pub_key_hex = hex::decode(&public_key).unwrap();
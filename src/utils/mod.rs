@@ -1 +1,29 @@
-pub mod mnemonic;
\ No newline at end of file
+use std::path::{Path, PathBuf};
+
+pub mod cache;
+pub mod codec;
+pub mod colors;
+pub mod hash;
+pub mod logger;
+pub mod mnemonic;
+pub mod output;
+pub mod version;
+pub mod watcher;
+
+/// Directory, relative to the project root, where clarinet keeps the files it
+/// generates for itself (console history, caches, ...).
+pub const DEFAULT_CACHE_DIR: &str = ".clarinet";
+
+/// Walk up from `start` to the nearest directory holding a Clarinet.toml,
+/// the way cargo looks for a Cargo.toml.
+pub fn find_project_root(start: &Path) -> Result<PathBuf, String> {
+    for dir in start.ancestors() {
+        if dir.join("Clarinet.toml").is_file() {
+            return Ok(dir.to_path_buf());
+        }
+    }
+    Err(format!(
+        "Error: no Clarinet.toml found in {} or any of its parent directories",
+        start.display()
+    ))
+}
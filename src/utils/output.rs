@@ -0,0 +1,55 @@
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+lazy_static! {
+    static ref OUTPUT_FILE: Mutex<Option<File>> = Mutex::new(None);
+}
+
+/// Print the results of a command on stdout, and copy them to the file set
+/// with `tee_to`, if any.
+#[macro_export]
+macro_rules! outputln {
+    () => {
+        $crate::utils::output::write_line("")
+    };
+    ($($arg:tt)*) => {
+        $crate::utils::output::write_line(&format!($($arg)*))
+    };
+}
+
+/// Copy every line printed with `outputln!` to `path` (`--output-file`),
+/// replacing its previous content. ANSI styling is left out of the file.
+pub fn tee_to(path: &Path) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| format!("Error: unable to create {}: {}", path.display(), e))?;
+    *OUTPUT_FILE.lock().unwrap() = Some(file);
+    Ok(())
+}
+
+pub fn write_line(line: &str) {
+    println!("{}", line);
+    if let Some(ref mut file) = *OUTPUT_FILE.lock().unwrap() {
+        if let Err(e) = writeln!(file, "{}", strip_ansi(line)).and_then(|_| file.flush()) {
+            let _ = writeln!(io::stderr(), "Error: unable to write the output file: {}", e);
+        }
+    }
+}
+
+/// `s` without its ANSI escape sequences (`ESC [ ... <letter>`).
+fn strip_ansi(s: &str) -> String {
+    let mut stripped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\u{1b}' {
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            stripped.push(c);
+        }
+    }
+    stripped
+}
@@ -0,0 +1,44 @@
+use std::time::Duration;
+
+/// Version of this build, from Cargo.toml.
+pub const CURRENT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+const LATEST_RELEASE_URL: &str = "https://api.github.com/repos/lgalabru/clarinet/releases/latest";
+
+/// Version of the latest clarinet release, as tagged on GitHub.
+pub fn fetch_latest_version(timeout: Duration) -> Result<String, String> {
+    #[derive(Deserialize, Debug)]
+    struct Release {
+        tag_name: String,
+    }
+
+    let client = reqwest::blocking::Client::builder()
+        .timeout(timeout)
+        .user_agent(format!("clarinet/{}", CURRENT_VERSION))
+        .build()
+        .map_err(|e| format!("Error: unable to build http client: {}", e))?;
+    let release: Release = client
+        .get(LATEST_RELEASE_URL)
+        .send()
+        .and_then(|response| response.error_for_status())
+        .and_then(|response| response.json())
+        .map_err(|e| format!("Error: unable to retrieve the latest release: {}", e))?;
+    Ok(release.tag_name.trim_start_matches('v').to_string())
+}
+
+/// Whether `latest` is a more recent version than `current`, comparing their
+/// numeric components (`0.10.0` is newer than `0.9.1`). Pre-release suffixes
+/// are ignored.
+pub fn is_newer(latest: &str, current: &str) -> bool {
+    let components = |version: &str| -> Vec<u64> {
+        version
+            .trim_start_matches('v')
+            .split(|c| c == '-' || c == '+')
+            .next()
+            .unwrap_or("")
+            .split('.')
+            .map(|component| component.parse().unwrap_or(0))
+            .collect()
+    };
+    components(latest) > components(current)
+}
@@ -0,0 +1,51 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+use walkdir::WalkDir;
+
+/// Tracks the modification time of `Clarinet.toml` and of the files in the
+/// `contracts` and `settings` directories.
+pub struct ProjectWatcher {
+    snapshot: BTreeMap<PathBuf, SystemTime>,
+}
+
+impl ProjectWatcher {
+    pub fn new() -> ProjectWatcher {
+        ProjectWatcher {
+            snapshot: ProjectWatcher::scan(),
+        }
+    }
+
+    fn scan() -> BTreeMap<PathBuf, SystemTime> {
+        let mut snapshot = BTreeMap::new();
+        // The manifest is not necessarily named Clarinet.toml: every toml
+        // file of the project root is watched.
+        let manifests = WalkDir::new(".")
+            .max_depth(1)
+            .into_iter()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().extension().map_or(false, |extension| extension == "toml"));
+        let files = WalkDir::new("contracts")
+            .into_iter()
+            .chain(WalkDir::new("settings").into_iter())
+            .filter_map(|entry| entry.ok())
+            .chain(manifests)
+            .filter(|entry| entry.file_type().is_file())
+            .map(|entry| entry.into_path());
+        for path in files {
+            if let Ok(modified) = fs::metadata(&path).and_then(|metadata| metadata.modified()) {
+                snapshot.insert(path, modified);
+            }
+        }
+        snapshot
+    }
+
+    /// Whether a file was added, removed or modified since the last call.
+    pub fn has_changed(&mut self) -> bool {
+        let snapshot = ProjectWatcher::scan();
+        let changed = snapshot != self.snapshot;
+        self.snapshot = snapshot;
+        changed
+    }
+}